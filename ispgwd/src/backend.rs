@@ -0,0 +1,101 @@
+//! Network backends
+//!
+//! The service reads and changes the network through a `NetBackend`, so
+//! the failover logic is the same against the real system, in a
+//! simulation and in tests.
+
+use std::fmt;
+use std::net::IpAddr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::discovery::GatewayDiscovery;
+use crate::net::*;
+use crate::prelude::*;
+use crate::probe::{self, run_probes, Probe, ProbeResult};
+
+/// Network operations used by the service
+pub trait NetBackend: fmt::Debug {
+    /// Test if the link of an interface is up.  Fails if there is no
+    /// such interface.
+    fn link_up(&mut self, interface: &str) -> Result<bool, AppError>;
+
+    /// Get the current default gateway of `family`
+    fn default_gateway(&mut self, family: Family) -> Result<Option<Gateway>, AppError>;
+
+    /// Get the `family` gateway handed out to an ISP interface
+    fn isp_gateway(&mut self, interface: &str, family: Family)
+        -> Result<Option<Gateway>, AppError>;
+
+    /// Replace the default route of the family of `gateway` with one
+    /// through `gateway`
+    fn replace_default_gateway(&mut self, gateway: &Gateway) -> Result<(), AppError>;
+
+    /// Run probes, concurrently where possible.  Returns a result for
+    /// each probe.
+    fn probe(&mut self, probes: &[Probe]) -> Vec<Result<ProbeResult, AppError>>;
+
+    /// Look up the `family` address of a host name probed.  May block
+    /// for as long as the resolver takes.
+    fn resolve(&mut self, host: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        probe::resolve(host, family)
+    }
+
+    /// Called once per service step
+    fn tick(&mut self) {}
+
+    /// Wait up to `timeout` for the next service step.  Backends that
+    /// watch the network return early when it changes.
+    fn wait(&mut self, timeout: Duration) {
+        thread::sleep(timeout);
+    }
+
+    /// Current time
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// The service should stop
+    fn finished(&self) -> bool {
+        false
+    }
+}
+
+/// Backend changing the routing of the host it runs on.  Needs root.
+#[derive(Debug, Default)]
+pub struct SystemBackend {
+    gateways: GatewayDiscovery,
+}
+
+impl SystemBackend {
+    /// Create a backend looking up ISP gateways with `gateways`
+    pub fn new(gateways: GatewayDiscovery) -> SystemBackend {
+        SystemBackend { gateways }
+    }
+}
+
+impl NetBackend for SystemBackend {
+    fn link_up(&mut self, interface: &str) -> Result<bool, AppError> {
+        Ok(get_interface(interface)?.is_up())
+    }
+
+    fn default_gateway(&mut self, family: Family) -> Result<Option<Gateway>, AppError> {
+        get_default_gw(family)
+    }
+
+    fn isp_gateway(
+        &mut self,
+        interface: &str,
+        family: Family,
+    ) -> Result<Option<Gateway>, AppError> {
+        Ok(self.gateways.gateway(interface, family))
+    }
+
+    fn replace_default_gateway(&mut self, gateway: &Gateway) -> Result<(), AppError> {
+        replace_default_gw(gateway)
+    }
+
+    fn probe(&mut self, probes: &[Probe]) -> Vec<Result<ProbeResult, AppError>> {
+        run_probes(probes)
+    }
+}
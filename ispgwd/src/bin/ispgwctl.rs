@@ -0,0 +1,79 @@
+use std::path::PathBuf;
+
+use anyhow::Context;
+use structopt::StructOpt;
+
+use ispgw::Command;
+
+fn main() -> Result<(), anyhow::Error> {
+    let args = CliArgs::from_args();
+
+    let command = match args.command {
+        CtlCommand::Status => Command::Status,
+        CtlCommand::Failover { isp } => Command::Failover(isp),
+        CtlCommand::Pin { isp } => Command::Pin(isp),
+        CtlCommand::Unpin => Command::Unpin,
+        CtlCommand::Drain { isp } => Command::Drain(isp),
+        CtlCommand::Undrain { isp } => Command::Undrain(isp),
+        CtlCommand::Discover => Command::Discover,
+    };
+
+    let response = command.send(&args.socket).context(format!(
+        "Error running '{}' on {}",
+        command,
+        args.socket.display()
+    ))?;
+    print!("{}", response);
+
+    Ok(())
+}
+
+#[derive(Debug, structopt::StructOpt)]
+#[structopt(name = "ispgwctl", author)]
+/// Control the ISP gateway daemon
+///
+/// Commands take effect at the next poll of the daemon.
+struct CliArgs {
+    /// Control socket of the daemon
+    #[structopt(short, long, default_value = "/run/ispgwd.sock")]
+    socket: PathBuf,
+
+    #[structopt(subcommand)]
+    command: CtlCommand,
+}
+
+#[derive(Debug, structopt::StructOpt)]
+enum CtlCommand {
+    /// Show the default routes, ISP health and the last transition
+    Status,
+
+    /// Fail over to an ISP now, failing back after the hold time
+    Failover {
+        /// ISP name
+        isp: String,
+    },
+
+    /// Keep the default routes on an ISP as long as it is up
+    Pin {
+        /// ISP name
+        isp: String,
+    },
+
+    /// Go back to choosing ISPs by priority
+    Unpin,
+
+    /// Move off an ISP and stop using it, e.g. for maintenance
+    Drain {
+        /// ISP name
+        isp: String,
+    },
+
+    /// Use a drained ISP again
+    Undrain {
+        /// ISP name
+        isp: String,
+    },
+
+    /// Re-run discovery of the default routes and ISP gateways
+    Discover,
+}
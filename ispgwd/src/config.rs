@@ -1,5 +1,9 @@
 //! ISP gateway daemon Configuration
 
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::PathBuf;
+
 use serde_derive::Deserialize;
 
 #[derive(Deserialize, Debug, Clone, Default)]
@@ -13,8 +17,203 @@ pub struct IspgwdConfig {
     /// Simulate operations
     pub simulation: bool,
 
+    #[serde(default = "default_netlink")]
+    /// Manage routes over netlink, otherwise through `/proc` and the
+    /// `ip` command.  On by default, which needs `CAP_NET_ADMIN` like
+    /// the `ip` command does.
+    pub netlink: bool,
+
+    #[serde(default)]
+    /// Scenario file played when simulating
+    pub scenario_file: Option<PathBuf>,
+
     /// Standard Polling duration
     pub poll_duration_ms: u64,
+
+    #[serde(default = "default_failback_hold_ms")]
+    /// Time an ISP that lost internet access is skipped before failing
+    /// back to it, from when it went bad.  Unlike `health.min_dwell_ms`
+    /// it holds off the ISP that failed, whichever ISP is active.
+    pub failback_hold_ms: u64,
+
+    #[serde(default)]
+    /// Probes run through every ISP.  ICMP echo to a few public DNS
+    /// servers when neither these nor the ISP probes are set.
+    pub probes: Vec<ProbeConfig>,
+
+    #[serde(default)]
+    /// When ISPs are considered up or down
+    pub health: HealthConfig,
+
+    #[serde(default = "default_gateway_sources")]
+    /// Where ISP gateways are looked up, in order.  The first source
+    /// that knows the gateway of an interface wins.
+    pub gateway_sources: Vec<GatewaySourceKind>,
+
+    #[serde(default)]
+    /// Manage the IPv6 default route too
+    pub ipv6: bool,
+
+    #[serde(default)]
+    /// Whether IPv4 and IPv6 fail over together or independently
+    pub failover: FailoverMode,
+
+    #[serde(default)]
+    /// Address Prometheus metrics are served on, e.g. `127.0.0.1:9464`.
+    /// No metrics when unset.
+    pub metrics_listen: Option<SocketAddr>,
+
+    #[serde(default)]
+    /// Unix-domain socket `ispgwctl` sends commands to.  No control
+    /// when unset.
+    pub control_socket: Option<PathBuf>,
+}
+
+fn default_netlink() -> bool {
+    true
+}
+
+fn default_failback_hold_ms() -> u64 {
+    60_000
+}
+
+fn default_gateway_sources() -> Vec<GatewaySourceKind> {
+    GatewaySourceKind::DEFAULT.to_vec()
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+/// IP address family
+pub enum Family {
+    /// IPv4
+    Ipv4,
+
+    /// IPv6
+    Ipv6,
+}
+
+impl Family {
+    /// Family of `addr`
+    pub fn of(addr: &IpAddr) -> Family {
+        match addr {
+            IpAddr::V4(_) => Family::Ipv4,
+            IpAddr::V6(_) => Family::Ipv6,
+        }
+    }
+}
+
+impl fmt::Display for Family {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Family::Ipv4 => write!(f, "IPv4"),
+            Family::Ipv6 => write!(f, "IPv6"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+/// How the IPv4 and IPv6 default routes fail over
+pub enum FailoverMode {
+    /// Both move to the same ISP, one that is up in every family it
+    /// has a gateway for
+    #[default]
+    Together,
+
+    /// Each moves to the best ISP for its family
+    Independent,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+/// A place ISP gateways are looked up
+pub enum GatewaySourceKind {
+    /// `gateway` of the ISP config
+    Static,
+
+    /// ISC dhclient lease files
+    Dhclient,
+
+    /// dhcpcd lease files
+    Dhcpcd,
+
+    /// systemd-networkd lease state
+    Networkd,
+
+    /// NetworkManager, through `nmcli`
+    NetworkManager,
+
+    /// Default routes out of the interface, in any routing table
+    Routes,
+}
+
+impl GatewaySourceKind {
+    /// Sources looked in when the configuration names none, in order
+    pub const DEFAULT: [GatewaySourceKind; 6] = [
+        GatewaySourceKind::Static,
+        GatewaySourceKind::Dhclient,
+        GatewaySourceKind::Dhcpcd,
+        GatewaySourceKind::Networkd,
+        GatewaySourceKind::NetworkManager,
+        GatewaySourceKind::Routes,
+    ];
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+/// ISP health Configuration
+pub struct HealthConfig {
+    /// Probe rounds averaged over
+    pub window: usize,
+
+    /// Loss at which an up ISP goes down, 0-1.  A round where the
+    /// gateway or most of the probe weight does not answer counts as
+    /// all lost.
+    pub down_loss: f64,
+
+    /// Loss at which a down ISP comes back up, 0-1
+    pub up_loss: f64,
+
+    /// Round trip time at which an up ISP goes down
+    pub down_rtt_ms: Option<u64>,
+
+    /// Round trip time at which a down ISP comes back up
+    pub up_rtt_ms: Option<u64>,
+
+    /// Time an ISP stays active before failing back to a more preferred
+    /// one, from when it became active.  Unlike `failback_hold_ms` it
+    /// applies whatever caused the failover, such as damping or an
+    /// operator.  After an outage both apply and the later one wins.
+    pub min_dwell_ms: u64,
+
+    /// Penalty added every time an ISP goes down
+    pub flap_penalty: f64,
+
+    /// Penalty above which an ISP is damped and not failed back to
+    pub suppress_penalty: f64,
+
+    /// Penalty below which a damped ISP is used again
+    pub reuse_penalty: f64,
+
+    /// Time for the penalty to decay by half
+    pub half_life_ms: u64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            window: 5,
+            down_loss: 0.5,
+            up_loss: 0.1,
+            down_rtt_ms: None,
+            up_rtt_ms: None,
+            min_dwell_ms: 30_000,
+            flap_penalty: 1000.0,
+            suppress_penalty: 2000.0,
+            reuse_penalty: 750.0,
+            half_life_ms: 300_000,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -24,9 +223,184 @@ pub struct IspConfig {
     /// ISP name
     pub name: String,
 
-    /// ISP interface
+    /// ISP interface, not shared with another ISP
     pub interface: String,
 
     /// ISP priority
     pub priority: u32,
+
+    #[serde(default)]
+    /// Probes run through this ISP only
+    pub probes: Vec<ProbeConfig>,
+
+    #[serde(default)]
+    /// Static gateway of the ISP, for interfaces not configured by DHCP
+    pub gateway: Option<Ipv4Addr>,
+
+    #[serde(default)]
+    /// Static IPv6 gateway of the ISP
+    pub gateway6: Option<Ipv6Addr>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(try_from = "ProbeFields")]
+/// Probe Configuration
+pub struct ProbeConfig {
+    /// What to probe
+    pub kind: ProbeKind,
+
+    /// Time to wait for an answer
+    pub timeout_ms: u64,
+
+    /// Weight of the probe in the ISP health, an ISP is healthy when
+    /// more than half of the total weight answers
+    pub weight: u32,
+
+    /// Family probed, for TCP and HTTP probes of host names.  IPv4 if
+    /// unset.  Probes of addresses are in the family of the address.
+    pub family: Option<Family>,
+}
+
+fn default_probe_timeout_ms() -> u64 {
+    1000
+}
+
+fn default_probe_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+// Type of a probe in the configuration
+enum ProbeType {
+    Icmp,
+    Tcp,
+    Http,
+    Dns,
+}
+
+// Fields of a probe of any type.  serde can not deny unknown fields
+// next to a flattened enum, so probes are read into this and checked
+// against their type.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ProbeFields {
+    #[serde(rename = "type")]
+    ty: ProbeType,
+
+    #[serde(default)]
+    target: Option<String>,
+
+    #[serde(default)]
+    url: Option<String>,
+
+    #[serde(default)]
+    status: Option<u16>,
+
+    #[serde(default)]
+    server: Option<IpAddr>,
+
+    #[serde(default)]
+    name: Option<String>,
+
+    #[serde(default = "default_probe_timeout_ms")]
+    timeout_ms: u64,
+
+    #[serde(default = "default_probe_weight")]
+    weight: u32,
+
+    #[serde(default)]
+    family: Option<Family>,
+}
+
+impl TryFrom<ProbeFields> for ProbeConfig {
+    type Error = String;
+
+    fn try_from(fields: ProbeFields) -> Result<ProbeConfig, String> {
+        let ProbeFields {
+            ty,
+            target,
+            url,
+            status,
+            server,
+            name,
+            ..
+        } = fields;
+        let kind = match (ty, target, url, status, server, name) {
+            (ProbeType::Icmp, Some(target), None, None, None, None) => ProbeKind::Icmp {
+                target: target
+                    .parse()
+                    .map_err(|_| format!("icmp probe target {} is not an address", target))?,
+            },
+            (ProbeType::Tcp, Some(target), None, None, None, None) => ProbeKind::Tcp { target },
+            (ProbeType::Http, None, Some(url), status, None, None) => ProbeKind::Http {
+                url,
+                status: status.unwrap_or(200),
+            },
+            (ProbeType::Dns, None, None, None, Some(server), Some(name)) => {
+                ProbeKind::Dns { server, name }
+            }
+            (ty, ..) => {
+                let expected = match ty {
+                    ProbeType::Icmp => "an icmp probe takes a target address",
+                    ProbeType::Tcp => "a tcp probe takes a target host:port",
+                    ProbeType::Http => "an http probe takes a url and optionally a status",
+                    ProbeType::Dns => "a dns probe takes a server and a name",
+                };
+                return Err(format!("Unexpected probe fields, {}", expected));
+            }
+        };
+
+        Ok(ProbeConfig {
+            kind,
+            timeout_ms: fields.timeout_ms,
+            weight: fields.weight,
+            family: fields.family,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Type and target of a probe
+pub enum ProbeKind {
+    /// ICMP echo
+    Icmp {
+        /// Address to ping
+        target: IpAddr,
+    },
+
+    /// TCP connection
+    Tcp {
+        /// `host:port` to connect to
+        target: String,
+    },
+
+    /// HTTP GET request
+    Http {
+        /// `http://` URL to get
+        url: String,
+
+        /// Expected response status
+        status: u16,
+    },
+
+    /// DNS query for an A record
+    Dns {
+        /// DNS server to ask
+        server: IpAddr,
+
+        /// Name to look up
+        name: String,
+    },
+}
+
+impl fmt::Display for ProbeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProbeKind::Icmp { target } => write!(f, "icmp {}", target),
+            ProbeKind::Tcp { target } => write!(f, "tcp {}", target),
+            ProbeKind::Http { url, .. } => write!(f, "http {}", url),
+            ProbeKind::Dns { server, name } => write!(f, "dns {} at {}", name, server),
+        }
+    }
 }
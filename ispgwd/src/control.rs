@@ -0,0 +1,396 @@
+//! Control socket
+//!
+//! `ispgwctl` talks to the daemon over a Unix-domain socket, one command
+//! per connection.  A request is a single line such as `drain ATT`, and
+//! the response is `ok` or `error` on a line of its own followed by text
+//! for the operator.  Commands are handed to the service and run at its
+//! next step, so they never race with a failover in progress.  A command
+//! the service does not take on in time is withdrawn, and the client is
+//! told it was not run.
+
+use std::fmt;
+use std::fs;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+use crate::prelude::*;
+
+// Time a client gets to send its request
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Time to wait for the daemon, which answers at its next step
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Time the server waits for the service to run a command, short of
+// REPLY_TIMEOUT so the client hears about it
+const SERVICE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Command sent to the daemon
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Report routes, ISP health and the last transition
+    Status,
+
+    /// Fail over to the named ISP now
+    Failover(String),
+
+    /// Keep the routes on the named ISP as long as it is up
+    Pin(String),
+
+    /// Go back to choosing ISPs by priority
+    Unpin,
+
+    /// Move off the named ISP and stop using it, e.g. for maintenance
+    Drain(String),
+
+    /// Use a drained ISP again
+    Undrain(String),
+
+    /// Re-run discovery of the default routes and ISP gateways
+    Discover,
+}
+
+impl FromStr for Command {
+    type Err = AppError;
+
+    fn from_str(line: &str) -> Result<Command, AppError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words[..] {
+            ["status"] => Command::Status,
+            ["failover", isp] => Command::Failover(isp.to_owned()),
+            ["pin", isp] => Command::Pin(isp.to_owned()),
+            ["unpin"] => Command::Unpin,
+            ["drain", isp] => Command::Drain(isp.to_owned()),
+            ["undrain", isp] => Command::Undrain(isp.to_owned()),
+            ["discover"] => Command::Discover,
+            _ => {
+                return Err(AppError::Control(format!(
+                    "Unknown command: {}",
+                    line.trim()
+                )))
+            }
+        };
+        Ok(command)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Status => write!(f, "status"),
+            Command::Failover(isp) => write!(f, "failover {}", isp),
+            Command::Pin(isp) => write!(f, "pin {}", isp),
+            Command::Unpin => write!(f, "unpin"),
+            Command::Drain(isp) => write!(f, "drain {}", isp),
+            Command::Undrain(isp) => write!(f, "undrain {}", isp),
+            Command::Discover => write!(f, "discover"),
+        }
+    }
+}
+
+impl Command {
+    /// Send the command to the daemon listening on `path`, and return
+    /// its response
+    pub fn send(&self, path: &Path) -> Result<String, AppError> {
+        let mut stream = UnixStream::connect(path)?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        writeln!(stream, "{}", self)?;
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response)?;
+        match response.split_once('\n') {
+            Some(("ok", text)) => Ok(text.to_owned()),
+            Some(("error", text)) => Err(AppError::Control(text.trim_end().to_owned())),
+            None if response.is_empty() => Err(AppError::Control(
+                "The daemon closed the connection".to_owned(),
+            )),
+            _ => Err(AppError::Control(format!(
+                "Unexpected response: {:?}",
+                response
+            ))),
+        }
+    }
+}
+
+/// Command waiting for the service to run it
+#[derive(Debug)]
+pub struct Request {
+    /// The command
+    pub command: Command,
+
+    reply: mpsc::Sender<Result<String, String>>,
+
+    // Set by the first of the service taking the command on and the
+    // server giving up waiting for it
+    taken: Arc<AtomicBool>,
+}
+
+impl Request {
+    /// Take the command on.  False when the client was told it timed
+    /// out, the command must not run then.
+    pub fn take(&self) -> bool {
+        !self.taken.swap(true, Ordering::SeqCst)
+    }
+
+    /// Answer the client
+    pub fn reply(self, reply: Result<String, String>) {
+        // the client may have given up already
+        let _ = self.reply.send(reply);
+    }
+}
+
+/// Accepts commands on a Unix-domain socket
+#[derive(Debug)]
+pub struct ControlServer {
+    path: PathBuf,
+    requests: mpsc::Receiver<Request>,
+}
+
+impl ControlServer {
+    /// Listen on `path` in a thread of its own.  A socket left behind by
+    /// a daemon that is gone is replaced, anything else at `path` is
+    /// left alone.
+    pub fn start(path: &Path) -> Result<ControlServer, AppError> {
+        if let Ok(metadata) = fs::symlink_metadata(path) {
+            if !metadata.file_type().is_socket() {
+                return Err(AppError::Control(format!(
+                    "{} exists and is not a socket",
+                    path.display()
+                )));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Err(AppError::Control(format!(
+                    "{} is in use by another daemon",
+                    path.display()
+                )));
+            }
+        }
+        let listener = bind_private(path)?;
+
+        let (sender, requests) = mpsc::channel();
+        thread::Builder::new()
+            .name("control".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result =
+                        stream.and_then(|mut stream| serve(&mut stream, &sender, SERVICE_TIMEOUT));
+                    if let Err(e) = result {
+                        debug!("Control request failed: {}", e);
+                    }
+                }
+            })?;
+
+        info!("Listening for commands on {}", path.display());
+        Ok(ControlServer {
+            path: path.to_owned(),
+            requests,
+        })
+    }
+
+    /// Commands received since the last call
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.try_iter().collect()
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// Listen on 'path', never letting anyone but the owner connect.  The
+// socket is made in a directory only the owner can enter and moved to
+// 'path' once it is private, replacing a stale socket.
+fn bind_private(path: &Path) -> Result<UnixListener, AppError> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let dir = path.with_file_name(format!(".{}.{}", name, process::id()));
+    fs::DirBuilder::new().mode(0o700).create(&dir)?;
+
+    let private = dir.join("socket");
+    let bound = UnixListener::bind(&private).and_then(|listener| {
+        fs::set_permissions(&private, fs::Permissions::from_mode(0o600))?;
+        fs::rename(&private, path)?;
+        Ok(listener)
+    });
+    let _ = fs::remove_file(&private);
+    fs::remove_dir(&dir)?;
+    Ok(bound?)
+}
+
+// Read one command, hand it to the service and send back its reply.  A
+// command the service has not taken on within 'timeout' is withdrawn.
+fn serve(
+    stream: &mut UnixStream,
+    service: &mpsc::Sender<Request>,
+    timeout: Duration,
+) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+    let mut line = String::new();
+    BufReader::new(&*stream).read_line(&mut line)?;
+
+    let reply = match line.parse::<Command>() {
+        Ok(command) => {
+            info!("Control command: {}", command);
+            let (reply, replied) = mpsc::channel();
+            let taken = Arc::new(AtomicBool::new(false));
+            let request = Request {
+                command,
+                reply,
+                taken: Arc::clone(&taken),
+            };
+            let stopping = || Err("The service is stopping".to_owned());
+            match service.send(request) {
+                Ok(()) => match replied.recv_timeout(timeout) {
+                    Ok(reply) => reply,
+                    // the service took it on just now, its answer is
+                    // on the way
+                    Err(mpsc::RecvTimeoutError::Timeout) if taken.swap(true, Ordering::SeqCst) => {
+                        replied.recv().unwrap_or_else(|_| stopping())
+                    }
+                    Err(mpsc::RecvTimeoutError::Timeout) => Err(
+                        "The service did not answer in time, the command was not run".to_owned(),
+                    ),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => stopping(),
+                },
+                Err(_) => stopping(),
+            }
+        }
+        Err(AppError::Control(e)) => Err(e),
+        Err(e) => Err(e.to_string()),
+    };
+
+    match reply {
+        Ok(text) => write!(stream, "ok\n{}", text)?,
+        Err(text) => writeln!(stream, "error\n{}", text)?,
+    }
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        for line in [
+            "status",
+            "failover ATT",
+            "pin ATT",
+            "unpin",
+            "drain ATT",
+            "undrain ATT",
+            "discover",
+        ] {
+            let command: Command = line.parse().unwrap();
+            assert_eq!(command.to_string(), line);
+        }
+        assert_eq!(
+            " pin  ATT\n".parse::<Command>().unwrap(),
+            Command::Pin("ATT".to_owned())
+        );
+        assert!("pin".parse::<Command>().is_err());
+        assert!("reboot now".parse::<Command>().is_err());
+    }
+
+    #[test]
+    fn serve() {
+        let path = std::env::temp_dir().join(format!("ispgwd-test-{}.sock", std::process::id()));
+
+        // anything but a socket is left alone
+        fs::write(&path, "keep").unwrap();
+        assert!(matches!(
+            ControlServer::start(&path),
+            Err(AppError::Control(_))
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "keep");
+        fs::remove_file(&path).unwrap();
+
+        let server = ControlServer::start(&path).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let name = path.file_name().unwrap().to_string_lossy();
+        assert!(!path
+            .with_file_name(format!(".{}.{}", name, process::id()))
+            .exists());
+        assert!(matches!(
+            ControlServer::start(&path),
+            Err(AppError::Control(_))
+        ));
+
+        let client = {
+            let path = path.clone();
+            thread::spawn(move || {
+                let status = Command::Status.send(&path);
+                let drain = Command::Drain("ATT".to_owned()).send(&path);
+                (status, drain)
+            })
+        };
+
+        let mut replies = vec![
+            Err("No ISP named ATT".to_owned()),
+            Ok("All good\n".to_owned()),
+        ];
+        while !replies.is_empty() {
+            for request in server.requests() {
+                assert!(request.take());
+                request.reply(replies.pop().unwrap());
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let (status, drain) = client.join().unwrap();
+        assert_eq!(status.unwrap(), "All good\n");
+        assert_eq!(
+            drain.unwrap_err().to_string(),
+            "Control request failed: No ISP named ATT"
+        );
+
+        drop(server);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn service_timeout() {
+        let timeout = Duration::from_millis(50);
+        let (sender, requests) = mpsc::channel();
+
+        let ask = |line: &str| {
+            let (mut client, mut server) = UnixStream::pair().unwrap();
+            writeln!(client, "{}", line).unwrap();
+            let sender = sender.clone();
+            let served = thread::spawn(move || super::serve(&mut server, &sender, timeout));
+            (client, served)
+        };
+        let answer = |mut client: UnixStream| {
+            let mut response = String::new();
+            client.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        // a command the service did not get to in time is not run
+        let (client, served) = ask("drain ATT");
+        served.join().unwrap().unwrap();
+        assert!(answer(client).starts_with("error\nThe service did not answer in time"));
+        let request: Request = requests.recv().unwrap();
+        assert!(!request.take());
+
+        // one the service took on is answered however long it takes
+        let (client, served) = ask("drain ATT");
+        let request = requests.recv().unwrap();
+        assert!(request.take());
+        thread::sleep(timeout * 2);
+        request.reply(Ok("Drained ATT\n".to_owned()));
+        served.join().unwrap().unwrap();
+        assert_eq!(answer(client), "ok\nDrained ATT\n");
+    }
+}
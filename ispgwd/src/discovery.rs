@@ -0,0 +1,792 @@
+//! ISP gateway discovery
+//!
+//! The gateway of an ISP interface is whatever its DHCP client was
+//! handed, and every DHCP client keeps its leases somewhere else.  Each
+//! `GatewaySource` knows one place to look, and `GatewayDiscovery`
+//! asks them in the configured order.
+//!
+//! IPv6 gateways come from router advertisements rather than DHCPv6, so
+//! the kernel installs them as routes and the lease file sources only
+//! know IPv4 gateways.
+//!
+//! Lease files outlive their leases, so expired leases are skipped.
+//! networkd only keeps current leases under `/run`, and NetworkManager
+//! and the routes are asked about the present.  Those two run a command,
+//! whose output is reused for a while as the service asks on every poll.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::net::Gateway;
+use crate::prelude::*;
+
+/// A place to look up the gateway of an interface
+pub trait GatewaySource: fmt::Debug + Send {
+    /// Name of the source, for logging
+    fn name(&self) -> &'static str;
+
+    /// `family` gateway of `interface`, None if the source does not
+    /// know it
+    fn gateway(&self, interface: &str, family: Family) -> Result<Option<IpAddr>, AppError>;
+}
+
+// Contents of a file, None if there is no such file
+fn read_optional(path: &Path) -> Result<Option<Vec<u8>>, AppError> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Output of a command, None if it is not installed or fails
+fn command_output(program: &str, args: &[&str]) -> Result<Option<String>, AppError> {
+    let output = match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    if !output.status.success() {
+        debug!("{} {}: {}", program, args.join(" "), output.status);
+        return Ok(None);
+    }
+    Ok(Some(String::from_utf8_lossy(&output.stdout).into_owned()))
+}
+
+// Time the output of a command is reused
+const COMMAND_TTL: Duration = Duration::from_secs(30);
+
+// When the output of a command was taken, and the output
+type TakenOutput = (Instant, Option<String>);
+
+// Output of commands by program and arguments
+#[derive(Debug, Default)]
+struct CommandCache {
+    outputs: Mutex<BTreeMap<Vec<String>, TakenOutput>>,
+}
+
+impl CommandCache {
+    // Output of a command, run again once COMMAND_TTL has passed
+    fn output(&self, program: &str, args: &[&str]) -> Result<Option<String>, AppError> {
+        let key: Vec<String> = std::iter::once(&program)
+            .chain(args)
+            .map(|arg| arg.to_string())
+            .collect();
+        let mut outputs = self.outputs.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if let Some((taken, output)) = outputs.get(&key) {
+            if now.duration_since(*taken) < COMMAND_TTL {
+                return Ok(output.clone());
+            }
+        }
+
+        let output = command_output(program, args)?;
+        outputs.insert(key, (now, output.clone()));
+        Ok(output)
+    }
+}
+
+// Seconds since the epoch
+fn unix_time(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(since) => since.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+// Days from 1970-01-01 to a date of the Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // years starting in March put leap days at the end
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Gateways set in the ISP configs
+#[derive(Debug, Clone, Default)]
+pub struct StaticGateways {
+    gateways: BTreeMap<(String, Family), IpAddr>,
+}
+
+impl StaticGateways {
+    /// Static gateways of `isps`, by interface and family
+    pub fn new(isps: &[IspConfig]) -> StaticGateways {
+        let mut gateways = BTreeMap::new();
+        for isp in isps {
+            let static_gateways = [
+                isp.gateway.map(IpAddr::from),
+                isp.gateway6.map(IpAddr::from),
+            ];
+            for gateway in static_gateways.into_iter().flatten() {
+                gateways.insert((isp.interface.clone(), Family::of(&gateway)), gateway);
+            }
+        }
+        StaticGateways { gateways }
+    }
+}
+
+impl GatewaySource for StaticGateways {
+    fn name(&self) -> &'static str {
+        "static"
+    }
+
+    fn gateway(&self, interface: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        Ok(self.gateways.get(&(interface.to_owned(), family)).copied())
+    }
+}
+
+/// ISC dhclient lease files
+#[derive(Debug, Clone)]
+pub struct Dhclient {
+    // Lease files, with "{}" standing for the interface
+    paths: Vec<String>,
+}
+
+impl Default for Dhclient {
+    /// The paths distributions and the dhclient examples use
+    fn default() -> Self {
+        Dhclient::new(&[
+            "/tmp/dhclient.{}.leases",
+            "/var/lib/dhcp/dhclient.{}.leases",
+            "/var/lib/dhcp/dhclient.leases",
+            "/var/lib/dhclient/dhclient-{}.leases",
+            "/var/lib/dhclient/dhclient.leases",
+            "/var/db/dhclient.leases.{}",
+        ])
+    }
+}
+
+impl Dhclient {
+    /// Look in `paths`, where "{}" stands for the interface
+    pub fn new(paths: &[&str]) -> Dhclient {
+        Dhclient {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        }
+    }
+}
+
+// Expiry of a dhclient lease in seconds since the epoch, from "4
+// 2026/10/22 10:00:00" in UTC or "epoch 1792663200".  None for "never"
+// or anything else.
+fn dhclient_expiry(expire: &str) -> Option<i64> {
+    let fields: Vec<&str> = expire.split_whitespace().collect();
+    match fields[..] {
+        ["epoch", secs] => secs.parse().ok(),
+        [_weekday, date, time] => {
+            let numbers = |text: &str, sep| -> Option<Vec<i64>> {
+                text.split(sep).map(|n| n.parse().ok()).collect()
+            };
+            match (&numbers(date, '/')?[..], &numbers(time, ':')?[..]) {
+                ([year, month @ 1..=12, day @ 1..=31], [hour, minute, second]) => Some(
+                    days_from_civil(*year, *month, *day) * 86_400
+                        + hour * 3600
+                        + minute * 60
+                        + second,
+                ),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+// First router of the last lease for 'interface' in a dhclient lease
+// file that has not expired at 'now', in seconds since the epoch.
+// Leases look like
+//
+// lease {
+//   interface "eth0";
+//   fixed-address 10.0.0.23;
+//   option routers 10.0.0.1;
+//   expire 4 2026/10/22 10:00:00;
+//   ...
+// }
+fn dhclient_router(leases: &str, interface: &str, now: i64) -> Option<Ipv4Addr> {
+    // comments run to the end of the line
+    let leases: Vec<&str> = leases
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or_default())
+        .collect();
+    let leases = leases.join("\n");
+
+    let mut router = None;
+    for lease in leases.split("lease {").skip(1) {
+        let mut lease_interface = None;
+        let mut lease_router = None;
+        let mut expiry = None;
+        for statement in lease.split(';').map(str::trim) {
+            let mut fields = statement.splitn(2, char::is_whitespace);
+            match (fields.next(), fields.next().map(str::trim)) {
+                (Some("interface"), Some(name)) => lease_interface = Some(name.trim_matches('"')),
+                (Some("option"), Some(option)) => {
+                    if let Some(routers) = option.strip_prefix("routers") {
+                        lease_router = routers
+                            .split(',')
+                            .next()
+                            .and_then(|r| r.trim().parse().ok());
+                    }
+                }
+                (Some("expire"), Some(expire)) => expiry = dhclient_expiry(expire),
+                _ => {}
+            }
+        }
+
+        let other_interface = lease_interface.is_some_and(|name| name != interface);
+        let expired = expiry.is_some_and(|expiry| expiry <= now);
+        if !other_interface && !expired && lease_router.is_some() {
+            router = lease_router;
+        }
+    }
+
+    router
+}
+
+impl GatewaySource for Dhclient {
+    fn name(&self) -> &'static str {
+        "dhclient"
+    }
+
+    fn gateway(&self, interface: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        if family != Family::Ipv4 {
+            return Ok(None);
+        }
+
+        for path in &self.paths {
+            let path = PathBuf::from(path.replace("{}", interface));
+            if let Some(leases) = read_optional(&path)? {
+                debug!("Reading {}", path.display());
+                let now = unix_time(SystemTime::now());
+                let router = dhclient_router(&String::from_utf8_lossy(&leases), interface, now);
+                if let Some(router) = router {
+                    return Ok(Some(router.into()));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// dhcpcd lease files
+#[derive(Debug, Clone)]
+pub struct Dhcpcd {
+    // Lease files, with "{}" standing for the interface
+    paths: Vec<String>,
+}
+
+impl Default for Dhcpcd {
+    /// The paths of current and older dhcpcd versions
+    fn default() -> Self {
+        Dhcpcd::new(&[
+            "/var/lib/dhcpcd/{}.lease",
+            "/var/lib/dhcpcd/dhcpcd-{}.lease",
+            "/var/db/dhcpcd/{}.lease",
+            "/var/db/dhcpcd-{}.lease",
+        ])
+    }
+}
+
+impl Dhcpcd {
+    /// Look in `paths`, where "{}" stands for the interface
+    pub fn new(paths: &[&str]) -> Dhcpcd {
+        Dhcpcd {
+            paths: paths.iter().map(|path| path.to_string()).collect(),
+        }
+    }
+}
+
+// dhcpcd saves the DHCP message of the lease as is
+const BOOTP_LEN: usize = 236;
+const DHCP_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const OPTION_PAD: u8 = 0;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_END: u8 = 255;
+
+// Lease time meaning forever
+const LEASE_INFINITE: u32 = u32::MAX;
+
+// Data of the first option 'code' of a DHCP message
+fn dhcp_option(message: &[u8], code: u8) -> Option<&[u8]> {
+    if message.get(BOOTP_LEN..BOOTP_LEN + 4)? != DHCP_COOKIE {
+        return None;
+    }
+
+    let mut options = &message[BOOTP_LEN + 4..];
+    loop {
+        match *options.first()? {
+            OPTION_PAD => options = &options[1..],
+            OPTION_END => return None,
+            option => {
+                let len = *options.get(1)? as usize;
+                let data = options.get(2..2 + len)?;
+                if option == code {
+                    return Some(data);
+                }
+                options = &options[2 + len..];
+            }
+        }
+    }
+}
+
+// First router option of a DHCP message
+fn dhcp_router(message: &[u8]) -> Option<Ipv4Addr> {
+    match *dhcp_option(message, OPTION_ROUTER)? {
+        [a, b, c, d, ..] => Some(Ipv4Addr::new(a, b, c, d)),
+        _ => None,
+    }
+}
+
+// Test if a DHCP lease saved at 'saved' has expired at 'now'.  Leases
+// without a lease time never do.
+fn dhcp_expired(message: &[u8], saved: SystemTime, now: SystemTime) -> bool {
+    let lease_time = match dhcp_option(message, OPTION_LEASE_TIME) {
+        Some(&[a, b, c, d]) => u32::from_be_bytes([a, b, c, d]),
+        _ => return false,
+    };
+    lease_time != LEASE_INFINITE && saved + Duration::from_secs(lease_time.into()) <= now
+}
+
+impl GatewaySource for Dhcpcd {
+    fn name(&self) -> &'static str {
+        "dhcpcd"
+    }
+
+    fn gateway(&self, interface: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        if family != Family::Ipv4 {
+            return Ok(None);
+        }
+
+        for path in &self.paths {
+            let path = PathBuf::from(path.replace("{}", interface));
+            if let Some(lease) = read_optional(&path)? {
+                debug!("Reading {}", path.display());
+                let saved = fs::metadata(&path)?.modified()?;
+                if dhcp_expired(&lease, saved, SystemTime::now()) {
+                    debug!("{} has expired", path.display());
+                    continue;
+                }
+                if let Some(router) = dhcp_router(&lease) {
+                    return Ok(Some(router.into()));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+}
+
+/// systemd-networkd lease state
+#[derive(Debug, Clone)]
+pub struct Networkd {
+    // Directory of leases named after the interface index
+    leases: PathBuf,
+}
+
+impl Default for Networkd {
+    fn default() -> Self {
+        Networkd::new("/run/systemd/netif/leases")
+    }
+}
+
+impl Networkd {
+    /// Look for leases in `leases`
+    pub fn new(leases: impl Into<PathBuf>) -> Networkd {
+        Networkd {
+            leases: leases.into(),
+        }
+    }
+}
+
+// First router of a networkd lease, a file of KEY=VALUE lines such as
+// "ROUTER=10.0.0.1"
+fn networkd_router(lease: &str) -> Option<Ipv4Addr> {
+    lease
+        .lines()
+        .find_map(|line| line.strip_prefix("ROUTER="))
+        .and_then(|routers| routers.split_whitespace().next()?.parse().ok())
+}
+
+impl GatewaySource for Networkd {
+    fn name(&self) -> &'static str {
+        "networkd"
+    }
+
+    fn gateway(&self, interface: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        if family != Family::Ipv4 {
+            return Ok(None);
+        }
+
+        let index =
+            match read_optional(&Path::new("/sys/class/net").join(interface).join("ifindex"))? {
+                Some(index) => String::from_utf8_lossy(&index).trim().to_owned(),
+                None => return Ok(None),
+            };
+
+        let lease = read_optional(&self.leases.join(index))?;
+        let router = lease.and_then(|lease| networkd_router(&String::from_utf8_lossy(&lease)));
+        Ok(router.map(IpAddr::from))
+    }
+}
+
+/// NetworkManager, asked through `nmcli`
+#[derive(Debug, Default)]
+pub struct NetworkManager {
+    commands: CommandCache,
+}
+
+impl GatewaySource for NetworkManager {
+    fn name(&self) -> &'static str {
+        "networkmanager"
+    }
+
+    fn gateway(&self, interface: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        let field = match family {
+            Family::Ipv4 => "IP4.GATEWAY",
+            Family::Ipv6 => "IP6.GATEWAY",
+        };
+
+        // Prints the gateway, or nothing or "--" without one
+        let output = self
+            .commands
+            .output("nmcli", &["-g", field, "device", "show", interface])?;
+        let gateway = output.and_then(|output| output.trim().parse().ok());
+        Ok(gateway.filter(|gateway| Family::of(gateway) == family))
+    }
+}
+
+/// Default routes out of the interface, in any routing table.  Catches
+/// gateways installed by other means, such as a per-interface table, a
+/// higher metric default route added by the DHCP client or the IPv6
+/// default route learned from router advertisements.
+#[derive(Debug, Default)]
+pub struct Routes {
+    commands: CommandCache,
+}
+
+// First gateway in the output of `ip route show default`, lines like
+// "default via 10.0.0.1 table 100 proto dhcp metric 100"
+fn route_gateway(routes: &str) -> Option<IpAddr> {
+    routes.lines().find_map(|line| {
+        let mut fields = line.split_whitespace();
+        fields.find(|field| *field == "via")?;
+        fields.next()?.parse().ok()
+    })
+}
+
+impl GatewaySource for Routes {
+    fn name(&self) -> &'static str {
+        "routes"
+    }
+
+    fn gateway(&self, interface: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        let family = match family {
+            Family::Ipv4 => "-4",
+            Family::Ipv6 => "-6",
+        };
+        let args = [
+            family, "route", "show", "table", "all", "default", "dev", interface,
+        ];
+        let routes = self.commands.output("ip", &args)?;
+        Ok(routes.and_then(|routes| route_gateway(&routes)))
+    }
+}
+
+/// Looks up ISP gateways in a list of sources
+#[derive(Debug)]
+pub struct GatewayDiscovery {
+    sources: Vec<Box<dyn GatewaySource>>,
+}
+
+impl Default for GatewayDiscovery {
+    /// The default sources, without any static gateways
+    fn default() -> Self {
+        GatewayDiscovery::with_sources(&GatewaySourceKind::DEFAULT, &[])
+    }
+}
+
+impl GatewayDiscovery {
+    /// Look in `sources`, in order
+    pub fn new(sources: Vec<Box<dyn GatewaySource>>) -> GatewayDiscovery {
+        GatewayDiscovery { sources }
+    }
+
+    /// Look in the configured sources, at their standard locations
+    pub fn from_config(config: &IspgwdConfig) -> GatewayDiscovery {
+        GatewayDiscovery::with_sources(&config.gateway_sources, &config.isp_configs)
+    }
+
+    // Look in 'kinds' of sources, with the static gateways of 'isps'
+    fn with_sources(kinds: &[GatewaySourceKind], isps: &[IspConfig]) -> GatewayDiscovery {
+        let sources = kinds
+            .iter()
+            .map(|kind| -> Box<dyn GatewaySource> {
+                match kind {
+                    GatewaySourceKind::Static => Box::new(StaticGateways::new(isps)),
+                    GatewaySourceKind::Dhclient => Box::new(Dhclient::default()),
+                    GatewaySourceKind::Dhcpcd => Box::new(Dhcpcd::default()),
+                    GatewaySourceKind::Networkd => Box::new(Networkd::default()),
+                    GatewaySourceKind::NetworkManager => Box::new(NetworkManager::default()),
+                    GatewaySourceKind::Routes => Box::new(Routes::default()),
+                }
+            })
+            .collect();
+
+        GatewayDiscovery::new(sources)
+    }
+
+    /// `family` gateway of `interface` from the first source that
+    /// knows it.  Sources that fail are skipped.
+    pub fn gateway(&self, interface: &str, family: Family) -> Option<Gateway> {
+        for source in &self.sources {
+            match source.gateway(interface, family) {
+                Ok(Some(next_hop)) => {
+                    debug!(
+                        "{} gateway of {} from {}: {}",
+                        family,
+                        interface,
+                        source.name(),
+                        next_hop
+                    );
+                    return Some(Gateway {
+                        device: interface.to_owned(),
+                        next_hop,
+                    });
+                }
+                Ok(None) => {}
+                Err(e) => warn!(
+                    "Unable to read {} gateway of {} from {}: {}",
+                    family,
+                    interface,
+                    source.name(),
+                    e
+                ),
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    // A scratch directory for lease files
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ispgwd-{}-{}", name, process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn dhclient_leases() {
+        let leases = r#"
+lease {
+  interface "eth0";
+  fixed-address 10.0.0.23;
+  option subnet-mask 255.255.255.0;
+  option routers 10.0.0.1;
+  option domain-name-servers 10.0.0.1;
+}
+lease {
+  interface "eth1";
+  fixed-address 192.168.1.5;
+  option routers 192.168.1.1, 192.168.1.2;
+}
+lease {
+  interface "eth0";
+  fixed-address 10.0.0.24;
+  option routers 10.0.0.7;
+  expire 4 2026/10/22 10:00:00;
+}
+"#;
+        let expiry = days_from_civil(2026, 10, 22) * 86_400 + 10 * 3600;
+        assert_eq!(dhclient_expiry("4 2026/10/22 10:00:00"), Some(expiry));
+        assert_eq!(dhclient_expiry("epoch 1792663200"), Some(1_792_663_200));
+        assert_eq!(dhclient_expiry("never"), None);
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+
+        let now = expiry - 1;
+        assert_eq!(
+            dhclient_router(leases, "eth0", now),
+            Some(Ipv4Addr::new(10, 0, 0, 7))
+        );
+        assert_eq!(
+            dhclient_router(leases, "eth1", now),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+        assert_eq!(dhclient_router(leases, "eth2", now), None);
+        assert_eq!(dhclient_router("garbage", "eth0", now), None);
+
+        // the latest lease expired, the older one never does
+        assert_eq!(
+            dhclient_router(leases, "eth0", expiry),
+            Some(Ipv4Addr::new(10, 0, 0, 1))
+        );
+        let commented =
+            "lease {\n  expire epoch 1792663200; # Thu Oct 22\n  option routers 10.0.0.9;\n}\n";
+        assert_eq!(
+            dhclient_router(commented, "eth0", now),
+            Some(Ipv4Addr::new(10, 0, 0, 9))
+        );
+
+        // the first path without a lease is skipped
+        let dir = scratch("dhclient");
+        fs::write(dir.join("dhclient.eth0.leases"), "lease {\n}\n").unwrap();
+        fs::write(
+            dir.join("dhclient.leases"),
+            leases.replace("2026/10/22", "2126/10/22"),
+        )
+        .unwrap();
+        let dhclient = Dhclient::new(&[
+            &format!("{}/dhclient.{{}}.leases", dir.display()),
+            &format!("{}/dhclient.leases", dir.display()),
+        ]);
+        assert_eq!(
+            dhclient.gateway("eth0", Family::Ipv4).unwrap(),
+            Some(IpAddr::from([10, 0, 0, 7]))
+        );
+        assert_eq!(dhclient.gateway("eth0", Family::Ipv6).unwrap(), None);
+        assert_eq!(dhclient.gateway("eth2", Family::Ipv4).unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn dhcpcd_lease() {
+        let mut message = vec![0; BOOTP_LEN];
+        message.extend_from_slice(&DHCP_COOKIE);
+        // message type ack, pad, two routers, end
+        message.extend_from_slice(&[53, 1, 5]);
+        message.push(OPTION_PAD);
+        message.extend_from_slice(&[OPTION_ROUTER, 8, 10, 0, 0, 1, 10, 0, 0, 2]);
+        message.push(OPTION_END);
+        assert_eq!(dhcp_router(&message), Some(Ipv4Addr::new(10, 0, 0, 1)));
+
+        // truncated
+        assert_eq!(dhcp_router(&message[..BOOTP_LEN + 8]), None);
+        assert_eq!(dhcp_router(&message[..100]), None);
+
+        // without a lease time the lease never expires
+        let saved = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let day = Duration::from_secs(86_400);
+        assert!(!dhcp_expired(&message, saved, saved + day));
+        let mut leased = message[..message.len() - 1].to_vec();
+        leased.extend_from_slice(&[OPTION_LEASE_TIME, 4, 0, 0, 0x0e, 0x10]);
+        leased.push(OPTION_END);
+        assert_eq!(dhcp_router(&leased), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert!(!dhcp_expired(
+            &leased,
+            saved,
+            saved + Duration::from_secs(3599)
+        ));
+        assert!(dhcp_expired(
+            &leased,
+            saved,
+            saved + Duration::from_secs(3600)
+        ));
+
+        let dir = scratch("dhcpcd");
+        fs::write(dir.join("eth0.lease"), &message).unwrap();
+        let dhcpcd = Dhcpcd::new(&[&format!("{}/{{}}.lease", dir.display())]);
+        assert_eq!(
+            dhcpcd.gateway("eth0", Family::Ipv4).unwrap(),
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+        assert_eq!(dhcpcd.gateway("eth1", Family::Ipv4).unwrap(), None);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn networkd_and_routes() {
+        let lease =
+            "# This is private data. Do not parse.\nADDRESS=10.0.0.23\nROUTER=10.0.0.1 10.0.0.2\n";
+        assert_eq!(networkd_router(lease), Some(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(networkd_router("ADDRESS=10.0.0.23\n"), None);
+
+        let routes = "default via 10.0.1.1 table 100 proto dhcp metric 100\ndefault via 10.0.1.2 metric 200\n";
+        assert_eq!(route_gateway(routes), Some(IpAddr::from([10, 0, 1, 1])));
+        assert_eq!(route_gateway("default dev ppp0 scope link\n"), None);
+
+        let routes =
+            "default via fe80::1 proto ra metric 1024 expires 1798sec hoplimit 64 pref medium\n";
+        assert_eq!(route_gateway(routes), "fe80::1".parse().ok());
+    }
+
+    #[test]
+    fn discovery_order() {
+        let isp = |interface: &str, gateway| IspConfig {
+            name: interface.to_owned(),
+            interface: interface.to_owned(),
+            priority: 10,
+            probes: Vec::new(),
+            gateway,
+            gateway6: Some("2001:db8::1".parse().unwrap()),
+        };
+        let config = IspgwdConfig {
+            isp_configs: vec![
+                isp("eth0", Some(Ipv4Addr::new(10, 0, 0, 1))),
+                isp("eth1", None),
+            ],
+            gateway_sources: vec![GatewaySourceKind::Static, GatewaySourceKind::Routes],
+            ..Default::default()
+        };
+        let discovery = GatewayDiscovery::from_config(&config);
+
+        assert_eq!(
+            discovery.gateway("eth0", Family::Ipv4),
+            Some(Gateway {
+                device: "eth0".to_owned(),
+                next_hop: IpAddr::from([10, 0, 0, 1]),
+            })
+        );
+        assert_eq!(
+            discovery
+                .gateway("eth1", Family::Ipv6)
+                .map(|gateway| gateway.next_hop.to_string()),
+            Some("2001:db8::1".to_owned())
+        );
+        // neither a static gateway nor routes, as there is no such link
+        assert_eq!(discovery.gateway("no-such-link", Family::Ipv4), None);
+
+        // the default sources are the default configuration's
+        let config: IspgwdConfig =
+            serde_yaml::from_str("{simulation: true, poll_duration_ms: 1000}").unwrap();
+        let names = |discovery: &GatewayDiscovery| -> Vec<&'static str> {
+            discovery.sources.iter().map(|s| s.name()).collect()
+        };
+        assert_eq!(
+            names(&GatewayDiscovery::default()),
+            names(&GatewayDiscovery::from_config(&config))
+        );
+    }
+
+    #[test]
+    fn command_cache() {
+        // the shell prints its own pid, a new one every run
+        let cache = CommandCache::default();
+        let pid = || cache.output("sh", &["-c", "echo $$"]).unwrap();
+        let first = pid();
+        assert!(first.is_some());
+        assert_eq!(pid(), first);
+        assert_eq!(cache.output("no-such-command", &[]).unwrap(), None);
+    }
+}
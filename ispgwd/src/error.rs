@@ -16,6 +16,30 @@ pub enum AppError {
     #[error("Interface name not found: {0}")]
     InterfaceNotFound(String),
 
+    /// External command failed
+    #[error("Command failed: {0}")]
+    CommandFailed(String),
+
+    /// Invalid configuration
+    #[error("Invalid configuration: {0}")]
+    Config(String),
+
+    /// Invalid simulation scenario
+    #[error("Invalid simulation scenario: {0}")]
+    Scenario(String),
+
+    /// Control request failed
+    #[error("Control request failed: {0}")]
+    Control(String),
+
+    /// No route out of an interface to a probe target
+    #[error("No route out of interface: {0}")]
+    NoRoute(String),
+
+    /// Netlink request failed
+    #[error("Netlink error")]
+    NetlinkError(#[from] rtnetlink::Error),
+
     /// Std IO error
     #[error("I/O error")]
     IoError(#[from] io::Error),
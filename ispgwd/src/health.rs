@@ -0,0 +1,310 @@
+//! ISP health model
+//!
+//! Probe rounds go into a sliding window and an ISP only changes state
+//! when the window crosses a threshold: it goes down at `down_loss` and
+//! comes back up at the lower `up_loss`, and likewise for latency, so a
+//! marginal link does not flip on every round.
+//!
+//! Every time an ISP goes down it collects a penalty that decays
+//! exponentially.  Once the penalty passes `suppress_penalty` the ISP is
+//! damped, and stays so until the penalty decays below `reuse_penalty`.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::prelude::*;
+
+/// Outcome of one probe round through an ISP
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    /// Fraction of requests lost, 0-1
+    pub loss: f64,
+
+    /// Mean round trip time of the replies
+    pub rtt: Option<Duration>,
+}
+
+/// Health of an ISP
+#[derive(Debug, Clone)]
+pub struct Health {
+    config: HealthConfig,
+
+    // Latest rounds, oldest first
+    window: VecDeque<Sample>,
+
+    up: bool,
+
+    // Flap penalty, as of 'decayed'
+    penalty: f64,
+    decayed: Option<Instant>,
+
+    damped: bool,
+}
+
+/// Check health settings make sense
+pub fn validate(config: &HealthConfig) -> Result<(), AppError> {
+    let invalid = |reason: &str| Err(AppError::Config(format!("health: {}", reason)));
+
+    if config.window == 0 {
+        return invalid("window must be at least 1");
+    }
+    if !(0.0..=config.down_loss).contains(&config.up_loss) || config.down_loss > 1.0 {
+        return invalid("need 0 <= up_loss <= down_loss <= 1");
+    }
+    if let (Some(up), Some(down)) = (config.up_rtt_ms, config.down_rtt_ms) {
+        if up > down {
+            return invalid("up_rtt_ms is above down_rtt_ms");
+        }
+    }
+    let penalties = [
+        config.flap_penalty,
+        config.suppress_penalty,
+        config.reuse_penalty,
+    ];
+    if !penalties.iter().all(|p| p.is_finite() && *p >= 0.0) {
+        return invalid("penalties must be non-negative numbers");
+    }
+    if config.reuse_penalty > config.suppress_penalty {
+        return invalid("reuse_penalty is above suppress_penalty");
+    }
+    if config.half_life_ms == 0 {
+        return invalid("half_life_ms must be at least 1");
+    }
+
+    Ok(())
+}
+
+impl Health {
+    /// Health of an ISP not probed yet, down until the first round
+    pub fn new(config: HealthConfig) -> Health {
+        Health {
+            config,
+            window: VecDeque::new(),
+            up: false,
+            penalty: 0.0,
+            decayed: None,
+            damped: false,
+        }
+    }
+
+    /// The ISP is up
+    pub fn is_up(&self) -> bool {
+        self.up
+    }
+
+    /// The ISP flapped too much lately
+    pub fn is_damped(&self) -> bool {
+        self.damped
+    }
+
+    /// Current flap penalty
+    pub fn penalty(&self) -> f64 {
+        self.penalty
+    }
+
+    /// Mean loss over the window, all lost without any rounds
+    pub fn loss(&self) -> f64 {
+        match self.window.len() {
+            0 => 1.0,
+            len => self.window.iter().map(|s| s.loss).sum::<f64>() / len as f64,
+        }
+    }
+
+    /// Mean round trip time over the window
+    pub fn rtt(&self) -> Option<Duration> {
+        let rtts: Vec<Duration> = self.window.iter().filter_map(|s| s.rtt).collect();
+        match rtts.len() {
+            0 => None,
+            len => Some(rtts.iter().sum::<Duration>() / len as u32),
+        }
+    }
+
+    // Decay the penalty up to 'now', and lift damping once it is low
+    // enough
+    fn decay(&mut self, now: Instant) {
+        if let Some(decayed) = self.decayed {
+            let half_lives = now.duration_since(decayed).as_secs_f64()
+                / Duration::from_millis(self.config.half_life_ms).as_secs_f64();
+            self.penalty *= 0.5f64.powf(half_lives);
+        }
+        self.decayed = Some(now);
+
+        if self.damped && self.penalty < self.config.reuse_penalty {
+            self.damped = false;
+        }
+    }
+
+    fn go_down(&mut self) {
+        self.up = false;
+        self.penalty += self.config.flap_penalty;
+        if self.penalty > self.config.suppress_penalty {
+            self.damped = true;
+        }
+    }
+
+    /// Add a probe round taken at `now`.  Returns true when the ISP went
+    /// up or down.
+    pub fn record(&mut self, sample: Sample, now: Instant) -> bool {
+        self.decay(now);
+
+        self.window.push_back(sample);
+        while self.window.len() > self.config.window {
+            self.window.pop_front();
+        }
+
+        let loss = self.loss();
+        let rtt_ms = self.rtt().map(|rtt| rtt.as_millis() as u64);
+        let above = |limit: Option<u64>| limit.is_some_and(|limit| rtt_ms > Some(limit));
+
+        if self.up && (loss >= self.config.down_loss || above(self.config.down_rtt_ms)) {
+            self.go_down();
+            true
+        } else if !self.up && loss <= self.config.up_loss && !above(self.config.up_rtt_ms) {
+            self.up = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The ISP can not be probed at all, e.g. its link is down.  It goes
+    /// down right away and starts over with an empty window.  Returns
+    /// true when the ISP was up.
+    pub fn fail(&mut self, now: Instant) -> bool {
+        self.decay(now);
+        self.window.clear();
+
+        match self.up {
+            true => {
+                self.go_down();
+                true
+            }
+            false => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(loss: f64, rtt_ms: u64) -> Sample {
+        Sample {
+            loss,
+            rtt: Some(Duration::from_millis(rtt_ms)),
+        }
+    }
+
+    #[test]
+    fn hysteresis() {
+        let config = HealthConfig {
+            window: 4,
+            down_rtt_ms: Some(200),
+            up_rtt_ms: Some(100),
+            ..Default::default()
+        };
+        validate(&config).unwrap();
+        let mut health = Health::new(config);
+        let now = Instant::now();
+
+        assert!(health.record(sample(0.0, 10), now));
+        assert!(health.is_up());
+        for _ in 0..3 {
+            assert!(!health.record(sample(0.0, 10), now));
+        }
+
+        // one lost round in four is not enough to go down
+        assert!(!health.record(sample(1.0, 10), now));
+        assert!(!health.record(sample(0.0, 10), now));
+        assert!(health.is_up());
+
+        // half of the window lost is
+        assert!(health.record(sample(1.0, 10), now));
+        assert!(!health.is_up());
+        assert_eq!(health.loss(), 0.5);
+
+        // and coming back needs the loss to drop under up_loss
+        assert!(!health.record(sample(0.0, 10), now));
+        assert!(!health.record(sample(0.0, 10), now));
+        assert!(!health.record(sample(0.0, 10), now));
+        assert!(health.record(sample(0.0, 10), now));
+
+        // latency works the same way
+        for _ in 0..3 {
+            health.record(sample(0.0, 300), now);
+        }
+        assert!(!health.is_up());
+        for _ in 0..3 {
+            health.record(sample(0.0, 50), now);
+        }
+        assert!(!health.is_up());
+        health.record(sample(0.0, 50), now);
+        assert!(health.is_up());
+        assert_eq!(health.rtt(), Some(Duration::from_millis(50)));
+    }
+
+    #[test]
+    fn flap_damping() {
+        let config = HealthConfig {
+            window: 1,
+            half_life_ms: 1000,
+            ..Default::default()
+        };
+        let mut health = Health::new(config);
+        let mut now = Instant::now();
+
+        for _ in 0..3 {
+            health.record(sample(0.0, 10), now);
+            assert!(health.fail(now));
+        }
+        assert_eq!(health.penalty(), 3000.0);
+        assert!(health.is_damped());
+        assert!(!health.fail(now));
+
+        // 3000 -> 1500 -> 750 takes two half lives
+        now += Duration::from_millis(1000);
+        health.record(sample(0.0, 10), now);
+        assert!(health.is_up());
+        assert!(health.is_damped());
+        now += Duration::from_millis(1100);
+        health.record(sample(0.0, 10), now);
+        assert!(!health.is_damped());
+        assert!(health.penalty() < 750.0);
+    }
+
+    #[test]
+    fn invalid() {
+        let check = |config| matches!(validate(&config), Err(AppError::Config(_)));
+        assert!(check(HealthConfig {
+            window: 0,
+            ..Default::default()
+        }));
+        assert!(check(HealthConfig {
+            up_loss: 0.6,
+            ..Default::default()
+        }));
+        assert!(check(HealthConfig {
+            down_rtt_ms: Some(100),
+            up_rtt_ms: Some(200),
+            ..Default::default()
+        }));
+        assert!(check(HealthConfig {
+            reuse_penalty: 5000.0,
+            ..Default::default()
+        }));
+        assert!(check(HealthConfig {
+            flap_penalty: -1000.0,
+            ..Default::default()
+        }));
+        assert!(check(HealthConfig {
+            suppress_penalty: f64::NAN,
+            ..Default::default()
+        }));
+        assert!(check(HealthConfig {
+            reuse_penalty: -1.0,
+            suppress_penalty: -0.5,
+            ..Default::default()
+        }));
+        assert!(validate(&HealthConfig::default()).is_ok());
+    }
+}
@@ -0,0 +1,243 @@
+//! ICMP echo prober
+//!
+//! Sends echo requests from inside the process instead of running
+//! `ping`.  The socket can be bound to an interface, so a standby ISP is
+//! probed through its own link whatever the default route is.  Targets
+//! beyond the gateway need a route out of that interface, such as the
+//! higher metric default route a DHCP client installs.  Without one the
+//! probe fails with `AppError::NoRoute`, and the service judges the ISP
+//! by its gateway alone.
+//!
+//! Unprivileged ICMP datagram sockets are used when the system allows
+//! them, raw sockets otherwise.  IPv6 targets get ICMPv6 echo requests,
+//! whose checksum the kernel fills in.
+
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV6};
+use std::process;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::net::get_interface;
+use crate::prelude::*;
+use crate::probe::{is_unroutable, ProbeResult};
+
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST: u8 = 8;
+const ECHO6_REPLY: u8 = 129;
+const ECHO6_REQUEST: u8 = 128;
+const PAYLOAD: &[u8] = b"ispgwd probe";
+
+// Identifier of the next echo request stream, raw sockets see every
+// reply so concurrent probes need different identifiers
+static NEXT_ID: AtomicU16 = AtomicU16::new(0);
+
+// Internet checksum
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(family: Family, id: u16, seq: u16) -> Vec<u8> {
+    let request = match family {
+        Family::Ipv4 => ECHO_REQUEST,
+        Family::Ipv6 => ECHO6_REQUEST,
+    };
+    let mut packet = vec![request, 0, 0, 0];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(PAYLOAD);
+
+    if family == Family::Ipv4 {
+        let sum = checksum(&packet);
+        packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    }
+    packet
+}
+
+// Test if 'packet' is the reply to request 'seq'.  Raw sockets receive
+// replies to every process, and for IPv4 the IP header too.
+fn is_reply(packet: &[u8], family: Family, raw: bool, id: u16, seq: u16) -> bool {
+    let (icmp, reply) = match (family, raw) {
+        (Family::Ipv4, true) => match packet.first() {
+            Some(first) => (
+                &packet[((first & 0x0f) as usize * 4).min(packet.len())..],
+                ECHO_REPLY,
+            ),
+            None => return false,
+        },
+        (Family::Ipv4, false) => (packet, ECHO_REPLY),
+        (Family::Ipv6, _) => (packet, ECHO6_REPLY),
+    };
+
+    icmp.len() >= 8
+        && icmp[0] == reply
+        && icmp[6..8] == seq.to_be_bytes()
+        && (!raw || icmp[4..6] == id.to_be_bytes())
+}
+
+// ICMP socket for 'family' and whether it is raw
+fn open_socket(family: Family) -> io::Result<(Socket, bool)> {
+    let (domain, protocol) = match family {
+        Family::Ipv4 => (Domain::IPV4, Protocol::ICMPV4),
+        Family::Ipv6 => (Domain::IPV6, Protocol::ICMPV6),
+    };
+    match Socket::new(domain, Type::DGRAM, Some(protocol)) {
+        Ok(socket) => Ok((socket, false)),
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+            Socket::new(domain, Type::RAW, Some(protocol)).map(|s| (s, true))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Socket address of `target`.  Link local IPv6 addresses, such as
+/// those of IPv6 gateways, need the interface they are on.
+pub fn socket_addr(
+    target: IpAddr,
+    port: u16,
+    interface: Option<&str>,
+) -> Result<SocketAddr, AppError> {
+    match (target, interface) {
+        (IpAddr::V6(addr), Some(interface)) if is_link_local(&addr) => {
+            let index = get_interface(interface)?.index;
+            Ok(SocketAddrV6::new(addr, port, 0, index).into())
+        }
+        _ => Ok(SocketAddr::new(target, port)),
+    }
+}
+
+fn is_link_local(addr: &Ipv6Addr) -> bool {
+    addr.segments()[0] & 0xffc0 == 0xfe80
+}
+
+/// Send `count` echo requests to `target`, out of `interface` if given,
+/// waiting up to `timeout` for each reply
+pub fn ping(
+    target: IpAddr,
+    interface: Option<&str>,
+    count: u32,
+    timeout: Duration,
+) -> Result<ProbeResult, AppError> {
+    let family = Family::of(&target);
+    let (socket, raw) = open_socket(family)?;
+    if let Some(interface) = interface {
+        socket.bind_device(Some(interface.as_bytes()))?;
+    }
+
+    let mut result = ProbeResult {
+        sent: count,
+        ..Default::default()
+    };
+    let addr = socket_addr(target, 0, interface)?;
+    if let Err(e) = socket.connect(&addr.into()) {
+        if let Some(interface) = interface.filter(|_| is_unroutable(&e)) {
+            return Err(AppError::NoRoute(interface.to_owned()));
+        }
+        debug!("No route to {}: {}", target, e);
+        return Ok(result);
+    }
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed) ^ process::id() as u16;
+    let mut total_rtt = Duration::ZERO;
+    let mut buf = [0; 1500];
+    for seq in 0..count as u16 {
+        let sent_at = Instant::now();
+        if let Err(e) = socket.send(&echo_request(family, id, seq)) {
+            debug!("Unable to send to {}: {}", target, e);
+            continue;
+        }
+
+        let deadline = sent_at + timeout;
+        while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+            if wait.is_zero() {
+                break;
+            }
+            socket.set_read_timeout(Some(wait))?;
+            match (&socket).read(&mut buf) {
+                Ok(len) if is_reply(&buf[..len], family, raw, id, seq) => {
+                    total_rtt += sent_at.elapsed();
+                    result.received += 1;
+                    break;
+                }
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                // timed out, or an ICMP error such as host unreachable
+                Err(_) => break,
+            }
+        }
+    }
+
+    if result.received > 0 {
+        result.rtt = Some(total_rtt / result.received);
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn echo_checksum() {
+        let packet = echo_request(Family::Ipv4, 0x1234, 7);
+        assert_eq!(checksum(&packet), 0);
+        assert!(!is_reply(&packet, Family::Ipv4, false, 0x1234, 7));
+
+        let mut reply = packet.clone();
+        reply[0] = ECHO_REPLY;
+        assert!(is_reply(&reply, Family::Ipv4, false, 0, 7));
+        assert!(!is_reply(&reply, Family::Ipv4, false, 0, 8));
+        assert!(!is_reply(&reply, Family::Ipv6, false, 0, 7));
+
+        // behind a 20 byte IP header
+        let mut ip = vec![0x45];
+        ip.resize(20, 0);
+        ip.extend_from_slice(&reply);
+        assert!(is_reply(&ip, Family::Ipv4, true, 0x1234, 7));
+        assert!(!is_reply(&ip, Family::Ipv4, true, 0x4321, 7));
+
+        // ICMPv6 replies come without the IP header
+        let mut reply = echo_request(Family::Ipv6, 0x1234, 7);
+        assert_eq!(reply[2..4], [0, 0]);
+        reply[0] = ECHO6_REPLY;
+        assert!(is_reply(&reply, Family::Ipv6, true, 0x1234, 7));
+    }
+
+    #[test]
+    fn ping_loopback() {
+        for target in [
+            IpAddr::from(Ipv4Addr::LOCALHOST),
+            Ipv6Addr::LOCALHOST.into(),
+        ] {
+            let result = ping(target, Some("lo"), 2, Duration::from_secs(1)).unwrap();
+            assert_eq!((result.sent, result.received), (2, 2));
+            assert!(result.rtt.is_some());
+            assert!(result.is_up());
+        }
+    }
+
+    #[test]
+    fn link_local_scope() {
+        let addr: IpAddr = "fe80::1".parse().unwrap();
+        match socket_addr(addr, 0, Some("lo")).unwrap() {
+            SocketAddr::V6(addr) => assert_eq!(addr.scope_id(), get_interface("lo").unwrap().index),
+            SocketAddr::V4(_) => panic!("not IPv6"),
+        }
+        assert!(socket_addr(addr, 0, Some("no-such-link")).is_err());
+        assert_eq!(
+            socket_addr("2001:db8::1".parse().unwrap(), 53, Some("no-such-link")).unwrap(),
+            "[2001:db8::1]:53".parse().unwrap()
+        );
+    }
+}
@@ -2,14 +2,35 @@
 
 #![warn(missing_docs)]
 
+mod backend;
 mod config;
+mod control;
+mod discovery;
 mod error;
+mod health;
+mod icmp;
+mod metrics;
 mod net;
+mod netlink;
+mod probe;
 mod service;
+mod sim;
 
+pub use backend::{NetBackend, SystemBackend};
 pub use config::*;
+pub use control::{Command, ControlServer, Request};
+pub use discovery::{
+    Dhclient, Dhcpcd, GatewayDiscovery, GatewaySource, NetworkManager, Networkd, Routes,
+    StaticGateways,
+};
 pub use error::AppError;
+pub use health::{Health, Sample};
+pub use metrics::{Exposition, MetricsServer};
+pub use net::Gateway;
+pub use netlink::NetlinkBackend;
+pub use probe::{Probe, ProbeResult};
 pub use service::Service;
+pub use sim::{Scenario, SimBackend, SimEvent, SimInterface};
 
 pub mod prelude {
     //! Common things to include in all modules
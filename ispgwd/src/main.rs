@@ -55,10 +55,19 @@ fn gather_config(args: &CliArgs) -> Result<IspgwdConfig, anyhow::Error> {
         &args.config_file.display()
     ))?;
 
+    // Scenario files in the config are relative to the config file
+    if let (Some(path), Some(dir)) = (&config.scenario_file, args.config_file.parent()) {
+        config.scenario_file = Some(dir.join(path));
+    }
+
     if args.simulation {
         config.simulation = args.simulation;
     }
 
+    if args.scenario_file.is_some() {
+        config.scenario_file = args.scenario_file.clone();
+    }
+
     debug!("Config: {:?}", config);
 
     Ok(config)
@@ -88,4 +97,8 @@ struct CliArgs {
     /// enable simluation backend
     #[structopt(short, long)]
     simulation: bool,
+
+    /// Scenario file played by the simulation backend
+    #[structopt(long)]
+    scenario_file: Option<PathBuf>,
 }
@@ -0,0 +1,172 @@
+//! Prometheus metrics
+//!
+//! The service renders its state in the Prometheus text format after
+//! every step, and a listener thread serves the latest rendering on
+//! `GET /metrics`.  The listener never touches the service itself, so a
+//! slow scrape can not hold up failover.
+
+use std::fmt::Write as _;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::prelude::*;
+
+// Time a client gets to send its request and read the response
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Metrics in the Prometheus text exposition format
+#[derive(Debug, Default)]
+pub struct Exposition {
+    text: String,
+}
+
+// Escape a label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl Exposition {
+    /// Start a metric of type `kind`, "gauge" or "counter"
+    pub fn metric(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+    }
+
+    /// Add a sample of the current metric
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape(value)))
+                .collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {}", value);
+    }
+
+    /// The rendered metrics
+    pub fn into_text(self) -> String {
+        self.text
+    }
+}
+
+/// Serves the latest metrics over HTTP
+#[derive(Debug)]
+pub struct MetricsServer {
+    addr: SocketAddr,
+    text: Arc<Mutex<String>>,
+}
+
+impl MetricsServer {
+    /// Listen on `addr` in a thread of its own
+    pub fn start(addr: SocketAddr) -> Result<MetricsServer, AppError> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let text = Arc::new(Mutex::new(String::new()));
+
+        let served = Arc::clone(&text);
+        thread::Builder::new()
+            .name("metrics".to_owned())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    let result = stream.and_then(|mut stream| respond(&mut stream, &served));
+                    if let Err(e) = result {
+                        debug!("Metrics request failed: {}", e);
+                    }
+                }
+            })?;
+
+        info!("Serving metrics on http://{}/metrics", addr);
+        Ok(MetricsServer { addr, text })
+    }
+
+    /// Address listened on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Replace the metrics served
+    pub fn update(&self, metrics: Exposition) {
+        *self.text.lock().unwrap_or_else(|e| e.into_inner()) = metrics.into_text();
+    }
+}
+
+// Answer one HTTP request
+fn respond(stream: &mut TcpStream, text: &Mutex<String>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+
+    // Only the request line matters, read up to the end of the headers
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < 8192 {
+        let len = stream.read(&mut buf)?;
+        if len == 0 {
+            break;
+        }
+        request.extend_from_slice(&buf[..len]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut fields = request.split_whitespace();
+
+    let (status, body) = match (fields.next(), fields.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            let text = text.lock().unwrap_or_else(|e| e.into_inner());
+            ("200 OK", text.clone())
+        }
+        (Some("GET"), _) => ("404 Not Found", "Not found, try /metrics\n".to_owned()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serve() {
+        let server = MetricsServer::start("127.0.0.1:0".parse().unwrap()).unwrap();
+        let addr = server.local_addr();
+
+        let mut metrics = Exposition::default();
+        metrics.metric("ispgwd_isp_up", "gauge", "ISP is up");
+        metrics.sample("ispgwd_isp_up", &[("isp", "a \"b\"\\c")], 1.0);
+        metrics.sample("ispgwd_steps_total", &[], 0.5);
+        server.update(metrics);
+
+        let response = get(addr, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(
+            "\r\n\r\n# HELP ispgwd_isp_up ISP is up\n\
+             # TYPE ispgwd_isp_up gauge\n\
+             ispgwd_isp_up{isp=\"a \\\"b\\\"\\\\c\"} 1\n\
+             ispgwd_steps_total 0.5\n"
+        ));
+
+        assert!(get(addr, "/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -2,9 +2,8 @@
 
 use std::fs;
 use std::io::{self, BufRead};
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::process::{Command, Stdio};
-use std::str::FromStr;
 
 use crate::prelude::*;
 
@@ -15,7 +14,14 @@ pub struct Gateway {
     pub device: String,
 
     /// The next hop neighbor IP address
-    pub next_hop: Ipv4Addr,
+    pub next_hop: IpAddr,
+}
+
+impl Gateway {
+    /// Address family of the gateway
+    pub fn family(&self) -> Family {
+        Family::of(&self.next_hop)
+    }
 }
 
 /// Get a NetworkInterface object given an interface name
@@ -28,37 +34,15 @@ pub fn get_interface(name: &str) -> Result<NetworkInterface, AppError> {
     Ok(interface)
 }
 
-/// Get gateway associated with ISP interface
-pub fn get_isp_gateway(name: &str) -> Result<Option<Gateway>, AppError> {
-    let lease_file_name = format!("/tmp/dhclient.{}.leases", name);
-    debug!("Trying to open {}", lease_file_name);
-    let lease_file = fs::File::open(lease_file_name)?;
-    let lines = io::BufReader::new(lease_file).lines();
-    let last_line = lines
-        .filter(|l| l.as_ref().unwrap().contains("option router"))
-        .last();
-    let gateway = if let Some(line) = last_line {
-        let input = line?;
-        debug!("Trying to parse line: {}", input);
-        let fields: Vec<&str> = input.split_whitespace().collect();
-        if fields[0] == "option" && fields[1] == "routers" {
-            debug!("Found line: {}", input);
-            Some(Gateway {
-                device: name.to_owned(),
-                next_hop: Ipv4Addr::from_str(fields[2].trim_end_matches(';'))?,
-            })
-        } else {
-            None
-        }
-    } else {
-        None
-    };
-
-    Ok(gateway)
+/// Get the current default gateway of `family`
+pub fn get_default_gw(family: Family) -> Result<Option<Gateway>, AppError> {
+    match family {
+        Family::Ipv4 => get_default_gw4(),
+        Family::Ipv6 => get_default_gw6(),
+    }
 }
 
-/// Get the current default IPv4 gateway
-pub fn get_default_gw() -> Result<Option<Gateway>, AppError> {
+fn get_default_gw4() -> Result<Option<Gateway>, AppError> {
     let proc_file = fs::File::open("/proc/net/route")?;
     let mut lines = io::BufReader::new(proc_file).lines();
 
@@ -82,7 +66,7 @@ pub fn get_default_gw() -> Result<Option<Gateway>, AppError> {
                 if let Ok(gateway) = u32::from_str_radix(fields[2], 16) {
                     result = Some(Gateway {
                         device: intf.to_owned(),
-                        next_hop: Ipv4Addr::from(u32::from_be(gateway)),
+                        next_hop: Ipv4Addr::from(u32::from_be(gateway)).into(),
                     });
                     break;
                 }
@@ -93,12 +77,120 @@ pub fn get_default_gw() -> Result<Option<Gateway>, AppError> {
     Ok(result)
 }
 
-/// ping IPv4 address
-pub fn ping_addr(dest: &str) -> Result<bool, AppError> {
-    Ok(Command::new("ping")
-        .args(["-4", "-c", "2", "-w", "2", dest])
+fn get_default_gw6() -> Result<Option<Gateway>, AppError> {
+    // /proc/net/ipv6_route lists the routes of every table, ask for the
+    // main table only
+    let args = ["-6", "route", "show", "table", "main", "default"];
+    let output = Command::new("ip")
+        .args(args)
+        .stdin(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(AppError::CommandFailed(format!(
+            "ip {}: {}",
+            args.join(" "),
+            output.status
+        )));
+    }
+
+    Ok(default_route6(&String::from_utf8_lossy(&output.stdout)))
+}
+
+// Default route with the lowest metric in the output of `ip -6 route
+// show default`.  Lines look like
+// default via fe80::1 dev eth0 proto ra metric 1024 expires 1798sec pref medium
+// and multipath routes list their next hops on lines of their own,
+// "nexthop via fe80::2 dev eth1 weight 1", of which the first is used.
+fn default_route6(routes: &str) -> Option<Gateway> {
+    let mut result: Option<(u32, Gateway)> = None;
+    let mut metric = 0;
+    for line in routes.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let value = |name| {
+            let i = fields.iter().position(|field| *field == name)?;
+            fields.get(i + 1).copied()
+        };
+        if fields.first() == Some(&"default") {
+            metric = value("metric").and_then(|m| m.parse().ok()).unwrap_or(0);
+        }
+
+        let (next_hop, device) = match (value("via").map(str::parse::<Ipv6Addr>), value("dev")) {
+            (Some(Ok(next_hop)), Some(device)) => (next_hop, device),
+            _ => continue,
+        };
+        let better = match &result {
+            Some((best, _)) => metric < *best,
+            None => true,
+        };
+        if better {
+            let gateway = Gateway {
+                device: device.to_owned(),
+                next_hop: next_hop.into(),
+            };
+            result = Some((metric, gateway));
+        }
+    }
+
+    result.map(|(_, gateway)| gateway)
+}
+
+/// Replace the default route of the family of `gateway` with one
+/// through `gateway`
+pub fn replace_default_gw(gateway: &Gateway) -> Result<(), AppError> {
+    let next_hop = gateway.next_hop.to_string();
+    let args = [
+        match gateway.family() {
+            Family::Ipv4 => "-4",
+            Family::Ipv6 => "-6",
+        },
+        "route",
+        "replace",
+        "default",
+        "via",
+        &next_hop,
+        "dev",
+        &gateway.device,
+    ];
+    let status = Command::new("ip")
+        .args(args)
         .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()?
-        .success())
+        .status()?;
+
+    if !status.success() {
+        return Err(AppError::CommandFailed(format!(
+            "ip {}: {}",
+            args.join(" "),
+            status
+        )));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_routes6() {
+        let gateway = |device: &str, next_hop: &str| Gateway {
+            device: device.to_owned(),
+            next_hop: next_hop.parse().unwrap(),
+        };
+
+        let routes = "\
+default via fe80::1 dev eth0 proto ra metric 1024 expires 1798sec pref medium
+default via fe80::2 dev eth1 proto static metric 100 pref medium
+default dev ppp0 proto kernel metric 50 pref medium
+";
+        assert_eq!(default_route6(routes), Some(gateway("eth1", "fe80::2")));
+
+        let multipath = "\
+default proto ra metric 1024 pref medium
+\tnexthop via fe80::1 dev eth0 weight 1
+\tnexthop via fe80::2 dev eth1 weight 1
+";
+        assert_eq!(default_route6(multipath), Some(gateway("eth0", "fe80::1")));
+        assert_eq!(default_route6(""), None);
+    }
 }
@@ -0,0 +1,343 @@
+//! Netlink backend
+//!
+//! Reads links and routes and replaces the default route over rtnetlink
+//! instead of parsing `/proc` and running `ip`.  The backend listens
+//! for link, address and route changes, so the service reacts to a lost
+//! carrier right away instead of at the next poll.
+
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::Duration;
+
+use futures_util::stream::{StreamExt, TryStreamExt};
+use rtnetlink::packet_core::NetlinkMessage;
+use rtnetlink::packet_route::link::{LinkAttribute, LinkFlags, LinkMessage};
+use rtnetlink::packet_route::route::{RouteAddress, RouteAttribute, RouteHeader, RouteMessage};
+use rtnetlink::packet_route::RouteNetlinkMessage;
+use rtnetlink::sys::SocketAddr;
+use rtnetlink::{new_multicast_connection, Handle, MulticastGroup, RouteMessageBuilder};
+use tokio::runtime::{self, Runtime};
+
+use crate::backend::NetBackend;
+use crate::discovery::GatewayDiscovery;
+use crate::net::*;
+use crate::prelude::*;
+use crate::probe::{run_probes, Probe, ProbeResult};
+
+type Events =
+    futures_channel::mpsc::UnboundedReceiver<(NetlinkMessage<RouteNetlinkMessage>, SocketAddr)>;
+
+/// Backend changing the routing of the host over netlink.  Needs root.
+pub struct NetlinkBackend {
+    runtime: Runtime,
+    handle: Handle,
+    events: Events,
+    gateways: GatewayDiscovery,
+}
+
+impl fmt::Debug for NetlinkBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NetlinkBackend").finish_non_exhaustive()
+    }
+}
+
+// A link: index, name and flags
+struct Link {
+    index: u32,
+    name: String,
+    flags: LinkFlags,
+}
+
+impl From<LinkMessage> for Link {
+    fn from(msg: LinkMessage) -> Self {
+        let name = msg
+            .attributes
+            .into_iter()
+            .find_map(|attr| match attr {
+                LinkAttribute::IfName(name) => Some(name),
+                _ => None,
+            })
+            .unwrap_or_default();
+
+        Link {
+            index: msg.header.index,
+            name,
+            flags: msg.header.flags,
+        }
+    }
+}
+
+// Gateway, output interface index and metric of a default route in the
+// main table
+fn default_route(msg: &RouteMessage) -> Option<(IpAddr, u32, u32)> {
+    if msg.header.destination_prefix_length != 0 {
+        return None;
+    }
+
+    let mut table = msg.header.table as u32;
+    let mut gateway = None;
+    let mut oif = None;
+    let mut metric = 0;
+    for attr in &msg.attributes {
+        match attr {
+            RouteAttribute::Table(t) => table = *t,
+            RouteAttribute::Gateway(RouteAddress::Inet(addr)) => gateway = Some((*addr).into()),
+            RouteAttribute::Gateway(RouteAddress::Inet6(addr)) => gateway = Some((*addr).into()),
+            RouteAttribute::Oif(index) => oif = Some(*index),
+            RouteAttribute::Priority(m) => metric = *m,
+            _ => {}
+        }
+    }
+
+    if table != RouteHeader::RT_TABLE_MAIN as u32 {
+        return None;
+    }
+    Some((gateway?, oif?, metric))
+}
+
+// Gateway and output interface index of the default route of 'family'
+// with the lowest metric
+fn best_default_route(routes: &[RouteMessage], family: Family) -> Option<(IpAddr, u32)> {
+    routes
+        .iter()
+        .filter_map(default_route)
+        .filter(|(next_hop, _, _)| Family::of(next_hop) == family)
+        .min_by_key(|(_, _, metric)| *metric)
+        .map(|(next_hop, index, _)| (next_hop, index))
+}
+
+// Events that should wake the service
+fn wakes_service(msg: &NetlinkMessage<RouteNetlinkMessage>) -> bool {
+    use rtnetlink::packet_core::NetlinkPayload::InnerMessage;
+    use RouteNetlinkMessage::*;
+
+    match &msg.payload {
+        InnerMessage(NewLink(_) | DelLink(_) | NewAddress(_) | DelAddress(_)) => true,
+        InnerMessage(DelRoute(route)) => default_route(route).is_some(),
+        _ => false,
+    }
+}
+
+impl NetlinkBackend {
+    /// Open a netlink connection.  ISP gateways are looked up with
+    /// `gateways`.
+    pub fn new(gateways: GatewayDiscovery) -> Result<NetlinkBackend, AppError> {
+        let runtime = runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let (connection, handle, events) = {
+            let _guard = runtime.enter();
+            new_multicast_connection(&[
+                MulticastGroup::Link,
+                MulticastGroup::Ipv4Ifaddr,
+                MulticastGroup::Ipv4Route,
+                MulticastGroup::Ipv6Ifaddr,
+                MulticastGroup::Ipv6Route,
+            ])?
+        };
+        runtime.spawn(connection);
+
+        Ok(NetlinkBackend {
+            runtime,
+            handle,
+            events,
+            gateways,
+        })
+    }
+
+    fn links(&mut self) -> Result<Vec<Link>, AppError> {
+        let handle = &self.handle;
+        let links = self.runtime.block_on(async {
+            handle
+                .link()
+                .get()
+                .execute()
+                .map_ok(Link::from)
+                .try_collect::<Vec<_>>()
+                .await
+        })?;
+
+        Ok(links)
+    }
+
+    fn link(&mut self, name: &str) -> Result<Link, AppError> {
+        self.links()?
+            .into_iter()
+            .find(|link| link.name == name)
+            .ok_or_else(|| AppError::InterfaceNotFound(name.to_owned()))
+    }
+}
+
+impl NetBackend for NetlinkBackend {
+    fn link_up(&mut self, interface: &str) -> Result<bool, AppError> {
+        let flags = self.link(interface)?.flags;
+        Ok(flags.contains(LinkFlags::Up | LinkFlags::LowerUp))
+    }
+
+    fn default_gateway(&mut self, family: Family) -> Result<Option<Gateway>, AppError> {
+        let handle = &self.handle;
+        let routes = self.runtime.block_on(async {
+            let route = match family {
+                Family::Ipv4 => RouteMessageBuilder::<Ipv4Addr>::new().build(),
+                Family::Ipv6 => RouteMessageBuilder::<Ipv6Addr>::new().build(),
+            };
+            handle
+                .route()
+                .get(route)
+                .execute()
+                .try_collect::<Vec<_>>()
+                .await
+        })?;
+
+        let (next_hop, index) = match best_default_route(&routes, family) {
+            Some(best) => best,
+            None => return Ok(None),
+        };
+
+        let device = self
+            .links()?
+            .into_iter()
+            .find(|link| link.index == index)
+            .map(|link| link.name)
+            .ok_or_else(|| AppError::InterfaceNotFound(format!("index {}", index)))?;
+
+        Ok(Some(Gateway { device, next_hop }))
+    }
+
+    fn isp_gateway(
+        &mut self,
+        interface: &str,
+        family: Family,
+    ) -> Result<Option<Gateway>, AppError> {
+        Ok(self.gateways.gateway(interface, family))
+    }
+
+    fn replace_default_gateway(&mut self, gateway: &Gateway) -> Result<(), AppError> {
+        let index = self.link(&gateway.device)?.index;
+        let route = match gateway.next_hop {
+            IpAddr::V4(next_hop) => RouteMessageBuilder::<Ipv4Addr>::new()
+                .gateway(next_hop)
+                .output_interface(index)
+                .build(),
+            IpAddr::V6(next_hop) => RouteMessageBuilder::<Ipv6Addr>::new()
+                .gateway(next_hop)
+                .output_interface(index)
+                .build(),
+        };
+
+        let handle = &self.handle;
+        self.runtime
+            .block_on(handle.route().add(route).replace().execute())?;
+
+        Ok(())
+    }
+
+    fn probe(&mut self, probes: &[Probe]) -> Vec<Result<ProbeResult, AppError>> {
+        run_probes(probes)
+    }
+
+    fn wait(&mut self, timeout: Duration) {
+        let events = &mut self.events;
+        let woken = self.runtime.block_on(async {
+            tokio::time::timeout(timeout, async {
+                while let Some((msg, _)) = events.next().await {
+                    if wakes_service(&msg) {
+                        debug!("Woken by netlink event: {:?}", msg.payload);
+                        return true;
+                    }
+                }
+                std::future::pending().await
+            })
+            .await
+        });
+
+        // A lost carrier comes with a burst of events, handle them in one
+        // step
+        if matches!(woken, Ok(true)) {
+            while self.events.try_recv().is_ok() {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(gateway: Ipv4Addr, oif: u32, metric: u32) -> RouteMessageBuilder<Ipv4Addr> {
+        RouteMessageBuilder::<Ipv4Addr>::new()
+            .gateway(gateway)
+            .output_interface(oif)
+            .priority(metric)
+    }
+
+    #[test]
+    fn default_routes() {
+        let gw = |last| Ipv4Addr::new(10, 0, 0, last);
+        let routes = vec![
+            route(gw(1), 2, 200).build(),
+            route(gw(2), 3, 100).build(),
+            // lower metrics, but not default routes of the main table
+            route(gw(3), 4, 10).table_id(100).build(),
+            route(gw(4), 5, 10).table_id(1000).build(),
+            route(gw(5), 6, 10)
+                .destination_prefix(Ipv4Addr::new(192, 168, 0, 0), 16)
+                .build(),
+            RouteMessageBuilder::<Ipv6Addr>::new()
+                .gateway("fe80::1".parse().unwrap())
+                .output_interface(7)
+                .priority(1024)
+                .build(),
+        ];
+
+        assert_eq!(default_route(&routes[0]), Some((gw(1).into(), 2, 200)));
+        for route in &routes[2..5] {
+            assert_eq!(default_route(route), None);
+        }
+        assert_eq!(
+            best_default_route(&routes, Family::Ipv4),
+            Some((gw(2).into(), 3))
+        );
+        assert_eq!(
+            best_default_route(&routes, Family::Ipv6),
+            Some(("fe80::1".parse().unwrap(), 7))
+        );
+        assert_eq!(best_default_route(&routes[2..5], Family::Ipv4), None);
+    }
+
+    #[test]
+    fn wakeups() {
+        let default = route(Ipv4Addr::new(10, 0, 0, 1), 2, 100).build();
+        let other = route(Ipv4Addr::new(10, 0, 0, 1), 2, 100)
+            .table_id(100)
+            .build();
+        let wakes = |msg: RouteNetlinkMessage| wakes_service(&NetlinkMessage::from(msg));
+
+        // carrier changes come as new link messages
+        assert!(wakes(RouteNetlinkMessage::NewLink(LinkMessage::default())));
+        assert!(wakes(RouteNetlinkMessage::DelLink(LinkMessage::default())));
+        assert!(wakes(RouteNetlinkMessage::DelRoute(default.clone())));
+
+        assert!(!wakes(RouteNetlinkMessage::DelRoute(other)));
+        assert!(!wakes(RouteNetlinkMessage::NewRoute(default)));
+    }
+
+    #[test]
+    #[ignore = "needs netlink access to the host"]
+    fn read_links_and_routes() {
+        let mut backend = NetlinkBackend::new(GatewayDiscovery::default()).unwrap();
+        assert!(backend.link_up("lo").is_ok());
+        assert!(matches!(
+            backend.link_up("no-such-link"),
+            Err(AppError::InterfaceNotFound(_))
+        ));
+        for family in [Family::Ipv4, Family::Ipv6] {
+            assert!(backend.default_gateway(family).is_ok());
+        }
+
+        // link events on the host may end the wait early, never late
+        let start = std::time::Instant::now();
+        backend.wait(Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
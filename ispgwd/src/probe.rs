@@ -0,0 +1,638 @@
+//! Reachability probes
+//!
+//! Every probe runs out of an ISP interface, so it checks that ISP
+//! whatever the default route is.  ICMP probes measure round trip time
+//! and loss over a couple of echo requests, the others whether the
+//! service answers at all.
+//!
+//! Probes run over IPv4 or IPv6, host names resolve to addresses of the
+//! family of the probe.  Names are looked up ahead of time by the
+//! service, a probe only connects to the address it is given.
+//!
+//! A probe that finds no route out of its interface fails with
+//! `AppError::NoRoute` rather than coming back down, the ISP may be
+//! fine but the host has no route out of it yet.
+
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::panic;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::icmp;
+use crate::prelude::*;
+
+// Echo requests per ICMP probe
+const ICMP_COUNT: u32 = 2;
+
+const DNS_PORT: u16 = 53;
+
+// Longest DNS label and name
+const DNS_LABEL_MAX: usize = 63;
+const DNS_NAME_MAX: usize = 253;
+
+// Bytes of an HTTP response read looking for the status line
+const HTTP_STATUS_MAX: usize = 1024;
+
+// Identifier of the next DNS query
+static NEXT_QUERY_ID: AtomicU16 = AtomicU16::new(1);
+
+/// A probe run out of `interface`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Probe {
+    /// Interface to send from
+    pub interface: String,
+
+    /// What to probe
+    pub kind: ProbeKind,
+
+    /// Family probed
+    pub family: Family,
+
+    /// Address of the host name `kind` targets, if it was looked up.
+    /// Probes of host names without one are down.
+    pub addr: Option<IpAddr>,
+
+    /// Time to wait for an answer
+    pub timeout: Duration,
+}
+
+/// Outcome of a probe
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProbeResult {
+    /// Requests sent
+    pub sent: u32,
+
+    /// Replies received
+    pub received: u32,
+
+    /// Mean round trip time of the replies
+    pub rtt: Option<Duration>,
+}
+
+impl ProbeResult {
+    /// The target answered at least once
+    pub fn is_up(&self) -> bool {
+        self.received > 0
+    }
+
+    /// Fraction of requests lost, 0-1
+    pub fn loss(&self) -> f64 {
+        match self.sent {
+            0 => 0.0,
+            sent => 1.0 - self.received as f64 / sent as f64,
+        }
+    }
+}
+
+/// Look up the first `family` address of `host`.  Blocks until the
+/// system resolver answers.
+pub fn resolve(host: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+    Ok((host, 0)
+        .to_socket_addrs()?
+        .map(|addr| addr.ip())
+        .find(|addr| Family::of(addr) == family))
+}
+
+/// Host name a probe targets, None for addresses
+pub fn host_name(kind: &ProbeKind) -> Option<&str> {
+    let host = match kind {
+        ProbeKind::Tcp { target } => split_host_port(target, None)?.0,
+        ProbeKind::Http { url, .. } => parse_url(url)?.0,
+        ProbeKind::Icmp { .. } | ProbeKind::Dns { .. } => return None,
+    };
+    host.parse::<IpAddr>().is_err().then_some(host)
+}
+
+// Address to connect to for 'host', an address itself or a name looked
+// up as 'addr'
+fn host_addr(host: &str, port: u16, addr: Option<IpAddr>) -> Option<SocketAddr> {
+    let ip = host.parse().ok().or(addr)?;
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Test if `e` means the kernel has no route out of the interface a
+/// socket is bound to
+pub fn is_unroutable(e: &io::Error) -> bool {
+    e.raw_os_error() == Some(libc::ENETUNREACH)
+}
+
+fn bound_socket(
+    family: Family,
+    ty: Type,
+    protocol: Protocol,
+    interface: &str,
+) -> Result<Socket, AppError> {
+    let domain = match family {
+        Family::Ipv4 => Domain::IPV4,
+        Family::Ipv6 => Domain::IPV6,
+    };
+    let socket = Socket::new(domain, ty, Some(protocol))?;
+    socket.bind_device(Some(interface.as_bytes()))?;
+    Ok(socket)
+}
+
+// TCP connection to 'addr' out of 'interface', None if it fails
+fn tcp_connect(
+    addr: SocketAddr,
+    interface: &str,
+    timeout: Duration,
+) -> Result<Option<TcpStream>, AppError> {
+    let family = Family::of(&addr.ip());
+    let socket = bound_socket(family, Type::STREAM, Protocol::TCP, interface)?;
+    match socket.connect_timeout(&addr.into(), timeout) {
+        Ok(()) => Ok(Some(socket.into())),
+        Err(e) if is_unroutable(&e) => Err(AppError::NoRoute(interface.to_owned())),
+        Err(e) => {
+            debug!("Unable to connect to {} on {}: {}", addr, interface, e);
+            Ok(None)
+        }
+    }
+}
+
+fn probe_tcp(
+    target: &str,
+    addr: Option<IpAddr>,
+    interface: &str,
+    timeout: Duration,
+) -> Result<bool, AppError> {
+    let (host, port) = match split_host_port(target, None) {
+        Some(parts) => parts,
+        None => return Err(AppError::Config(format!("Not a host:port: {}", target))),
+    };
+    match host_addr(host, port, addr) {
+        Some(addr) => Ok(tcp_connect(addr, interface, timeout)?.is_some()),
+        None => {
+            debug!("No address for {}", host);
+            Ok(false)
+        }
+    }
+}
+
+// Split "host:port" into host and port, 'default_port' if there is
+// none.  IPv6 addresses are in brackets, "[2001:db8::1]:8080".
+fn split_host_port(authority: &str, default_port: Option<u16>) -> Option<(&str, u16)> {
+    let (host, port) = match authority.strip_prefix('[') {
+        Some(bracketed) => {
+            let (host, port) = bracketed.split_once(']')?;
+            match port {
+                "" => (host, default_port?),
+                port => (host, port.strip_prefix(':')?.parse().ok()?),
+            }
+        }
+        None => match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None => (authority, default_port?),
+        },
+    };
+
+    (!host.is_empty()).then_some((host, port))
+}
+
+// Split an http:// URL into host, port and path.  IPv6 addresses are
+// in brackets, "http://[2001:db8::1]:8080/".
+fn parse_url(url: &str) -> Option<(&str, u16, &str)> {
+    let rest = url.strip_prefix("http://")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let (host, port) = split_host_port(authority, Some(80))?;
+    Some((host, port, path))
+}
+
+fn probe_http(
+    url: &str,
+    status: u16,
+    addr: Option<IpAddr>,
+    interface: &str,
+    timeout: Duration,
+) -> Result<bool, AppError> {
+    let (host, port, path) = match parse_url(url) {
+        Some(parts) => parts,
+        None => return Err(AppError::Config(format!("Unsupported URL: {}", url))),
+    };
+    let mut stream = match host_addr(host, port, addr) {
+        Some(addr) => match tcp_connect(addr, interface, timeout)? {
+            Some(stream) => stream,
+            None => return Ok(false),
+        },
+        None => {
+            debug!("No address for {}", host);
+            return Ok(false);
+        }
+    };
+
+    let exchange = |stream: &mut TcpStream| -> io::Result<Vec<u8>> {
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+        let host = match host.contains(':') {
+            true => format!("[{}]", host),
+            false => host.to_owned(),
+        };
+        // one write, a server may answer the first segment it sees
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: ispgwd\r\nConnection: close\r\n\r\n",
+            path, host
+        );
+        stream.write_all(request.as_bytes())?;
+
+        // Read up to the end of the status line, "HTTP/1.1 200 OK"
+        let mut response = Vec::new();
+        let mut buf = [0; 256];
+        while !response.windows(2).any(|w| w == b"\r\n") && response.len() < HTTP_STATUS_MAX {
+            let len = stream.read(&mut buf)?;
+            if len == 0 {
+                break;
+            }
+            response.extend_from_slice(&buf[..len]);
+        }
+        Ok(response)
+    };
+
+    match exchange(&mut stream) {
+        Ok(response) => {
+            let response = String::from_utf8_lossy(&response);
+            let got = response.split_whitespace().nth(1);
+            debug!("GET {} on {}: {:?}", url, interface, got);
+            Ok(got == Some(&status.to_string()))
+        }
+        Err(e) => {
+            debug!("GET {} on {}: {}", url, interface, e);
+            Ok(false)
+        }
+    }
+}
+
+fn dns_query(id: u16, name: &str) -> Vec<u8> {
+    // id, recursion desired, one question
+    let mut query = id.to_be_bytes().to_vec();
+    query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    // root, type A, class IN
+    query.extend_from_slice(&[0, 0, 1, 0, 1]);
+    query
+}
+
+// Test if 'response' answers query 'id' with at least one record
+fn dns_answered(response: &[u8], id: u16) -> bool {
+    response.len() >= 12
+        && response[0..2] == id.to_be_bytes()
+        && response[2] & 0x80 != 0
+        && response[3] & 0x0f == 0
+        && u16::from_be_bytes([response[6], response[7]]) > 0
+}
+
+// Check a name can be put in a DNS query
+fn validate_dns_name(name: &str) -> Result<(), AppError> {
+    let name = name.strip_suffix('.').unwrap_or(name);
+    if name.len() > DNS_NAME_MAX {
+        return Err(AppError::Config(format!(
+            "DNS name {} is longer than {} bytes",
+            name, DNS_NAME_MAX
+        )));
+    }
+    let bad_label = name
+        .split('.')
+        .find(|label| label.is_empty() || label.len() > DNS_LABEL_MAX);
+    match bad_label {
+        Some(label) => Err(AppError::Config(format!(
+            "DNS name {} has a label of {} bytes, it must have 1-{}",
+            name,
+            label.len(),
+            DNS_LABEL_MAX
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn probe_dns(
+    server: IpAddr,
+    port: u16,
+    name: &str,
+    interface: &str,
+    timeout: Duration,
+) -> Result<bool, AppError> {
+    let family = Family::of(&server);
+    let socket = bound_socket(family, Type::DGRAM, Protocol::UDP, interface)?;
+    let id = NEXT_QUERY_ID.fetch_add(1, Ordering::Relaxed);
+    let deadline = Instant::now() + timeout;
+
+    let addr = icmp::socket_addr(server, port, Some(interface))?;
+    let sent = socket
+        .connect(&addr.into())
+        .and_then(|_| socket.send(&dns_query(id, name)));
+    match sent {
+        Ok(_) => {}
+        Err(e) if is_unroutable(&e) => return Err(AppError::NoRoute(interface.to_owned())),
+        Err(e) => {
+            debug!("Unable to query {} on {}: {}", server, interface, e);
+            return Ok(false);
+        }
+    }
+
+    let mut buf = [0; 512];
+    while let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+        if wait.is_zero() {
+            break;
+        }
+        socket.set_read_timeout(Some(wait))?;
+        match (&socket).read(&mut buf) {
+            Ok(len) if buf[..len].starts_with(&id.to_be_bytes()) => {
+                return Ok(dns_answered(&buf[..len], id))
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+
+    Ok(false)
+}
+
+// Family of the address a probe targets, None for host names
+fn address_family(kind: &ProbeKind) -> Option<Family> {
+    let addr = match kind {
+        ProbeKind::Icmp { target } => *target,
+        ProbeKind::Dns { server, .. } => *server,
+        ProbeKind::Tcp { target } => target.parse::<SocketAddr>().ok()?.ip(),
+        ProbeKind::Http { url, .. } => parse_url(url)?.0.parse().ok()?,
+    };
+    Some(Family::of(&addr))
+}
+
+/// Family a probe runs over
+pub fn family(config: &ProbeConfig) -> Family {
+    address_family(&config.kind)
+        .or(config.family)
+        .unwrap_or(Family::Ipv4)
+}
+
+/// Check a probe can run
+pub fn validate(config: &ProbeConfig) -> Result<(), AppError> {
+    match &config.kind {
+        ProbeKind::Tcp { target } if split_host_port(target, None).is_none() => {
+            return Err(AppError::Config(format!("Not a host:port: {}", target)));
+        }
+        ProbeKind::Http { url, .. } if parse_url(url).is_none() => {
+            return Err(AppError::Config(format!("Unsupported URL: {}", url)));
+        }
+        ProbeKind::Dns { name, .. } => validate_dns_name(name)?,
+        _ => {}
+    }
+
+    match (address_family(&config.kind), config.family) {
+        (Some(family), Some(configured)) if family != configured => Err(AppError::Config(format!(
+            "{} probe {} is not {}",
+            family, config.kind, configured
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Run a probe
+pub fn run_probe(probe: &Probe) -> Result<ProbeResult, AppError> {
+    let interface = probe.interface.as_str();
+    let start = Instant::now();
+    let up = match &probe.kind {
+        ProbeKind::Icmp { target } => {
+            return icmp::ping(*target, Some(interface), ICMP_COUNT, probe.timeout)
+        }
+        ProbeKind::Tcp { target } => probe_tcp(target, probe.addr, interface, probe.timeout)?,
+        ProbeKind::Http { url, status } => {
+            probe_http(url, *status, probe.addr, interface, probe.timeout)?
+        }
+        ProbeKind::Dns { server, name } => {
+            probe_dns(*server, DNS_PORT, name, interface, probe.timeout)?
+        }
+    };
+
+    Ok(ProbeResult {
+        sent: 1,
+        received: up as u32,
+        rtt: up.then(|| start.elapsed()),
+    })
+}
+
+/// Run probes concurrently, one thread each
+pub fn run_probes(probes: &[Probe]) -> Vec<Result<ProbeResult, AppError>> {
+    thread::scope(|s| {
+        let handles: Vec<_> = probes
+            .iter()
+            .map(|probe| s.spawn(move || run_probe(probe)))
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|e| panic::resume_unwind(e)))
+            .collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{Ipv4Addr, TcpListener, UdpSocket};
+
+    use super::*;
+
+    fn probe(kind: ProbeKind) -> Probe {
+        Probe {
+            interface: "lo".to_owned(),
+            family: address_family(&kind).unwrap(),
+            kind,
+            addr: None,
+            timeout: Duration::from_millis(500),
+        }
+    }
+
+    #[test]
+    fn url() {
+        assert_eq!(
+            parse_url("http://example.com"),
+            Some(("example.com", 80, "/"))
+        );
+        assert_eq!(
+            parse_url("http://127.0.0.1:8080/a/b?c"),
+            Some(("127.0.0.1", 8080, "/a/b?c"))
+        );
+        assert_eq!(
+            parse_url("http://[2001:db8::1]:8080/x"),
+            Some(("2001:db8::1", 8080, "/x"))
+        );
+        assert_eq!(parse_url("http://[::1]"), Some(("::1", 80, "/")));
+        assert_eq!(parse_url("https://example.com/"), None);
+        assert_eq!(parse_url("http://:80/"), None);
+        assert_eq!(parse_url("http://[::1/"), None);
+        assert_eq!(parse_url("http://[::1]80/"), None);
+    }
+
+    #[test]
+    fn families() {
+        let config = |kind, family| ProbeConfig {
+            kind,
+            timeout_ms: 1000,
+            weight: 1,
+            family,
+        };
+        let tcp = |target: &str| ProbeKind::Tcp {
+            target: target.to_owned(),
+        };
+
+        let ping6 = ProbeKind::Icmp {
+            target: "2001:4860:4860::8888".parse().unwrap(),
+        };
+        assert_eq!(family(&config(ping6.clone(), None)), Family::Ipv6);
+        assert_eq!(family(&config(tcp("[::1]:443"), None)), Family::Ipv6);
+        assert_eq!(family(&config(tcp("example.com:443"), None)), Family::Ipv4);
+        let named6 = config(tcp("example.com:443"), Some(Family::Ipv6));
+        assert_eq!(family(&named6), Family::Ipv6);
+        assert!(validate(&named6).is_ok());
+
+        assert!(matches!(
+            validate(&config(ping6, Some(Family::Ipv4))),
+            Err(AppError::Config(_))
+        ));
+        let http6 = ProbeKind::Http {
+            url: "http://[2001:db8::1]/".to_owned(),
+            status: 200,
+        };
+        assert_eq!(family(&config(http6, None)), Family::Ipv6);
+    }
+
+    #[test]
+    fn tcp_and_http() {
+        for host in ["127.0.0.1", "[::1]"] {
+            tcp_and_http_on(host);
+        }
+    }
+
+    fn tcp_and_http_on(host: &str) {
+        let listener = TcpListener::bind(format!("{}:0", host)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            for _ in 0..3 {
+                let (mut stream, _) = listener.accept().unwrap();
+                // the whole request, closing with some of it unread
+                // resets the connection
+                let mut request = Vec::new();
+                let mut buf = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buf).unwrap() {
+                        0 => break,
+                        len => request.extend_from_slice(&buf[..len]),
+                    }
+                }
+                if !request.is_empty() {
+                    stream
+                        .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                        .unwrap();
+                }
+            }
+        });
+
+        let tcp = probe(ProbeKind::Tcp {
+            target: format!("{}:{}", host, port),
+        });
+        assert!(run_probe(&tcp).unwrap().is_up());
+
+        let http = |status| {
+            probe(ProbeKind::Http {
+                url: format!("http://{}:{}/generate_204", host, port),
+                status,
+            })
+        };
+        let result = run_probe(&http(204)).unwrap();
+        assert_eq!((result.sent, result.received), (1, 1));
+        assert!(result.rtt.is_some());
+        assert!(!run_probe(&http(200)).unwrap().is_up());
+        server.join().unwrap();
+
+        // nothing listens any more
+        assert!(!run_probe(&tcp).unwrap().is_up());
+    }
+
+    #[test]
+    fn dns() {
+        let query = dns_query(0x1234, "example.com.");
+        assert_eq!(query.len(), 12 + 13 + 4);
+        assert_eq!(&query[12..20], b"\x07example");
+
+        // echo queries back as answers, with one record and then none
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = server.local_addr().unwrap().port();
+        let responder = thread::spawn(move || {
+            for records in [1, 0] {
+                let mut buf = [0; 512];
+                let (len, from) = server.recv_from(&mut buf).unwrap();
+                buf[2] |= 0x80;
+                buf[7] = records;
+                server.send_to(&buf[..len], from).unwrap();
+            }
+        });
+
+        let localhost = IpAddr::from(Ipv4Addr::LOCALHOST);
+        let timeout = Duration::from_millis(500);
+        assert!(probe_dns(localhost, port, "example.com", "lo", timeout).unwrap());
+        assert!(!probe_dns(localhost, port, "example.com", "lo", timeout).unwrap());
+        responder.join().unwrap();
+
+        // nothing answers any more
+        assert!(!probe_dns(localhost, port, "example.com", "lo", timeout).unwrap());
+    }
+
+    #[test]
+    fn config_fields() {
+        let parse = |yaml| serde_yaml::from_str::<ProbeConfig>(yaml);
+
+        let http = parse("{type: http, url: 'http://example.com/', weight: 2}").unwrap();
+        assert_eq!(
+            http.kind,
+            ProbeKind::Http {
+                url: "http://example.com/".to_owned(),
+                status: 200
+            }
+        );
+        assert_eq!((http.weight, http.timeout_ms), (2, 1000));
+        assert_eq!(host_name(&http.kind), Some("example.com"));
+        assert!(parse("{type: icmp, target: 8.8.8.8}").is_ok());
+
+        // misspelt, missing and misplaced fields
+        assert!(parse("{type: http, url: 'http://example.com/', wieght: 2}").is_err());
+        assert!(parse("{type: dns, server: 8.8.8.8}").is_err());
+        assert!(parse("{type: tcp, target: 'example.com:443', status: 200}").is_err());
+        assert!(parse("{type: icmp, target: example.com}").is_err());
+
+        let dns = |name: &str| {
+            validate(&ProbeConfig {
+                kind: ProbeKind::Dns {
+                    server: "8.8.8.8".parse().unwrap(),
+                    name: name.to_owned(),
+                },
+                timeout_ms: 1000,
+                weight: 1,
+                family: None,
+            })
+        };
+        assert!(dns("example.com.").is_ok());
+        assert!(dns("example..com").is_err());
+        assert!(dns(&format!("{}.com", "a".repeat(64))).is_err());
+        assert!(dns(&vec!["a".repeat(63); 5].join(".")).is_err());
+
+        let tcp = |target: &str| ProbeKind::Tcp {
+            target: target.to_owned(),
+        };
+        assert_eq!(host_name(&tcp("[::1]:443")), None);
+        assert_eq!(host_name(&tcp("example.com:443")), Some("example.com"));
+        assert!(validate(&ProbeConfig {
+            kind: tcp("example.com"),
+            ..http
+        })
+        .is_err());
+    }
+}
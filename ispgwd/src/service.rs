@@ -1,33 +1,128 @@
-//! Future service experiment
+//! ISP failover service
+//!
+//! The service keeps the default route of every managed address family
+//! on the most preferred ISP that is up.  IPv4 and IPv6 fail over as one
+//! group, to an ISP that is up in every family it has a gateway for, or
+//! each on its own.
 
-use std::thread;
-use std::time::Instant;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::time::{Duration, Instant};
 
+use crate::backend::{NetBackend, SystemBackend};
+use crate::control::{Command, ControlServer};
+use crate::discovery::GatewayDiscovery;
+use crate::health::{self, Health, Sample};
+use crate::metrics::{Exposition, MetricsServer};
 use crate::net::*;
+use crate::netlink::NetlinkBackend;
 use crate::prelude::*;
+use crate::probe::{self, Probe, ProbeResult};
+use crate::sim::{Scenario, SimBackend};
+
+// Hosts probed when no probes are configured, make these odd numbers
+const INTERNET_HOSTS: [Ipv4Addr; 3] = [
+    Ipv4Addr::new(8, 8, 8, 8), // google DNS
+    Ipv4Addr::new(1, 1, 1, 1), // cloudflare DNS
+    Ipv4Addr::new(9, 9, 9, 9), // quad9
+];
+const INTERNET_HOSTS6: [Ipv6Addr; 3] = [
+    Ipv6Addr::new(0x2001, 0x4860, 0x4860, 0, 0, 0, 0, 0x8888), // google DNS
+    Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111), // cloudflare DNS
+    Ipv6Addr::new(0x2620, 0xfe, 0, 0, 0, 0, 0, 0xfe),          // quad9
+];
+
+// Time to wait for the gateway to answer
+const GATEWAY_TIMEOUT: Duration = Duration::from_secs(1);
+
+// Time between lookups of probed host names that did not resolve
+const RESOLVE_RETRY: Duration = Duration::from_secs(60);
+
+// One address family of an ISP
+#[derive(Debug, Clone)]
+struct Stack {
+    // Gateway of the ISP in this family
+    gateway: Option<Gateway>,
+
+    // Probes run through the ISP
+    probes: Vec<ProbeConfig>,
+
+    // Loss and latency over the latest probe rounds
+    health: Health,
+
+    // Instant when this ISP became the active gateway
+    became_active: Option<Instant>,
+
+    // Instant when this ISP went bad
+    became_bad: Option<Instant>,
+
+    // Instant when this ISP last went up or down
+    changed: Option<Instant>,
+}
 
 #[derive(Debug, Clone)]
 struct IspState {
-    #[allow(dead_code)]
     // ISP name
     name: String,
 
-    // Interface associated with ISP
-    interface: NetworkInterface,
+    // Name of the interface associated with ISP
+    interface: String,
 
-    // IPv4 gateway ISP
-    gateway: Option<Gateway>,
+    // Link of the interface is up
+    link: bool,
 
     #[allow(dead_code)]
-    // Priority of ISP, lower is more deseriable
+    // Priority of ISP, lower is more desirable
     priority: u32,
 
-    // Instant when this ISP becamse the active gateway
-    became_active: Option<Instant>,
+    // Drained by the operator, never used
+    drained: bool,
 
-    #[allow(dead_code)]
-    // Instant when this ISP went bad
-    became_bad: Option<Instant>,
+    // Address families, in the order of the routes
+    stacks: Vec<Stack>,
+}
+
+impl IspState {
+    // Up in every family of 'group' it has a gateway for, and has one
+    fn is_up(&self, group: &[usize]) -> bool {
+        let mut stacks = group
+            .iter()
+            .map(|r| &self.stacks[*r])
+            .filter(|stack| stack.gateway.is_some())
+            .peekable();
+        stacks.peek().is_some() && stacks.all(|stack| stack.health.is_up())
+    }
+
+    // Went bad lately or is damped in a family of 'group'
+    fn is_held(&self, group: &[usize]) -> bool {
+        group.iter().map(|r| &self.stacks[*r]).any(|stack| {
+            stack.gateway.is_some() && (stack.became_bad.is_some() || stack.health.is_damped())
+        })
+    }
+}
+
+// Default route of an address family
+#[derive(Debug, Clone)]
+struct Route {
+    family: Family,
+
+    // Current default gateway
+    gateway: Option<Gateway>,
+
+    // Is the internet reachable via default gateway
+    good: bool,
+
+    // Current active ISP.  Index into isps
+    active_isp: Option<usize>,
+
+    // The active ISP was switched to in a failover, so moving to a more
+    // preferred one is a failback
+    failed_over: bool,
+
+    // Switches to a less and to a more preferred ISP
+    failovers: u64,
+    failbacks: u64,
 }
 
 #[derive(Debug, Clone)]
@@ -36,69 +131,378 @@ enum ServiceState {
     Monitor,
 }
 
+impl ServiceState {
+    const ALL: [ServiceState; 2] = [ServiceState::Discovery, ServiceState::Monitor];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ServiceState::Discovery => "discovery",
+            ServiceState::Monitor => "monitor",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct ServiceFsmState {
     // FSM state
     state: ServiceState,
 
-    // Vector of ISP state
+    // Vector of ISP state, sorted by priority
     isps: Vec<IspState>,
 
-    // Current default gateway
-    default_gw: Option<Gateway>,
+    // Managed default routes, IPv4 first
+    routes: Vec<Route>,
 
-    // Is the internet reachable via default gateway
-    gw_good: bool,
+    // ISP pinned by the operator, used whenever it is up.  Index into
+    // isps
+    pinned: Option<usize>,
 
-    // Current active ISP.  Index into isps
-    active_isp: Option<usize>,
+    // ISP the operator failed over to, and when.  More preferred ISPs
+    // are held off until the hold time is over.
+    manual_failover: Option<(usize, Instant)>,
+
+    // Latest ISP going up or down or route switch, and when
+    last_transition: Option<(Instant, String)>,
+
+    // Addresses of the host names probed, by name and family
+    hosts: BTreeMap<(String, Family), IpAddr>,
+
+    // When to look up names that did not resolve again
+    resolve_retry: Option<Instant>,
 
     // count of iterations
     count: u32,
 }
 
 /// Service struct
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Service {
     #[allow(dead_code)]
     config: IspgwdConfig,
 
-    poll_duration: std::time::Duration,
+    poll_duration: Duration,
+
+    failback_hold: Duration,
+
+    min_dwell: Duration,
+
+    failover: FailoverMode,
+
+    backend: Box<dyn NetBackend>,
+
+    // Serves the state as Prometheus metrics
+    metrics: Option<MetricsServer>,
+
+    // Takes commands from ispgwctl
+    control: Option<ControlServer>,
 
     inner: ServiceFsmState,
 }
 
+// Global and ISP probes of 'family', or pings of well known hosts if
+// there are none
+fn isp_probes(
+    config: &IspgwdConfig,
+    isp: &IspConfig,
+    family: Family,
+) -> Result<Vec<ProbeConfig>, AppError> {
+    let mut probes: Vec<ProbeConfig> = config
+        .probes
+        .iter()
+        .chain(&isp.probes)
+        .filter(|probe| probe::family(probe) == family)
+        .cloned()
+        .collect();
+    if probes.is_empty() {
+        let hosts: Vec<IpAddr> = match family {
+            Family::Ipv4 => INTERNET_HOSTS.iter().map(|&host| host.into()).collect(),
+            Family::Ipv6 => INTERNET_HOSTS6.iter().map(|&host| host.into()).collect(),
+        };
+        probes = hosts
+            .into_iter()
+            .map(|target| ProbeConfig {
+                kind: ProbeKind::Icmp { target },
+                timeout_ms: 1000,
+                weight: 1,
+                family: None,
+            })
+            .collect();
+    }
+
+    if probes.iter().all(|probe| probe.weight == 0) {
+        return Err(AppError::Config(format!(
+            "ISP {} {} probes all have weight 0",
+            isp.name, family
+        )));
+    }
+
+    Ok(probes)
+}
+
+// Sample of a probe round through 'isp', from the results of its
+// gateway probe followed by its configured probes.  A round where the
+// gateway or probes of most of the weight do not answer is all lost.
+// When no probe finds a route out of the ISP, as for a standby ISP
+// without a default route of its own, the gateway answering has to do.
+fn probe_round(
+    isp: &str,
+    stack: &Stack,
+    results: impl Iterator<Item = Result<ProbeResult, AppError>>,
+) -> Sample {
+    let mut results = results.map(|result| match result {
+        Ok(result) => Some(result),
+        Err(AppError::NoRoute(_)) => None,
+        Err(e) => {
+            warn!("Probe through {} failed: {}", isp, e);
+            Some(Default::default())
+        }
+    });
+    let gateway = results.next().flatten().unwrap_or_default();
+
+    let mut sent = 0;
+    let mut received = 0;
+    let mut rtts = Vec::new();
+    let mut up_weight = 0;
+    let mut total_weight = 0;
+    let mut routed = false;
+    for (probe, result) in stack.probes.iter().zip(results) {
+        let result = match result {
+            Some(result) => {
+                routed = true;
+                result
+            }
+            None => {
+                debug!("ISP {}: no route to {}", isp, probe.kind);
+                Default::default()
+            }
+        };
+        sent += result.sent;
+        received += result.received;
+        total_weight += probe.weight;
+        if result.is_up() {
+            up_weight += probe.weight;
+            rtts.extend(result.rtt);
+        } else {
+            debug!("ISP {}: {} is down", isp, probe.kind);
+        }
+    }
+    debug!(
+        "ISP {}: gateway_up: {}, weight up: {}/{}",
+        isp,
+        gateway.is_up(),
+        up_weight,
+        total_weight
+    );
+
+    if !gateway.is_up() {
+        return Sample {
+            loss: 1.0,
+            rtt: None,
+        };
+    }
+    if !routed {
+        debug!(
+            "ISP {}: no route beyond the gateway, judging by the gateway",
+            isp
+        );
+        return Sample {
+            loss: gateway.loss(),
+            rtt: gateway.rtt,
+        };
+    }
+    if up_weight * 2 <= total_weight {
+        return Sample {
+            loss: 1.0,
+            rtt: None,
+        };
+    }
+    Sample {
+        loss: 1.0 - received as f64 / sent.max(1) as f64,
+        rtt: match rtts.len() {
+            0 => None,
+            len => Some(rtts.iter().sum::<Duration>() / len as u32),
+        },
+    }
+}
+
+// Gateway of an ISP interface, None if it can not be read
+fn lookup_gateway(
+    backend: &mut dyn NetBackend,
+    interface: &str,
+    family: Family,
+) -> Option<Gateway> {
+    match backend.isp_gateway(interface, family) {
+        Ok(gateway) => gateway,
+        Err(e) => {
+            warn!("Unable to read {} gateway of {}: {}", family, interface, e);
+            None
+        }
+    }
+}
+
+// Name, help and value of a metric per ISP and family
+type StackMetric = (&'static str, &'static str, fn(&Stack) -> Option<f64>);
+
+// Name, type, help and value of a metric per family
+type RouteMetric = (&'static str, &'static str, &'static str, fn(&Route) -> f64);
+
+// Whole seconds from 'then' to 'now', for the operator
+fn secs_since(now: Instant, then: Instant) -> u64 {
+    now.duration_since(then).as_secs()
+}
+
+// Metric value of a flag
+fn flag(set: bool) -> f64 {
+    match set {
+        true => 1.0,
+        false => 0.0,
+    }
+}
+
 impl Service {
-    /// Create a new service
+    /// Create a new service managing the routing of this host, or
+    /// playing the configured scenario when simulating
     pub fn new(config: IspgwdConfig) -> Result<Service, AppError> {
+        if !config.simulation {
+            let gateways = GatewayDiscovery::from_config(&config);
+            let backend: Box<dyn NetBackend> = match config.netlink {
+                true => Box::new(NetlinkBackend::new(gateways)?),
+                false => Box::new(SystemBackend::new(gateways)),
+            };
+            return Service::with_backend(config, backend);
+        }
+
+        let scenario = match &config.scenario_file {
+            Some(path) => Scenario::from_file(path)?,
+            None => {
+                return Err(AppError::Scenario(
+                    "simulation needs a scenario file".to_owned(),
+                ))
+            }
+        };
+        let step = Duration::from_millis(config.poll_duration_ms);
+        let backend = SimBackend::new(scenario, step)?;
+        Service::with_backend(config, Box::new(backend))
+    }
+
+    /// Create a new service using `backend` for network operations
+    pub fn with_backend(
+        config: IspgwdConfig,
+        mut backend: Box<dyn NetBackend>,
+    ) -> Result<Service, AppError> {
         let mut isp_configs = config.isp_configs.clone();
-        isp_configs.sort_by(|a, b| a.priority.cmp(&b.priority));
+        isp_configs.sort_by_key(|isp| isp.priority);
+
+        // ISPs are told apart by the interface of the default route
+        for (i, isp) in isp_configs.iter().enumerate() {
+            if let Some(other) = isp_configs[..i]
+                .iter()
+                .find(|other| other.interface == isp.interface)
+            {
+                return Err(AppError::Config(format!(
+                    "ISPs {} and {} share interface {}",
+                    other.name, isp.name, isp.interface
+                )));
+            }
+        }
+
+        health::validate(&config.health)?;
+        for probe in config
+            .probes
+            .iter()
+            .chain(isp_configs.iter().flat_map(|isp| &isp.probes))
+        {
+            probe::validate(probe)?;
+        }
+
+        let families = match config.ipv6 {
+            true => vec![Family::Ipv4, Family::Ipv6],
+            false => vec![Family::Ipv4],
+        };
+        let mut routes = Vec::new();
+        for family in families {
+            // Read again at every step, a failure may be over by then
+            let gateway = match backend.default_gateway(family) {
+                Ok(gateway) => gateway,
+                Err(e) => {
+                    warn!("Unable to read the {} default route: {}", family, e);
+                    None
+                }
+            };
+            routes.push(Route {
+                family,
+                gateway,
+                good: false,
+                active_isp: None,
+                failed_over: false,
+                failovers: 0,
+                failbacks: 0,
+            });
+        }
 
         let mut isps = Vec::new();
         for isp in isp_configs {
+            let mut stacks = Vec::new();
+            for route in &routes {
+                stacks.push(Stack {
+                    gateway: lookup_gateway(backend.as_mut(), &isp.interface, route.family),
+                    probes: isp_probes(&config, &isp, route.family)?,
+                    health: Health::new(config.health.clone()),
+                    became_active: None,
+                    became_bad: None,
+                    changed: None,
+                });
+            }
+            // e.g. a PPP or LTE interface not brought up yet
+            let link = match backend.link_up(&isp.interface) {
+                Ok(link) => link,
+                Err(e) => {
+                    warn!("Unable to read link of {}: {}", isp.interface, e);
+                    false
+                }
+            };
             let isp_state = IspState {
                 name: isp.name.to_owned(),
-                interface: get_interface(&isp.interface)?,
+                link,
+                interface: isp.interface,
                 priority: isp.priority,
-                gateway: get_isp_gateway(&isp.interface)?,
-                became_active: None,
-                became_bad: None,
+                drained: false,
+                stacks,
             };
             isps.push(isp_state);
         }
 
-        let poll_duration = std::time::Duration::from_millis(config.poll_duration_ms);
-        let default_gw = get_default_gw()?;
+        let poll_duration = Duration::from_millis(config.poll_duration_ms);
+        let failback_hold = Duration::from_millis(config.failback_hold_ms);
+        let min_dwell = Duration::from_millis(config.health.min_dwell_ms);
+        let failover = config.failover;
+        let metrics = match config.metrics_listen {
+            Some(addr) => Some(MetricsServer::start(addr)?),
+            None => None,
+        };
+        let control = match &config.control_socket {
+            Some(path) => Some(ControlServer::start(path)?),
+            None => None,
+        };
 
         Ok(Service {
             config,
             poll_duration,
+            failback_hold,
+            min_dwell,
+            failover,
+            backend,
+            metrics,
+            control,
             inner: ServiceFsmState {
                 state: ServiceState::Discovery,
                 isps,
-                default_gw,
-                gw_good: false,
-                active_isp: None,
+                routes,
+                pinned: None,
+                manual_failover: None,
+                last_transition: None,
+                hosts: BTreeMap::new(),
+                resolve_retry: None,
                 count: 0,
             },
         })
@@ -109,69 +513,716 @@ impl Service {
         debug!("Running a service: {:#?}", self);
 
         loop {
-            let should_stop = self.handle_state()?;
+            let should_stop = self.step()?;
             if should_stop {
                 break;
             }
-            thread::sleep(self.poll_duration);
-            self.inner.count += 1;
+            self.backend.wait(self.poll_duration);
         }
 
         Ok(())
     }
 
-    fn handle_discovery(&mut self) -> Result<bool, AppError> {
-        info!("Discovery");
+    /// Run one iteration of the service.  Returns true when the service
+    /// should stop.
+    pub fn step(&mut self) -> Result<bool, AppError> {
+        self.backend.tick();
+        self.handle_commands();
+        let should_stop = self.handle_state()? || self.backend.finished();
+        self.inner.count += 1;
+        if let Some(server) = &self.metrics {
+            server.update(self.metrics());
+        }
+        Ok(should_stop)
+    }
 
-        // get default gateway
-        self.inner.default_gw = get_default_gw()?;
-        debug!("Found gateway: {:#?}", self.inner.default_gw);
+    /// Current state as Prometheus metrics
+    pub fn metrics(&self) -> Exposition {
+        let mut metrics = Exposition::default();
+        let isps = &self.inner.isps;
+        let routes = &self.inner.routes;
+        metrics.metric("ispgwd_state", "gauge", "Current state of the service");
+        for state in ServiceState::ALL {
+            let current = state.name() == self.inner.state.name();
+            metrics.sample("ispgwd_state", &[("state", state.name())], flag(current));
+        }
 
-        // update all the interface info
-        for (i, isp) in self.inner.isps.iter_mut().enumerate() {
-            isp.interface = get_interface(&isp.interface.name)?;
-            isp.gateway = get_isp_gateway(&isp.interface.name)?;
-            if isp.gateway == self.inner.default_gw {
-                isp.became_active = Some(Instant::now());
-                self.inner.active_isp = Some(i);
-            }
-        }
-
-        // check internet reachability
-        let mut good_host = 0;
-        let mut bad_host = 0;
-        // make this an odd number
-        let internet_hosts = [
-            "8.8.8.8", // google DNS
-            "1.1.1.1", // cloudflare DNS
-            "9.9.9.9", // quad9
+        metrics.metric("ispgwd_steps_total", "counter", "Iterations of the service");
+        metrics.sample("ispgwd_steps_total", &[], self.inner.count as f64);
+
+        metrics.metric(
+            "ispgwd_isp_link_up",
+            "gauge",
+            "Link of the ISP interface is up",
+        );
+        for isp in isps {
+            let labels = [("isp", isp.name.as_str()), ("interface", &isp.interface)];
+            metrics.sample("ispgwd_isp_link_up", &labels, flag(isp.link));
+        }
+
+        metrics.metric(
+            "ispgwd_isp_drained",
+            "gauge",
+            "ISP is drained by the operator",
+        );
+        for isp in isps {
+            metrics.sample(
+                "ispgwd_isp_drained",
+                &[("isp", &isp.name)],
+                flag(isp.drained),
+            );
+        }
+
+        metrics.metric(
+            "ispgwd_isp_pinned",
+            "gauge",
+            "ISP is pinned by the operator",
+        );
+        for (i, isp) in isps.iter().enumerate() {
+            let pinned = self.inner.pinned == Some(i);
+            metrics.sample("ispgwd_isp_pinned", &[("isp", &isp.name)], flag(pinned));
+        }
+
+        // Per ISP and family, only for families the ISP has a gateway in
+        let stacks: Vec<(&IspState, &Route, &Stack)> = isps
+            .iter()
+            .flat_map(|isp| {
+                routes
+                    .iter()
+                    .zip(&isp.stacks)
+                    .map(move |(r, s)| (isp, r, s))
+            })
+            .filter(|(_, _, stack)| stack.gateway.is_some())
+            .collect();
+        let per_stack: [StackMetric; 5] = [
+            ("ispgwd_isp_up", "ISP is up", |s| {
+                Some(flag(s.health.is_up()))
+            }),
+            ("ispgwd_isp_loss_ratio", "Mean probe loss of the ISP", |s| {
+                Some(s.health.loss())
+            }),
+            (
+                "ispgwd_isp_rtt_seconds",
+                "Mean probe round trip time of the ISP",
+                |s| s.health.rtt().map(|rtt| rtt.as_secs_f64()),
+            ),
+            ("ispgwd_isp_flap_penalty", "Flap penalty of the ISP", |s| {
+                Some(s.health.penalty())
+            }),
+            ("ispgwd_isp_damped", "ISP is damped for flapping", |s| {
+                Some(flag(s.health.is_damped()))
+            }),
+        ];
+        for (name, help, value) in per_stack {
+            metrics.metric(name, "gauge", help);
+            for (isp, route, stack) in &stacks {
+                if let Some(value) = value(stack) {
+                    let family = route.family.to_string();
+                    metrics.sample(name, &[("isp", &isp.name), ("family", &family)], value);
+                }
+            }
+        }
+
+        metrics.metric(
+            "ispgwd_isp_active",
+            "gauge",
+            "ISP carries the default route",
+        );
+        for (i, isp) in isps.iter().enumerate() {
+            for route in routes {
+                let family = route.family.to_string();
+                let labels = [("isp", isp.name.as_str()), ("family", &family)];
+                metrics.sample(
+                    "ispgwd_isp_active",
+                    &labels,
+                    flag(route.active_isp == Some(i)),
+                );
+            }
+        }
+
+        let per_route: [RouteMetric; 3] = [
+            (
+                "ispgwd_route_good",
+                "gauge",
+                "Internet is reachable over the default route",
+                |r| flag(r.good),
+            ),
+            (
+                "ispgwd_failovers_total",
+                "counter",
+                "Switches to a less preferred ISP",
+                |r| r.failovers as f64,
+            ),
+            (
+                "ispgwd_failbacks_total",
+                "counter",
+                "Switches to a more preferred ISP",
+                |r| r.failbacks as f64,
+            ),
         ];
-        for host in internet_hosts {
-            match ping_addr(host)? {
-                true => good_host += 1,
-                false => bad_host += 1,
+        for (name, kind, help, value) in per_route {
+            metrics.metric(name, kind, help);
+            for route in routes {
+                let family = route.family.to_string();
+                metrics.sample(name, &[("family", &family)], value(route));
+            }
+        }
+
+        metrics
+    }
+
+    // Read the default gateways and ISP gateways, and find which ISP is
+    // active in each family
+    fn refresh(&mut self) -> Result<(), AppError> {
+        for route in self.inner.routes.iter_mut() {
+            // e.g. a route dump racing the interface going away, keep
+            // the last gateway read until the next step
+            match self.backend.default_gateway(route.family) {
+                Ok(gateway) => route.gateway = gateway,
+                Err(e) => warn!("Unable to read the {} default route: {}", route.family, e),
             }
+            route.active_isp = None;
+            debug!("Found {} gateway: {:#?}", route.family, route.gateway);
         }
 
-        info!("good_host: {}, bad_host: {}", good_host, bad_host);
-        self.inner.gw_good = good_host > bad_host;
+        let now = self.backend.now();
+        for (i, isp) in self.inner.isps.iter_mut().enumerate() {
+            let link = match self.backend.link_up(&isp.interface) {
+                Ok(link) => link,
+                Err(e) => {
+                    warn!("Unable to read link of {}: {}", isp.interface, e);
+                    false
+                }
+            };
+            if link != isp.link {
+                info!("ISP {} link {}", isp.name, if link { "up" } else { "down" });
+                isp.link = link;
+            }
+
+            for (route, stack) in self.inner.routes.iter_mut().zip(&mut isp.stacks) {
+                stack.gateway = lookup_gateway(self.backend.as_mut(), &isp.interface, route.family);
+                if stack.gateway.is_some() && stack.gateway == route.gateway {
+                    stack.became_active.get_or_insert(now);
+                    route.active_isp = Some(i);
+                } else {
+                    stack.became_active = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Look up the host names probed that have no address yet.  Lookups
+    // block and go out through the active ISP, so they are done in
+    // discovery rather than by every probe, and retried now and then
+    // for names that did not resolve.
+    fn resolve_hosts(&mut self) {
+        let mut missing = false;
+        for isp in &self.inner.isps {
+            for (route, stack) in self.inner.routes.iter().zip(&isp.stacks) {
+                for host in stack
+                    .probes
+                    .iter()
+                    .filter_map(|p| probe::host_name(&p.kind))
+                {
+                    let key = (host.to_owned(), route.family);
+                    if self.inner.hosts.contains_key(&key) {
+                        continue;
+                    }
+                    match self.backend.resolve(host, route.family) {
+                        Ok(Some(addr)) => {
+                            debug!("Probed host {} is {}", host, addr);
+                            self.inner.hosts.insert(key, addr);
+                        }
+                        Ok(None) => {
+                            warn!("Probed host {} has no {} address", host, route.family);
+                            missing = true;
+                        }
+                        Err(e) => {
+                            warn!("Unable to look up probed host {}: {}", host, e);
+                            missing = true;
+                        }
+                    }
+                }
+            }
+        }
+
+        let now = self.backend.now();
+        self.inner.resolve_retry = missing.then(|| now + RESOLVE_RETRY);
+    }
+
+    // Probe the gateway and configured targets through every ISP with
+    // a link, in every family, all at once
+    fn probe_isps(&mut self) {
+        let mut probes = Vec::new();
+        for isp in &self.inner.isps {
+            for (route, stack) in self.inner.routes.iter().zip(&isp.stacks) {
+                if let Some(gateway) = stack.gateway.as_ref().filter(|_| isp.link) {
+                    probes.push(Probe {
+                        interface: gateway.device.clone(),
+                        kind: ProbeKind::Icmp {
+                            target: gateway.next_hop,
+                        },
+                        family: route.family,
+                        addr: None,
+                        timeout: GATEWAY_TIMEOUT,
+                    });
+                    probes.extend(stack.probes.iter().map(|probe| Probe {
+                        interface: gateway.device.clone(),
+                        kind: probe.kind.clone(),
+                        family: route.family,
+                        addr: probe::host_name(&probe.kind).and_then(|host| {
+                            let key = (host.to_owned(), route.family);
+                            self.inner.hosts.get(&key).copied()
+                        }),
+                        timeout: Duration::from_millis(probe.timeout_ms),
+                    }));
+                }
+            }
+        }
+        let mut results = self.backend.probe(&probes).into_iter();
+
+        let now = self.backend.now();
+        for isp in self.inner.isps.iter_mut() {
+            for (route, stack) in self.inner.routes.iter().zip(&mut isp.stacks) {
+                let family = route.family;
+                let was_damped = stack.health.is_damped();
+                let changed = match isp.link && stack.gateway.is_some() {
+                    true => {
+                        let results = results.by_ref().take(1 + stack.probes.len());
+                        let sample = probe_round(&isp.name, stack, results);
+                        stack.health.record(sample, now)
+                    }
+                    false => stack.health.fail(now),
+                };
+                debug!(
+                    "ISP {} {}: up: {}, loss: {:.2}, rtt: {:?}, penalty: {:.0}",
+                    isp.name,
+                    family,
+                    stack.health.is_up(),
+                    stack.health.loss(),
+                    stack.health.rtt(),
+                    stack.health.penalty()
+                );
+
+                if changed && stack.health.is_up() {
+                    info!("ISP {} {} is up", isp.name, family);
+                } else if changed {
+                    warn!("ISP {} lost {} internet access", isp.name, family);
+                    stack.became_bad = Some(now);
+                }
+                if changed {
+                    stack.changed = Some(now);
+                    let up = if stack.health.is_up() { "up" } else { "down" };
+                    self.inner.last_transition =
+                        Some((now, format!("ISP {} {} went {}", isp.name, family, up)));
+                }
+                match (was_damped, stack.health.is_damped()) {
+                    (false, true) => warn!("ISP {} {} is flapping, damping it", isp.name, family),
+                    (true, false) => info!("ISP {} {} is no longer damped", isp.name, family),
+                    _ => {}
+                }
+
+                // An ISP that went bad is held off for a while after it
+                // recovers
+                if stack.health.is_up()
+                    && stack
+                        .became_bad
+                        .is_some_and(|t| now.duration_since(t) >= self.failback_hold)
+                {
+                    info!("ISP {} {} recovered", isp.name, family);
+                    stack.became_bad = None;
+                }
+            }
+        }
+
+        for (r, route) in self.inner.routes.iter_mut().enumerate() {
+            let good = route
+                .active_isp
+                .is_some_and(|i| self.inner.isps[i].stacks[r].health.is_up());
+            if good != route.good {
+                info!("Internet reachable over {}: {}", route.family, good);
+            }
+            route.good = good;
+        }
+    }
+
+    // Routes failing over together, as indices into routes
+    fn groups(&self) -> Vec<Vec<usize>> {
+        let routes = 0..self.inner.routes.len();
+        match self.failover {
+            FailoverMode::Together => vec![routes.collect()],
+            FailoverMode::Independent => routes.map(|r| vec![r]).collect(),
+        }
+    }
+
+    // Most preferred ISP that is up in 'group'.  An ISP that went bad
+    // is only used again once it is past the hold time and no longer
+    // damped, unless it is still active.  With nothing else left any ISP
+    // that is up will do.  The pinned ISP wins whenever it is up, and
+    // drained ISPs are never used.
+    fn best_isp(&self, group: &[usize]) -> Option<usize> {
+        let isps = &self.inner.isps;
+        let usable = |i: &usize| !isps[*i].drained && isps[*i].is_up(group);
+        let held = |i: &usize| isps[*i].is_held(group) || self.overridden(*i);
+        let active = |i: &usize| {
+            group
+                .iter()
+                .any(|r| self.inner.routes[*r].active_isp == Some(*i))
+        };
+
+        self.inner
+            .pinned
+            .filter(usable)
+            .or_else(|| (0..isps.len()).find(|i| usable(i) && (active(i) || !held(i))))
+            .or_else(|| (0..isps.len()).find(usable))
+    }
+
+    // Test if ISP 'i' is held off by a manual failover to a less
+    // preferred one
+    fn overridden(&self, i: usize) -> bool {
+        let now = self.backend.now();
+        self.inner
+            .manual_failover
+            .is_some_and(|(to, then)| i < to && now.duration_since(then) < self.failback_hold)
+    }
+
+    // Test if moving route 'r' to ISP 'i' is a failback that has to
+    // wait for the active ISP to dwell long enough
+    fn dwelling(&self, i: usize, r: usize) -> bool {
+        let route = &self.inner.routes[r];
+        let active = match route.active_isp {
+            Some(active) if route.failed_over && route.good && i < active => {
+                &self.inner.isps[active]
+            }
+            _ => return false,
+        };
+        let now = self.backend.now();
+        active.stacks[r]
+            .became_active
+            .is_some_and(|t| now.duration_since(t) < self.min_dwell)
+    }
+
+    // Point the default route 'r' at ISP 'i'
+    fn switch_to(&mut self, i: usize, r: usize) {
+        let gateway = match &self.inner.isps[i].stacks[r].gateway {
+            Some(gateway) => gateway.clone(),
+            None => return,
+        };
+        let name = &self.inner.isps[i].name;
+        let route = &self.inner.routes[r];
+
+        let transition = match route.active_isp {
+            Some(active) => format!(
+                "{} default route switched from {} to {}",
+                route.family, self.inner.isps[active].name, name
+            ),
+            None => format!("{} default route set to {}", route.family, name),
+        };
         info!(
-            "good_host: {}, bad_host: {}, gw_good: {}",
-            good_host, bad_host, self.inner.gw_good
+            "{} via {} on {}",
+            transition, gateway.next_hop, gateway.device
         );
 
+        if let Err(e) = self.backend.replace_default_gateway(&gateway) {
+            error!(
+                "Unable to switch {} default route to {}: {}",
+                route.family, name, e
+            );
+            return;
+        }
+
+        for isp in self.inner.isps.iter_mut() {
+            isp.stacks[r].became_active = None;
+        }
+        let now = self.backend.now();
+        self.inner.isps[i].stacks[r].became_active = Some(now);
+        self.inner.last_transition = Some((now, transition));
+        let route = &mut self.inner.routes[r];
+        match route.active_isp {
+            Some(active) if i > active => route.failovers += 1,
+            Some(_) if route.failed_over => route.failbacks += 1,
+            _ => {}
+        }
+        route.failed_over = route.active_isp.is_some_and(|active| i > active);
+        route.active_isp = Some(i);
+        route.gateway = Some(gateway);
+    }
+
+    // Run the commands sent over the control socket
+    fn handle_commands(&mut self) {
+        let requests = match &self.control {
+            Some(control) => control.requests(),
+            None => return,
+        };
+        for request in requests {
+            // the client was told it timed out
+            if !request.take() {
+                debug!("Dropped control command {}, it timed out", request.command);
+                continue;
+            }
+            let reply = self.command(&request.command).map_err(|e| match e {
+                AppError::Control(e) => e,
+                e => e.to_string(),
+            });
+            request.reply(reply);
+        }
+    }
+
+    // Index of the ISP called 'name'
+    fn isp_index(&self, name: &str) -> Result<usize, AppError> {
+        self.inner
+            .isps
+            .iter()
+            .position(|isp| isp.name == name)
+            .ok_or_else(|| AppError::Control(format!("No ISP named {}", name)))
+    }
+
+    /// Run a control command.  Returns the text for the operator.
+    pub fn command(&mut self, command: &Command) -> Result<String, AppError> {
+        let refuse = |reason: String| Err(AppError::Control(reason));
+
+        match command {
+            Command::Status => Ok(self.status()),
+            Command::Failover(name) => {
+                let i = self.isp_index(name)?;
+                let all: Vec<usize> = (0..self.inner.routes.len()).collect();
+                if self.inner.isps[i].drained {
+                    return refuse(format!("ISP {} is drained", name));
+                }
+                if !self.inner.isps[i].is_up(&all) {
+                    return refuse(format!("ISP {} is not up", name));
+                }
+                if let Some(pinned) = self.inner.pinned.filter(|pinned| *pinned != i) {
+                    let pinned = &self.inner.isps[pinned].name;
+                    return refuse(format!("ISP {} is pinned, unpin it first", pinned));
+                }
+
+                // The more preferred ISPs are held off, so the routes
+                // fail back once the hold time is over
+                self.inner.manual_failover = Some((i, self.backend.now()));
+                for r in all {
+                    if self.inner.routes[r].active_isp != Some(i) {
+                        self.switch_to(i, r);
+                    }
+                }
+
+                let switched = self.inner.routes.iter().enumerate().all(|(r, route)| {
+                    route.active_isp == Some(i) || self.inner.isps[i].stacks[r].gateway.is_none()
+                });
+                match switched {
+                    true => Ok(format!("Failed over to {}\n", name)),
+                    false => refuse(format!("Unable to fail over to {}, see the log", name)),
+                }
+            }
+            Command::Pin(name) => {
+                let i = self.isp_index(name)?;
+                if self.inner.isps[i].drained {
+                    return refuse(format!("ISP {} is drained", name));
+                }
+                self.inner.pinned = Some(i);
+                Ok(format!("Pinned {}\n", name))
+            }
+            Command::Unpin => match self.inner.pinned.take() {
+                Some(i) => Ok(format!("Unpinned {}\n", self.inner.isps[i].name)),
+                None => Ok("No ISP was pinned\n".to_owned()),
+            },
+            Command::Drain(name) => {
+                let i = self.isp_index(name)?;
+                self.inner.isps[i].drained = true;
+                if self.inner.pinned == Some(i) {
+                    self.inner.pinned = None;
+                }
+                if self.inner.manual_failover.is_some_and(|(to, _)| to == i) {
+                    self.inner.manual_failover = None;
+                }
+                Ok(format!("Draining {}\n", name))
+            }
+            Command::Undrain(name) => {
+                let i = self.isp_index(name)?;
+                self.inner.isps[i].drained = false;
+                Ok(format!("Undrained {}\n", name))
+            }
+            Command::Discover => {
+                self.inner.state = ServiceState::Discovery;
+                Ok("Running discovery\n".to_owned())
+            }
+        }
+    }
+
+    // Routes, ISP health and the last transition, for the operator
+    fn status(&self) -> String {
+        let now = self.backend.now();
+        let isps = &self.inner.isps;
+        let mut text = String::new();
+
+        let _ = writeln!(text, "State: {}", self.inner.state.name());
+        for route in &self.inner.routes {
+            let isp = route.active_isp.map_or("none", |i| &isps[i].name);
+            let via = match &route.gateway {
+                Some(gateway) => format!("via {} on {}", gateway.next_hop, gateway.device),
+                None => "no default route".to_owned(),
+            };
+            let internet = if route.good {
+                "reachable"
+            } else {
+                "unreachable"
+            };
+            let _ = writeln!(
+                text,
+                "{} route: ISP {}, {}, internet {}, {} failovers, {} failbacks",
+                route.family, isp, via, internet, route.failovers, route.failbacks
+            );
+        }
+        let pinned = self.inner.pinned.map_or("none", |i| &isps[i].name);
+        let _ = writeln!(text, "Pinned: {}", pinned);
+        match self.inner.manual_failover {
+            Some((i, then)) => {
+                let secs = secs_since(now, then);
+                let _ = writeln!(text, "Manual failover: to {}, {}s ago", isps[i].name, secs);
+            }
+            None => {
+                let _ = writeln!(text, "Manual failover: none");
+            }
+        }
+        match &self.inner.last_transition {
+            Some((then, transition)) => {
+                let secs = secs_since(now, *then);
+                let _ = writeln!(text, "Last transition: {}, {}s ago", transition, secs);
+            }
+            None => {
+                let _ = writeln!(text, "Last transition: none");
+            }
+        }
+
+        for (i, isp) in isps.iter().enumerate() {
+            let mut flags = vec![if isp.link { "link up" } else { "link down" }];
+            if isp.drained {
+                flags.push("drained");
+            }
+            if self.inner.pinned == Some(i) {
+                flags.push("pinned");
+            }
+            let _ = writeln!(
+                text,
+                "ISP {} on {}: {}",
+                isp.name,
+                isp.interface,
+                flags.join(", ")
+            );
+
+            for (route, stack) in self.inner.routes.iter().zip(&isp.stacks) {
+                let gateway = match &stack.gateway {
+                    Some(gateway) => gateway,
+                    None => {
+                        let _ = writeln!(text, "  {}: no gateway", route.family);
+                        continue;
+                    }
+                };
+                let health = &stack.health;
+                let mut state = vec![match (health.is_up(), stack.changed) {
+                    (up, Some(then)) => {
+                        let up = if up { "up" } else { "down" };
+                        format!("{} for {}s", up, secs_since(now, then))
+                    }
+                    (_, None) => "down".to_owned(),
+                }];
+                state.push(format!("loss {:.0}%", health.loss() * 100.0));
+                if let Some(rtt) = health.rtt() {
+                    state.push(format!("rtt {:.1} ms", rtt.as_secs_f64() * 1000.0));
+                }
+                state.push(format!("penalty {:.0}", health.penalty()));
+                if health.is_damped() {
+                    state.push("damped".to_owned());
+                }
+                if stack.became_bad.is_some() || self.overridden(i) {
+                    state.push("held".to_owned());
+                }
+                let _ = writeln!(
+                    text,
+                    "  {} via {}: {}",
+                    route.family,
+                    gateway.next_hop,
+                    state.join(", ")
+                );
+            }
+        }
+
+        text
+    }
+
+    fn handle_discovery(&mut self) -> Result<bool, AppError> {
+        info!("Discovery");
+
+        // names may have moved since they were last looked up
+        self.inner.hosts.clear();
+        self.resolve_hosts();
+        self.refresh()?;
+        self.probe_isps();
+
+        for route in &self.inner.routes {
+            info!(
+                "Active {} ISP: {}, good: {}",
+                route.family,
+                route
+                    .active_isp
+                    .map_or("none", |i| &self.inner.isps[i].name),
+                route.good
+            );
+        }
+
         self.inner.state = ServiceState::Monitor;
         Ok(false)
     }
 
     fn handle_monitor(&mut self) -> Result<bool, AppError> {
-        info!("Monitor");
+        debug!("Monitor");
 
-        if self.inner.count > 2 {
-            Ok(true)
-        } else {
-            Ok(false)
+        let now = self.backend.now();
+        if self.inner.resolve_retry.is_some_and(|t| now >= t) {
+            self.resolve_hosts();
         }
+        self.refresh()?;
+        self.probe_isps();
+
+        if let Some((i, then)) = self.inner.manual_failover {
+            if now.duration_since(then) >= self.failback_hold {
+                info!("Manual failover to {} is over", self.inner.isps[i].name);
+                self.inner.manual_failover = None;
+            }
+        }
+
+        for group in self.groups() {
+            let best = match self.best_isp(&group) {
+                Some(i) => i,
+                None => {
+                    if group.iter().any(|r| !self.inner.routes[*r].good) {
+                        warn!("No ISP available to fail over to");
+                    }
+                    continue;
+                }
+            };
+
+            for r in group {
+                let route = &self.inner.routes[r];
+                if route.active_isp == Some(best)
+                    || self.inner.isps[best].stacks[r].gateway.is_none()
+                {
+                    continue;
+                }
+                if self.inner.pinned != Some(best) && self.dwelling(best, r) {
+                    debug!(
+                        "Not failing {} back to {} yet",
+                        route.family, self.inner.isps[best].name
+                    );
+                    continue;
+                }
+                self.switch_to(best, r);
+            }
+        }
+
+        Ok(false)
     }
 
     fn handle_state(&mut self) -> Result<bool, AppError> {
@@ -181,3 +1232,526 @@ impl Service {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn config(failback_hold_ms: u64) -> IspgwdConfig {
+        let isp = |name: &str, interface: &str, priority| IspConfig {
+            name: name.to_owned(),
+            interface: interface.to_owned(),
+            priority,
+            probes: Vec::new(),
+            gateway: None,
+            gateway6: None,
+        };
+        IspgwdConfig {
+            isp_configs: vec![isp("backup", "eth1", 20), isp("primary", "eth0", 10)],
+            poll_duration_ms: 1000,
+            failback_hold_ms,
+            // every round counts, as in the scenarios below
+            health: HealthConfig {
+                window: 1,
+                min_dwell_ms: 0,
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    fn service_with(scenario: &str, config: IspgwdConfig) -> Result<Service, AppError> {
+        let scenario: Scenario = serde_yaml::from_str(scenario).unwrap();
+        let backend = SimBackend::new(scenario, Duration::from_secs(1)).unwrap();
+        Service::with_backend(config, Box::new(backend))
+    }
+
+    fn service(scenario: &str, failback_hold_ms: u64) -> Service {
+        service_with(scenario, config(failback_hold_ms)).unwrap()
+    }
+
+    fn active(service: &Service) -> Option<&str> {
+        active_route(service, 0)
+    }
+
+    fn active_route(service: &Service, r: usize) -> Option<&str> {
+        service.inner.routes[r]
+            .gateway
+            .as_ref()
+            .map(|gw| gw.device.as_str())
+    }
+
+    const TWO_ISPS: &str = "
+interfaces:
+  eth0:
+    gateway: 10.0.0.1
+  eth1:
+    gateway: 10.0.1.1
+default_interface: eth1
+";
+
+    #[test]
+    fn failover_and_failback() {
+        let scenario = format!(
+            "{}
+events:
+  - at_ms: 3000
+    interface: eth0
+    internet: false
+  - at_ms: 5000
+    interface: eth0
+    internet: true
+    gateway: 10.0.0.7
+",
+            TWO_ISPS
+        );
+        let mut service = service(&scenario, 3000);
+
+        // discovery only looks, monitor moves to the preferred ISP
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+        assert_eq!(service.inner.routes[0].active_isp, Some(0));
+
+        // primary loses internet access
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        assert!(service.inner.isps[0].stacks[0].became_bad.is_some());
+
+        // and is not tried again within the hold time, even though it
+        // recovers at 5000 ms
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+
+        // after the hold time it is, with its new gateway
+        service.step().unwrap();
+        assert_eq!(
+            service.inner.routes[0]
+                .gateway
+                .as_ref()
+                .unwrap()
+                .next_hop
+                .to_string(),
+            "10.0.0.7"
+        );
+        service.step().unwrap();
+        assert!(service.inner.routes[0].good);
+        assert!(service.inner.isps[0].stacks[0].became_bad.is_none());
+
+        // moving to the preferred ISP at startup is neither
+        let metrics = service.metrics().into_text();
+        for line in [
+            "ispgwd_failovers_total{family=\"IPv4\"} 1\n",
+            "ispgwd_failbacks_total{family=\"IPv4\"} 1\n",
+            "ispgwd_isp_active{isp=\"primary\",family=\"IPv4\"} 1\n",
+            "ispgwd_isp_active{isp=\"backup\",family=\"IPv4\"} 0\n",
+            "ispgwd_isp_up{isp=\"primary\",family=\"IPv4\"} 1\n",
+            "ispgwd_isp_loss_ratio{isp=\"backup\",family=\"IPv4\"} 0\n",
+            "ispgwd_state{state=\"monitor\"} 1\n",
+            "ispgwd_steps_total 7\n",
+        ] {
+            assert!(metrics.contains(line), "{} not in {}", line, metrics);
+        }
+    }
+
+    #[test]
+    fn link_down() {
+        let scenario = format!(
+            "{}
+events:
+  - at_ms: 3000
+    interface: eth0
+    link: false
+  - at_ms: 4000
+    interface: eth1
+    internet: false
+duration_ms: 5000
+",
+            TWO_ISPS
+        );
+        let mut service = service(&scenario, 0);
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        assert!(!service.inner.isps[0].link);
+
+        // with nowhere to go the bad ISP stays active
+        assert!(!service.step().unwrap());
+        assert_eq!(active(&service), Some("eth1"));
+        assert!(!service.inner.routes[0].good);
+
+        // until the scenario ends
+        assert!(service.step().unwrap());
+    }
+
+    #[test]
+    fn missing_interface() {
+        // the primary is brought up late, and goes away later
+        let scenario = "
+interfaces:
+  eth0:
+    gateway: 10.0.0.1
+    present: false
+  eth1:
+    gateway: 10.0.1.1
+default_interface: eth1
+events:
+  - at_ms: 2000
+    interface: eth0
+    present: true
+  - at_ms: 5000
+    interface: eth0
+    present: false
+";
+        let mut service = service(scenario, 0);
+        assert!(!service.inner.isps[0].link);
+
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        service.step().unwrap();
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // the default route can not be read while it goes out of the
+        // missing interface, the service carries on without it
+        service.step().unwrap();
+        assert!(!service.inner.isps[0].link);
+        assert_eq!(active(&service), Some("eth1"));
+    }
+
+    #[test]
+    fn standby_without_route() {
+        // neither ISP has a route of its own beyond the gateway
+        let scenario = "
+interfaces:
+  eth0:
+    gateway: 10.0.0.1
+    routed: false
+  eth1:
+    gateway: 10.0.1.1
+    routed: false
+default_interface: eth1
+events:
+  - at_ms: 4000
+    interface: eth0
+    gateway_reachable: false
+";
+        let mut service = service(scenario, 0);
+
+        // the preferred standby is judged by its gateway
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // and so is the ISP it left
+        service.step().unwrap();
+        assert!(service.inner.routes[0].good);
+        assert!(service.inner.isps[1].stacks[0].health.is_up());
+
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+    }
+
+    #[test]
+    fn marginal_link() {
+        let mut config = config(0);
+        config.health = HealthConfig {
+            window: 3,
+            min_dwell_ms: 3000,
+            flap_penalty: 3000.0,
+            half_life_ms: 3000,
+            ..Default::default()
+        };
+        let scenario = format!(
+            "{}
+events:
+  - at_ms: 3000
+    interface: eth0
+    internet: false
+  - at_ms: 4000
+    interface: eth0
+    internet: true
+  - at_ms: 5000
+    interface: eth0
+    internet: false
+  - at_ms: 7000
+    interface: eth0
+    internet: true
+",
+            TWO_ISPS
+        );
+        let mut service = service_with(&scenario, config).unwrap();
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // a single lost round is ridden out
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // two out of three are not, and one flap is enough to damp eth0
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        assert!(service.inner.isps[0].stacks[0].health.is_damped());
+
+        // eth0 is up again after three clean rounds at 9000 ms, past the
+        // dwell time, but stays damped until the penalty decays to under
+        // 750 after two half lives
+        for _ in 6..=11 {
+            service.step().unwrap();
+            assert_eq!(active(&service), Some("eth1"));
+        }
+        assert!(service.inner.isps[0].stacks[0].health.is_up());
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+        assert!(!service.inner.isps[0].stacks[0].health.is_damped());
+    }
+
+    #[test]
+    fn min_dwell() {
+        let mut config = config(0);
+        config.health.min_dwell_ms = 3000;
+        let scenario = format!(
+            "{}
+events:
+  - at_ms: 3000
+    interface: eth0
+    internet: false
+  - at_ms: 4000
+    interface: eth0
+    internet: true
+",
+            TWO_ISPS
+        );
+        let mut service = service_with(&scenario, config).unwrap();
+        service.step().unwrap();
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+
+        // eth0 is back at 4000 ms, failing back waits until eth1 has been
+        // active for 3000 ms
+        service.step().unwrap();
+        service.step().unwrap();
+        assert!(service.inner.isps[0].stacks[0].health.is_up());
+        assert_eq!(active(&service), Some("eth1"));
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+    }
+
+    #[test]
+    fn control() {
+        let mut service = service(TWO_ISPS, 3000);
+        let command = |service: &mut Service, line: &str| service.command(&line.parse().unwrap());
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // draining moves off the ISP for good, until undrained
+        command(&mut service, "drain primary").unwrap();
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        assert!(command(&mut service, "pin primary").is_err());
+        assert!(command(&mut service, "failover primary").is_err());
+        command(&mut service, "undrain primary").unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // a pinned ISP is used whenever it is up
+        command(&mut service, "pin backup").unwrap();
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        assert_eq!(command(&mut service, "unpin").unwrap(), "Unpinned backup\n");
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // a forced failover switches right away and fails back after
+        // the hold time
+        command(&mut service, "failover backup").unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        // without the primary counting as failed
+        assert!(service.inner.isps[0].stacks[0].became_bad.is_none());
+        assert!(command(&mut service, "status")
+            .unwrap()
+            .contains("Manual failover: to backup, 2s ago\n"));
+        service.step().unwrap();
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+        service.step().unwrap();
+
+        let status = command(&mut service, "status").unwrap();
+        for line in [
+            "State: monitor\n",
+            "IPv4 route: ISP primary, via 10.0.0.1 on eth0, internet reachable, \
+             3 failovers, 3 failbacks\n",
+            "Pinned: none\n",
+            "Manual failover: none\n",
+            "Last transition: IPv4 default route switched from backup to primary, 2s ago\n",
+            "ISP backup on eth1: link up\n",
+            "  IPv4 via 10.0.1.1: up for 12s, loss 0%, rtt 10.0 ms, penalty 0\n",
+        ] {
+            assert!(status.contains(line), "{:?} not in {}", line, status);
+        }
+
+        assert!(matches!(
+            command(&mut service, "drain nobody"),
+            Err(AppError::Control(_))
+        ));
+        command(&mut service, "discover").unwrap();
+        service.step().unwrap();
+        assert!(matches!(service.inner.state, ServiceState::Monitor));
+    }
+
+    #[test]
+    fn weighted_probes() {
+        let mut config = config(0);
+        config.probes = serde_yaml::from_str(
+            "
+- type: tcp
+  target: example.com:443
+- type: http
+  url: http://example.com/
+  weight: 2
+",
+        )
+        .unwrap();
+        config.isp_configs[1].probes = serde_yaml::from_str(
+            "
+- type: dns
+  server: 10.0.0.53
+  name: example.com
+",
+        )
+        .unwrap();
+        let scenario = format!(
+            "{}
+events:
+  - at_ms: 3000
+    interface: eth0
+    failing_probes: [tcp example.com:443]
+  - at_ms: 4000
+    interface: eth0
+    failing_probes: [tcp example.com:443, dns example.com at 10.0.0.53]
+",
+            TWO_ISPS
+        );
+        let mut service = service_with(&scenario, config.clone()).unwrap();
+        assert_eq!(service.inner.isps[0].stacks[0].probes.len(), 3);
+        assert_eq!(service.inner.isps[1].stacks[0].probes.len(), 2);
+
+        // host names are looked up once, in discovery
+        service.step().unwrap();
+        assert_eq!(
+            service.inner.hosts[&("example.com".to_owned(), Family::Ipv4)].to_string(),
+            "192.0.2.1"
+        );
+        assert!(service.inner.resolve_retry.is_none());
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // 3 of 4 weight still up
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth0"));
+
+        // 2 of 4 is not a majority
+        service.step().unwrap();
+        assert_eq!(active(&service), Some("eth1"));
+        assert!(!service.inner.isps[0].stacks[0].health.is_up());
+
+        config.probes[0].kind = ProbeKind::Http {
+            url: "https://example.com/".to_owned(),
+            status: 200,
+        };
+        assert!(matches!(
+            service_with(TWO_ISPS, config.clone()),
+            Err(AppError::Config(_))
+        ));
+
+        config.probes.clear();
+        config.isp_configs[1].probes[0].weight = 0;
+        assert!(matches!(
+            service_with(TWO_ISPS, config.clone()),
+            Err(AppError::Config(_))
+        ));
+
+        config.isp_configs[1].probes.clear();
+        config.isp_configs[1].interface = "eth1".to_owned();
+        assert!(matches!(
+            service_with(TWO_ISPS, config),
+            Err(AppError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn dual_stack() {
+        let scenario = "
+interfaces:
+  eth0:
+    gateway: 10.0.0.1
+    gateway6: fe80::1
+  eth1:
+    gateway: 10.0.1.1
+    gateway6: fe80::2
+default_interface: eth1
+events:
+  - at_ms: 3000
+    interface: eth0
+    internet6: false
+";
+        for mode in [FailoverMode::Together, FailoverMode::Independent] {
+            let mut config = config(0);
+            config.ipv6 = true;
+            config.failover = mode;
+            let mut service = service_with(scenario, config).unwrap();
+            assert_eq!(service.inner.isps[0].stacks[1].probes.len(), 3);
+
+            service.step().unwrap();
+            service.step().unwrap();
+            assert_eq!(active_route(&service, 0), Some("eth0"));
+            assert_eq!(active_route(&service, 1), Some("eth0"));
+
+            // eth0 loses IPv6 only, IPv4 follows it unless independent
+            service.step().unwrap();
+            let expected = match mode {
+                FailoverMode::Together => Some("eth1"),
+                FailoverMode::Independent => Some("eth0"),
+            };
+            assert_eq!(active_route(&service, 0), expected, "{:?}", mode);
+            assert_eq!(active_route(&service, 1), Some("eth1"));
+            service.step().unwrap();
+            assert!(service.inner.routes.iter().all(|route| route.good));
+        }
+    }
+
+    #[test]
+    fn example_scenario() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let text = std::fs::read_to_string(dir.join("config/simulation.yaml")).unwrap();
+        let mut config: IspgwdConfig = serde_yaml::from_str(&text).unwrap();
+        config.scenario_file = config
+            .scenario_file
+            .map(|path| dir.join("config").join(path));
+
+        let mut service = Service::new(config).unwrap();
+        let mut steps = 0;
+        while !service.step().unwrap() {
+            steps += 1;
+        }
+        assert_eq!(steps, 24);
+        assert_eq!(active(&service), Some("eth0"));
+    }
+}
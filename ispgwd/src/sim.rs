@@ -0,0 +1,494 @@
+//! Simulation backend
+//!
+//! `SimBackend` plays a scripted scenario instead of touching the host:
+//! a set of interfaces with their link state, gateway and reachability,
+//! and events changing them over time.  Time is simulated too, each
+//! service step advances the clock by a fixed amount, so a scenario
+//! always plays out the same way.
+//!
+//! Interfaces may have an IPv6 gateway too, with its own internet
+//! reachability.  The gateway itself answers in both families or none.
+//!
+//! An interface that is not present, like a PPP or LTE interface before
+//! it is brought up, fails every lookup, and so does reading the default
+//! routes while one of them goes out of it.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde_derive::Deserialize;
+
+use crate::backend::NetBackend;
+use crate::net::Gateway;
+use crate::prelude::*;
+use crate::probe::{Probe, ProbeResult};
+
+// Echo requests per simulated probe
+const PROBE_COUNT: u32 = 10;
+
+fn yes() -> bool {
+    true
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+/// A simulated interface
+pub struct SimInterface {
+    #[serde(default = "yes")]
+    /// Interface exists
+    pub present: bool,
+
+    #[serde(default = "yes")]
+    /// Link is up
+    pub link: bool,
+
+    #[serde(default)]
+    /// Gateway handed out to the interface
+    pub gateway: Option<Ipv4Addr>,
+
+    #[serde(default = "yes")]
+    /// Gateway answers pings
+    pub gateway_reachable: bool,
+
+    #[serde(default = "yes")]
+    /// The internet is reachable through the gateway
+    pub internet: bool,
+
+    #[serde(default)]
+    /// IPv6 gateway of the interface
+    pub gateway6: Option<Ipv6Addr>,
+
+    #[serde(default = "yes")]
+    /// The internet is reachable through the IPv6 gateway
+    pub internet6: bool,
+
+    #[serde(default = "default_rtt_ms")]
+    /// Round trip time of probes
+    pub rtt_ms: u64,
+
+    #[serde(default)]
+    /// Fraction of probe requests lost, 0-1
+    pub loss: f64,
+
+    #[serde(default)]
+    /// Probes that fail even with internet access, e.g. `tcp
+    /// example.com:443`, as printed by `ProbeKind`
+    pub failing_probes: Vec<String>,
+
+    #[serde(default = "yes")]
+    /// The host has a route to the internet out of the interface, e.g.
+    /// the higher metric default route of a DHCP client.  Without one
+    /// only the gateway can be probed, unless the interface carries the
+    /// default route.
+    pub routed: bool,
+}
+
+fn default_rtt_ms() -> u64 {
+    10
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+/// A change to a simulated interface.  Unset fields are left as they
+/// are.
+pub struct SimEvent {
+    /// Time of the event from the start of the scenario
+    pub at_ms: u64,
+
+    /// Interface changed
+    pub interface: String,
+
+    #[serde(default)]
+    /// Interface added or removed
+    pub present: Option<bool>,
+
+    #[serde(default)]
+    /// New link state
+    pub link: Option<bool>,
+
+    #[serde(default)]
+    /// New gateway
+    pub gateway: Option<Ipv4Addr>,
+
+    #[serde(default)]
+    /// New gateway reachability
+    pub gateway_reachable: Option<bool>,
+
+    #[serde(default)]
+    /// New internet reachability
+    pub internet: Option<bool>,
+
+    #[serde(default)]
+    /// New IPv6 gateway
+    pub gateway6: Option<Ipv6Addr>,
+
+    #[serde(default)]
+    /// New IPv6 internet reachability
+    pub internet6: Option<bool>,
+
+    #[serde(default)]
+    /// New round trip time
+    pub rtt_ms: Option<u64>,
+
+    #[serde(default)]
+    /// New loss
+    pub loss: Option<f64>,
+
+    #[serde(default)]
+    /// New failing probes
+    pub failing_probes: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+/// A simulation scenario
+pub struct Scenario {
+    #[serde(default)]
+    /// Interfaces by name
+    pub interfaces: BTreeMap<String, SimInterface>,
+
+    #[serde(default)]
+    /// Interface whose gateways are the default routes at the start
+    pub default_interface: Option<String>,
+
+    #[serde(default)]
+    /// Events, played in time order, events at the same time in the
+    /// order given
+    pub events: Vec<SimEvent>,
+
+    #[serde(default)]
+    /// Stop the service after this long, run forever if unset
+    pub duration_ms: Option<u64>,
+}
+
+impl Scenario {
+    /// Read a scenario from a YAML file
+    pub fn from_file(path: &Path) -> Result<Scenario, AppError> {
+        let text = fs::read_to_string(path)?;
+        serde_yaml::from_str(&text)
+            .map_err(|e| AppError::Scenario(format!("{}: {}", path.display(), e)))
+    }
+}
+
+/// Backend playing a `Scenario`
+#[derive(Debug, Clone)]
+pub struct SimBackend {
+    scenario: Scenario,
+
+    // Clock, advanced by 'step' on every tick
+    start: Instant,
+    elapsed: Duration,
+    step: Duration,
+
+    // Index of the next event to apply
+    next_event: usize,
+
+    default_gws: BTreeMap<Family, Gateway>,
+}
+
+impl SimBackend {
+    /// Create a backend playing `scenario`, advancing the clock by
+    /// `step` on every service step
+    pub fn new(mut scenario: Scenario, step: Duration) -> Result<SimBackend, AppError> {
+        for name in scenario
+            .events
+            .iter()
+            .map(|e| &e.interface)
+            .chain(&scenario.default_interface)
+        {
+            if !scenario.interfaces.contains_key(name) {
+                return Err(AppError::InterfaceNotFound(name.to_owned()));
+            }
+        }
+        // a stable sort keeps events at the same time in order
+        scenario.events.sort_by_key(|e| e.at_ms);
+
+        let mut default_gws = BTreeMap::new();
+        if let Some(name) = &scenario.default_interface {
+            for family in [Family::Ipv4, Family::Ipv6] {
+                if let Some(gateway) = gateway(name, &scenario.interfaces[name], family) {
+                    default_gws.insert(family, gateway);
+                }
+            }
+        }
+
+        let mut backend = SimBackend {
+            scenario,
+            start: Instant::now(),
+            elapsed: Duration::ZERO,
+            step,
+            next_event: 0,
+            default_gws,
+        };
+        backend.apply_events();
+
+        Ok(backend)
+    }
+
+    fn interface(&self, name: &str) -> Result<&SimInterface, AppError> {
+        self.scenario
+            .interfaces
+            .get(name)
+            .filter(|intf| intf.present)
+            .ok_or_else(|| AppError::InterfaceNotFound(name.to_owned()))
+    }
+
+    // Apply the events due by now
+    fn apply_events(&mut self) {
+        while let Some(event) = self.scenario.events.get(self.next_event) {
+            if Duration::from_millis(event.at_ms) > self.elapsed {
+                break;
+            }
+            self.next_event += 1;
+
+            info!("Simulation at {} ms: {:?}", event.at_ms, event);
+            let intf = self
+                .scenario
+                .interfaces
+                .get_mut(&event.interface)
+                .expect("event interfaces are checked in new");
+            if let Some(present) = event.present {
+                intf.present = present;
+            }
+            if let Some(link) = event.link {
+                intf.link = link;
+            }
+            if let Some(gateway) = event.gateway {
+                intf.gateway = Some(gateway);
+            }
+            if let Some(reachable) = event.gateway_reachable {
+                intf.gateway_reachable = reachable;
+            }
+            if let Some(internet) = event.internet {
+                intf.internet = internet;
+            }
+            if let Some(gateway6) = event.gateway6 {
+                intf.gateway6 = Some(gateway6);
+            }
+            if let Some(internet6) = event.internet6 {
+                intf.internet6 = internet6;
+            }
+            if let Some(rtt_ms) = event.rtt_ms {
+                intf.rtt_ms = rtt_ms;
+            }
+            if let Some(loss) = event.loss {
+                intf.loss = loss;
+            }
+            if let Some(failing) = &event.failing_probes {
+                intf.failing_probes = failing.clone();
+            }
+        }
+    }
+}
+
+fn gateway(name: &str, intf: &SimInterface, family: Family) -> Option<Gateway> {
+    let next_hop = match family {
+        Family::Ipv4 => IpAddr::from(intf.gateway?),
+        Family::Ipv6 => IpAddr::from(intf.gateway6?),
+    };
+    Some(Gateway {
+        device: name.to_owned(),
+        next_hop,
+    })
+}
+
+impl NetBackend for SimBackend {
+    fn link_up(&mut self, interface: &str) -> Result<bool, AppError> {
+        Ok(self.interface(interface)?.link)
+    }
+
+    fn default_gateway(&mut self, family: Family) -> Result<Option<Gateway>, AppError> {
+        match self.default_gws.get(&family) {
+            Some(gateway) => {
+                self.interface(&gateway.device)?;
+                Ok(Some(gateway.clone()))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn isp_gateway(
+        &mut self,
+        interface: &str,
+        family: Family,
+    ) -> Result<Option<Gateway>, AppError> {
+        Ok(gateway(interface, self.interface(interface)?, family))
+    }
+
+    fn replace_default_gateway(&mut self, gateway: &Gateway) -> Result<(), AppError> {
+        if !self.interface(&gateway.device)?.link {
+            return Err(AppError::CommandFailed(format!(
+                "{} link is down",
+                gateway.device
+            )));
+        }
+
+        info!(
+            "Simulation: {} default route via {} on {}",
+            gateway.family(),
+            gateway.next_hop,
+            gateway.device
+        );
+        self.default_gws.insert(gateway.family(), gateway.clone());
+        Ok(())
+    }
+
+    // Every name resolves, to a documentation address
+    fn resolve(&mut self, _host: &str, family: Family) -> Result<Option<IpAddr>, AppError> {
+        Ok(Some(match family {
+            Family::Ipv4 => Ipv4Addr::new(192, 0, 2, 1).into(),
+            Family::Ipv6 => Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1).into(),
+        }))
+    }
+
+    fn probe(&mut self, probes: &[Probe]) -> Vec<Result<ProbeResult, AppError>> {
+        probes
+            .iter()
+            .map(|probe| {
+                let intf = self.interface(&probe.interface)?;
+                let (next_hop, internet) = match probe.family {
+                    Family::Ipv4 => (intf.gateway.map(IpAddr::from), intf.internet),
+                    Family::Ipv6 => (intf.gateway6.map(IpAddr::from), intf.internet6),
+                };
+                let to_gateway = matches!(probe.kind,
+                    ProbeKind::Icmp { target } if next_hop == Some(target));
+                let default_route = self
+                    .default_gws
+                    .get(&probe.family)
+                    .is_some_and(|gw| gw.device == probe.interface);
+                if !to_gateway && !intf.routed && !default_route {
+                    return Err(AppError::NoRoute(probe.interface.clone()));
+                }
+                let failing = intf.failing_probes.contains(&probe.kind.to_string());
+                let up = intf.link
+                    && next_hop.is_some()
+                    && intf.gateway_reachable
+                    && (to_gateway || (internet && !failing));
+
+                let received = match up {
+                    true => (PROBE_COUNT as f64 * (1.0 - intf.loss)).round() as u32,
+                    false => 0,
+                };
+                Ok(ProbeResult {
+                    sent: PROBE_COUNT,
+                    received,
+                    rtt: (received > 0).then(|| Duration::from_millis(intf.rtt_ms)),
+                })
+            })
+            .collect()
+    }
+
+    fn tick(&mut self) {
+        self.elapsed += self.step;
+        self.apply_events();
+    }
+
+    fn now(&self) -> Instant {
+        self.start + self.elapsed
+    }
+
+    fn finished(&self) -> bool {
+        self.scenario
+            .duration_ms
+            .is_some_and(|ms| self.elapsed >= Duration::from_millis(ms))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn up(sim: &mut SimBackend, target: &str) -> bool {
+        let target: IpAddr = target.parse().unwrap();
+        let probe = Probe {
+            interface: "eth0".to_owned(),
+            kind: ProbeKind::Icmp { target },
+            family: Family::of(&target),
+            addr: None,
+            timeout: Duration::from_secs(1),
+        };
+        sim.probe(&[probe]).remove(0).unwrap().is_up()
+    }
+
+    #[test]
+    fn play_scenario() {
+        let scenario: Scenario = serde_yaml::from_str(
+            "
+interfaces:
+  eth0:
+    gateway: 10.0.0.1
+    gateway6: fe80::1
+default_interface: eth0
+events:
+  - at_ms: 1000
+    interface: eth0
+    internet6: false
+  - at_ms: 2000
+    interface: eth0
+    internet: false
+  - at_ms: 3000
+    interface: eth0
+    link: false
+duration_ms: 3000
+",
+        )
+        .unwrap();
+        let mut sim = SimBackend::new(scenario, Duration::from_secs(1)).unwrap();
+        let start = sim.now();
+
+        assert!(up(&mut sim, "8.8.8.8"));
+        assert!(up(&mut sim, "2001:4860:4860::8888"));
+        assert_eq!(
+            sim.default_gateway(Family::Ipv6).unwrap().unwrap().next_hop,
+            "fe80::1".parse::<IpAddr>().unwrap()
+        );
+        sim.tick();
+        assert!(up(&mut sim, "8.8.8.8"));
+        assert!(!up(&mut sim, "2001:4860:4860::8888"));
+        assert!(up(&mut sim, "fe80::1"));
+        sim.tick();
+        assert!(!up(&mut sim, "8.8.8.8"));
+        assert!(up(&mut sim, "10.0.0.1"));
+        assert!(!sim.finished());
+
+        sim.tick();
+        assert!(!sim.link_up("eth0").unwrap());
+        assert!(!up(&mut sim, "10.0.0.1"));
+        assert!(sim.finished());
+        assert_eq!(sim.now() - start, Duration::from_secs(3));
+
+        assert!(sim.link_up("eth9").is_err());
+    }
+
+    #[test]
+    fn unordered_events() {
+        let scenario: Scenario = serde_yaml::from_str(
+            "
+interfaces:
+  eth0:
+    gateway: 10.0.0.1
+events:
+  - at_ms: 2000
+    interface: eth0
+    internet: true
+  - at_ms: 1000
+    interface: eth0
+    internet: false
+  - at_ms: 2000
+    interface: eth0
+    link: false
+",
+        )
+        .unwrap();
+        let mut sim = SimBackend::new(scenario, Duration::from_secs(1)).unwrap();
+
+        sim.tick();
+        assert!(!up(&mut sim, "8.8.8.8"));
+        sim.tick();
+        assert!(!sim.link_up("eth0").unwrap());
+        assert!(sim.interface("eth0").unwrap().internet);
+    }
+}
@@ -0,0 +1,24 @@
+use std::env;
+use std::path::PathBuf;
+
+fn main() {
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    let config = cbindgen::Config::from_file(crate_dir.join("cbindgen.toml")).unwrap();
+
+    // Generate into OUT_DIR, the committed include/sudoku.h is refreshed
+    // with the cbindgen command line and checked by the tests
+    let include_dir = PathBuf::from(env::var("OUT_DIR").unwrap()).join("include");
+    cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("Unable to generate C header")
+        .write_to_file(include_dir.join("sudoku.h"));
+
+    println!(
+        "cargo:rustc-env=SUDOKU_CAPI_INCLUDE_DIR={}",
+        include_dir.display()
+    );
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+}
@@ -0,0 +1,115 @@
+/* Generated by cbindgen from sudoku-capi, do not edit. */
+
+#ifndef SUDOKU_H
+#define SUDOKU_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+// Result of a sudoku API call
+typedef enum SudokuStatus {
+  // Success
+  SUDOKU_STATUS_OK = 0,
+  // A required pointer argument was NULL
+  SUDOKU_STATUS_NULL_POINTER,
+  // The puzzle text could not be parsed
+  SUDOKU_STATUS_PARSE_ERROR,
+  // The puzzle breaks the sudoku rules
+  SUDOKU_STATUS_INCONSISTENT,
+  // The puzzle has no solution
+  SUDOKU_STATUS_UNSOLVABLE,
+  // The puzzle has more than one solution
+  SUDOKU_STATUS_MULTIPLE_SOLUTIONS,
+  // A solver limit was reached first
+  SUDOKU_STATUS_LIMIT_EXCEEDED,
+  // A row, column or value was out of range
+  SUDOKU_STATUS_OUT_OF_RANGE,
+  // An internal error, the puzzle should not be used further
+  SUDOKU_STATUS_PANIC,
+} SudokuStatus;
+
+// Opaque puzzle handle
+typedef struct SudokuPuzzle SudokuPuzzle;
+
+// Bounds on the work done by `sudoku_puzzle_solve`.  Zero means
+// unlimited.
+typedef struct SudokuLimits {
+  // Maximum wall-clock time in milliseconds
+  uint64_t timeout_ms;
+  // Maximum number of guesses
+  uint64_t max_nodes;
+  // Maximum number of logic solver iterations
+  uint64_t max_iterations;
+} SudokuLimits;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+// Message describing the last failed call on this thread, or an empty
+// string.  The pointer is valid until the next call on this thread.
+const char *sudoku_last_error(void);
+
+// Create a puzzle from text, either the nine line grid format or a
+// single line of 81 characters with `.`, `0` or `X` for blanks.
+//
+// # Safety
+//
+// `text` must be a NUL terminated string and `out` must point to
+// writable storage for the handle.  On success the handle must be
+// released with `sudoku_puzzle_free`.
+enum SudokuStatus sudoku_puzzle_new(const char *text, struct SudokuPuzzle **out);
+
+// Solve a puzzle in place.  `limits` may be NULL for no limits and
+// `iterations`, if not NULL, receives the logic solver iterations.  A
+// puzzle that fails to solve is left unchanged.
+//
+// # Safety
+//
+// `puzzle` must be a handle from `sudoku_puzzle_new`.  `limits` and
+// `iterations` must be NULL or valid pointers.
+enum SudokuStatus sudoku_puzzle_solve(struct SudokuPuzzle *puzzle,
+                                      const struct SudokuLimits *limits,
+                                      uint64_t *iterations);
+
+// Read the value of a cell, 1-9, or 0 when empty.  `row` and `col` are
+// 0-based.
+//
+// # Safety
+//
+// `puzzle` must be a handle from `sudoku_puzzle_new` and `value` a
+// valid pointer.
+enum SudokuStatus sudoku_puzzle_cell(const struct SudokuPuzzle *puzzle,
+                                     uint8_t row,
+                                     uint8_t col,
+                                     uint8_t *value);
+
+// Read the possible values of a cell as a bit mask, bit `v - 1` set for
+// each possible value `v`.  A resolved cell has only its value set.
+// `row` and `col` are 0-based.
+//
+// # Safety
+//
+// `puzzle` must be a handle from `sudoku_puzzle_new` and `mask` a
+// valid pointer.
+enum SudokuStatus sudoku_puzzle_candidates(const struct SudokuPuzzle *puzzle,
+                                           uint8_t row,
+                                           uint8_t col,
+                                           uint16_t *mask);
+
+// Release a puzzle.  NULL is ignored.
+//
+// # Safety
+//
+// `puzzle` must be NULL or a handle from `sudoku_puzzle_new` not
+// already released.
+void sudoku_puzzle_free(struct SudokuPuzzle *puzzle);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* SUDOKU_H */
@@ -0,0 +1,401 @@
+//! C ABI bindings for the sudoku solver
+//!
+//! A puzzle is an opaque `SudokuPuzzle` handle, created by
+//! `sudoku_puzzle_new` and released by `sudoku_puzzle_free`.  Functions
+//! return a `SudokuStatus`; on failure `sudoku_last_error` describes the
+//! problem.  The header `include/sudoku.h` is generated from this file
+//! by the build script.
+
+#![warn(missing_docs)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::time::Duration;
+
+use sudoku::prelude::*;
+
+const GRID_SIZE: u8 = 9;
+
+/// Result of a sudoku API call
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SudokuStatus {
+    /// Success
+    Ok = 0,
+
+    /// A required pointer argument was NULL
+    NullPointer,
+
+    /// The puzzle text could not be parsed
+    ParseError,
+
+    /// The puzzle breaks the sudoku rules
+    Inconsistent,
+
+    /// The puzzle has no solution
+    Unsolvable,
+
+    /// The puzzle has more than one solution
+    MultipleSolutions,
+
+    /// A solver limit was reached first
+    LimitExceeded,
+
+    /// A row, column or value was out of range
+    OutOfRange,
+
+    /// An internal error, the puzzle should not be used further
+    Panic,
+}
+
+/// Bounds on the work done by `sudoku_puzzle_solve`.  Zero means
+/// unlimited.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SudokuLimits {
+    /// Maximum wall-clock time in milliseconds
+    pub timeout_ms: u64,
+
+    /// Maximum number of guesses
+    pub max_nodes: u64,
+
+    /// Maximum number of logic solver iterations
+    pub max_iterations: u64,
+}
+
+impl From<&SudokuLimits> for SolveLimits {
+    fn from(limits: &SudokuLimits) -> Self {
+        let nonzero = |v: u64| (v != 0).then_some(v);
+        // Limits past usize::MAX can not be reached anyway
+        let count = |n: u64| usize::try_from(n).unwrap_or(usize::MAX);
+        SolveLimits {
+            timeout: nonzero(limits.timeout_ms).map(Duration::from_millis),
+            max_nodes: nonzero(limits.max_nodes).map(count),
+            max_iterations: nonzero(limits.max_iterations).map(count),
+            cancel: None,
+        }
+    }
+}
+
+/// Opaque puzzle handle
+pub struct SudokuPuzzle(Puzzle);
+
+thread_local! {
+    static LAST_ERROR: RefCell<CString> = RefCell::new(CString::default());
+}
+
+fn set_last_error(message: &str) {
+    let message = CString::new(message.replace('\0', " ")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = message);
+}
+
+// A failed call: the status to return and the message to record
+struct Failure(SudokuStatus, String);
+
+impl From<SudokuError> for Failure {
+    fn from(e: SudokuError) -> Self {
+        let status = match e {
+            SudokuError::PuzzleStateInconsistent(..) => SudokuStatus::Inconsistent,
+            SudokuError::PuzzleUnsolved | SudokuError::PuzzleUnsolvable => SudokuStatus::Unsolvable,
+            SudokuError::MultipleSolutions => SudokuStatus::MultipleSolutions,
+            SudokuError::InvalidValue { .. } => SudokuStatus::OutOfRange,
+            SudokuError::LimitExceeded { .. } => SudokuStatus::LimitExceeded,
+        };
+        Failure(status, e.to_string())
+    }
+}
+
+fn null_pointer(name: &str) -> Failure {
+    Failure(SudokuStatus::NullPointer, format!("{} is NULL", name))
+}
+
+// Run an API call, recording the error message and catching panics so
+// they do not unwind into C
+fn call(f: impl FnOnce() -> Result<(), Failure>) -> SudokuStatus {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => {
+            set_last_error("");
+            SudokuStatus::Ok
+        }
+        Ok(Err(Failure(status, message))) => {
+            set_last_error(&message);
+            status
+        }
+        Err(_) => {
+            set_last_error("Internal error in the sudoku library");
+            SudokuStatus::Panic
+        }
+    }
+}
+
+fn check_cell(row: u8, col: u8) -> Result<(usize, usize), Failure> {
+    if row >= GRID_SIZE || col >= GRID_SIZE {
+        return Err(Failure(
+            SudokuStatus::OutOfRange,
+            format!("Cell out of range: row: {}, col: {}", row, col),
+        ));
+    }
+    Ok((row as usize, col as usize))
+}
+
+/// Message describing the last failed call on this thread, or an empty
+/// string.  The pointer is valid until the next call on this thread.
+#[no_mangle]
+pub extern "C" fn sudoku_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ptr())
+}
+
+/// Create a puzzle from text, either the nine line grid format or a
+/// single line of 81 characters with `.`, `0` or `X` for blanks.
+///
+/// # Safety
+///
+/// `text` must be a NUL terminated string and `out` must point to
+/// writable storage for the handle.  On success the handle must be
+/// released with `sudoku_puzzle_free`.
+#[no_mangle]
+pub unsafe extern "C" fn sudoku_puzzle_new(
+    text: *const c_char,
+    out: *mut *mut SudokuPuzzle,
+) -> SudokuStatus {
+    call(|| {
+        if text.is_null() {
+            return Err(null_pointer("text"));
+        }
+        if out.is_null() {
+            return Err(null_pointer("out"));
+        }
+        *out = ptr::null_mut();
+
+        let text = CStr::from_ptr(text)
+            .to_str()
+            .map_err(|e| Failure(SudokuStatus::ParseError, e.to_string()))?;
+        let puzzle = text
+            .parse::<Puzzle>()
+            .map_err(|e| match e.downcast_ref::<SudokuError>() {
+                Some(SudokuError::PuzzleStateInconsistent(..)) => {
+                    Failure(SudokuStatus::Inconsistent, format!("{:#}", e))
+                }
+                _ => Failure(SudokuStatus::ParseError, format!("{:#}", e)),
+            })?;
+
+        *out = Box::into_raw(Box::new(SudokuPuzzle(puzzle)));
+        Ok(())
+    })
+}
+
+/// Solve a puzzle in place.  `limits` may be NULL for no limits and
+/// `iterations`, if not NULL, receives the logic solver iterations.  A
+/// puzzle that fails to solve is left unchanged.
+///
+/// # Safety
+///
+/// `puzzle` must be a handle from `sudoku_puzzle_new`.  `limits` and
+/// `iterations` must be NULL or valid pointers.
+#[no_mangle]
+pub unsafe extern "C" fn sudoku_puzzle_solve(
+    puzzle: *mut SudokuPuzzle,
+    limits: *const SudokuLimits,
+    iterations: *mut u64,
+) -> SudokuStatus {
+    call(|| {
+        let puzzle = puzzle.as_mut().ok_or_else(|| null_pointer("puzzle"))?;
+        let limits = limits.as_ref().map(SolveLimits::from).unwrap_or_default();
+
+        let mut solution = puzzle.0.clone();
+        let result = solution.solve_with_limits(&limits);
+        let iters = match &result {
+            Ok(iters) | Err((_, iters)) => *iters,
+        };
+        if let Some(iterations) = iterations.as_mut() {
+            *iterations = iters as u64;
+        }
+
+        result.map_err(|(e, _)| Failure::from(e))?;
+        puzzle.0 = solution;
+        Ok(())
+    })
+}
+
+/// Read the value of a cell, 1-9, or 0 when empty.  `row` and `col` are
+/// 0-based.
+///
+/// # Safety
+///
+/// `puzzle` must be a handle from `sudoku_puzzle_new` and `value` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn sudoku_puzzle_cell(
+    puzzle: *const SudokuPuzzle,
+    row: u8,
+    col: u8,
+    value: *mut u8,
+) -> SudokuStatus {
+    call(|| {
+        let puzzle = puzzle.as_ref().ok_or_else(|| null_pointer("puzzle"))?;
+        let value = value.as_mut().ok_or_else(|| null_pointer("value"))?;
+        let (r, c) = check_cell(row, col)?;
+
+        *value = puzzle.0.values()[r][c] as u8;
+        Ok(())
+    })
+}
+
+/// Read the possible values of a cell as a bit mask, bit `v - 1` set for
+/// each possible value `v`.  A resolved cell has only its value set.
+/// `row` and `col` are 0-based.
+///
+/// # Safety
+///
+/// `puzzle` must be a handle from `sudoku_puzzle_new` and `mask` a
+/// valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn sudoku_puzzle_candidates(
+    puzzle: *const SudokuPuzzle,
+    row: u8,
+    col: u8,
+    mask: *mut u16,
+) -> SudokuStatus {
+    call(|| {
+        let puzzle = puzzle.as_ref().ok_or_else(|| null_pointer("puzzle"))?;
+        let mask = mask.as_mut().ok_or_else(|| null_pointer("mask"))?;
+        let (r, c) = check_cell(row, col)?;
+
+        *mask = puzzle.0.possible_mask(r, c);
+        Ok(())
+    })
+}
+
+/// Release a puzzle.  NULL is ignored.
+///
+/// # Safety
+///
+/// `puzzle` must be NULL or a handle from `sudoku_puzzle_new` not
+/// already released.
+#[no_mangle]
+pub unsafe extern "C" fn sudoku_puzzle_free(puzzle: *mut SudokuPuzzle) {
+    if !puzzle.is_null() {
+        drop(Box::from_raw(puzzle));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &CStr =
+        c"8...4629.7....9..5..2..5....6.21.84..27.8.53..38.67.2....4..6..9..3......4165...3";
+
+    // Needs trials of the values of cells to solve
+    const HARD: &CStr =
+        c"2....8.1.5.6..........4.37...5..3..882.....634..1..5...84.1..........6.9.6.5....1";
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(sudoku_last_error()) }
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    #[test]
+    fn solve_puzzle() {
+        unsafe {
+            let mut puzzle = ptr::null_mut();
+            let status = sudoku_puzzle_new(PUZZLE.as_ptr(), &mut puzzle);
+            assert_eq!(status, SudokuStatus::Ok);
+
+            let mut value = 0;
+            assert_eq!(
+                sudoku_puzzle_cell(puzzle, 0, 0, &mut value),
+                SudokuStatus::Ok
+            );
+            assert_eq!(value, 8);
+            assert_eq!(
+                sudoku_puzzle_cell(puzzle, 0, 1, &mut value),
+                SudokuStatus::Ok
+            );
+            assert_eq!(value, 0);
+
+            let mut mask = 0;
+            assert_eq!(
+                sudoku_puzzle_candidates(puzzle, 0, 0, &mut mask),
+                SudokuStatus::Ok
+            );
+            assert_eq!(mask, 1 << 7);
+            assert_eq!(
+                sudoku_puzzle_candidates(puzzle, 0, 1, &mut mask),
+                SudokuStatus::Ok
+            );
+            assert!(mask.count_ones() > 1);
+
+            assert_eq!(
+                sudoku_puzzle_cell(puzzle, 9, 0, &mut value),
+                SudokuStatus::OutOfRange
+            );
+            assert!(last_error().contains("out of range"));
+
+            let status = sudoku_puzzle_solve(puzzle, ptr::null(), ptr::null_mut());
+            assert_eq!(status, SudokuStatus::Ok);
+            assert!(last_error().is_empty());
+
+            for r in 0..GRID_SIZE {
+                for c in 0..GRID_SIZE {
+                    assert_eq!(
+                        sudoku_puzzle_cell(puzzle, r, c, &mut value),
+                        SudokuStatus::Ok
+                    );
+                    assert!((1..=9).contains(&value));
+                }
+            }
+
+            sudoku_puzzle_free(puzzle);
+        }
+    }
+
+    #[test]
+    fn errors() {
+        unsafe {
+            let mut puzzle = ptr::null_mut();
+            let status = sudoku_puzzle_new(c"12".as_ptr(), &mut puzzle);
+            assert_eq!(status, SudokuStatus::ParseError);
+            assert!(puzzle.is_null());
+            assert!(!last_error().is_empty());
+
+            let text = CString::new(format!("11{}", ".".repeat(79))).unwrap();
+            let status = sudoku_puzzle_new(text.as_ptr(), &mut puzzle);
+            assert_eq!(status, SudokuStatus::Inconsistent);
+
+            let status = sudoku_puzzle_new(ptr::null(), &mut puzzle);
+            assert_eq!(status, SudokuStatus::NullPointer);
+
+            let status = sudoku_puzzle_solve(ptr::null_mut(), ptr::null(), ptr::null_mut());
+            assert_eq!(status, SudokuStatus::NullPointer);
+
+            // a puzzle over the limits is left as it was
+            let status = sudoku_puzzle_new(HARD.as_ptr(), &mut puzzle);
+            assert_eq!(status, SudokuStatus::Ok);
+            let before = (*puzzle).0.clone();
+            let limits = SudokuLimits {
+                max_nodes: 1,
+                ..Default::default()
+            };
+            let status = sudoku_puzzle_solve(puzzle, &limits, ptr::null_mut());
+            assert_eq!(status, SudokuStatus::LimitExceeded);
+            assert_eq!((*puzzle).0.to_line(), before.to_line());
+            for (r, c) in (0..9).flat_map(|r| (0..9).map(move |c| (r, c))) {
+                assert_eq!((*puzzle).0.possible_mask(r, c), before.possible_mask(r, c));
+            }
+            sudoku_puzzle_free(puzzle);
+
+            let limits = SudokuLimits {
+                max_nodes: u64::MAX,
+                ..Default::default()
+            };
+            assert_eq!(SolveLimits::from(&limits).max_nodes, Some(usize::MAX));
+
+            sudoku_puzzle_free(ptr::null_mut());
+        }
+    }
+}
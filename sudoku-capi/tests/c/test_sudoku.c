@@ -0,0 +1,106 @@
+/*
+ * Exercise the sudoku C API: parse, read cells and candidates, solve
+ * with limits, and report errors.  Exits non-zero on the first failure.
+ */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "sudoku.h"
+
+#define CHECK(cond)                                                     \
+    do {                                                                \
+        if (!(cond)) {                                                  \
+            fprintf(stderr, "%s:%d: check failed: %s (last error: %s)\n", \
+                    __FILE__, __LINE__, #cond, sudoku_last_error());    \
+            return 1;                                                   \
+        }                                                               \
+    } while (0)
+
+static const char *PUZZLE =
+    "8 X X   X 4 6   2 9 X\n"
+    "7 X X   X X 9   X X 5\n"
+    "X X 2   X X 5   X X X\n"
+    "X 6 X   2 1 X   8 4 X\n"
+    "X 2 7   X 8 X   5 3 X\n"
+    "X 3 8   X 6 7   X 2 X\n"
+    "X X X   4 X X   6 X X\n"
+    "9 X X   3 X X   X X X\n"
+    "X 4 1   6 5 X   X X 3\n";
+
+/* needs trials of the values of cells to solve */
+static const char *HARD =
+    "2....8.1.5.6..........4.37...5..3..882.....634..1..5...84.1..........6.9.6.5....1";
+
+static int test_solve(void)
+{
+    SudokuPuzzle *puzzle = NULL;
+    uint8_t value;
+    uint16_t mask;
+    uint64_t iterations = 0;
+    SudokuLimits limits = { .timeout_ms = 10000 };
+
+    CHECK(sudoku_puzzle_new(PUZZLE, &puzzle) == SUDOKU_STATUS_OK);
+    CHECK(puzzle != NULL);
+
+    CHECK(sudoku_puzzle_cell(puzzle, 0, 0, &value) == SUDOKU_STATUS_OK);
+    CHECK(value == 8);
+    CHECK(sudoku_puzzle_cell(puzzle, 0, 1, &value) == SUDOKU_STATUS_OK);
+    CHECK(value == 0);
+
+    CHECK(sudoku_puzzle_candidates(puzzle, 0, 0, &mask) == SUDOKU_STATUS_OK);
+    CHECK(mask == 1 << 7);
+    CHECK(sudoku_puzzle_candidates(puzzle, 0, 1, &mask) == SUDOKU_STATUS_OK);
+    CHECK((mask & (1 << 7)) == 0);
+
+    CHECK(sudoku_puzzle_solve(puzzle, &limits, &iterations) == SUDOKU_STATUS_OK);
+    CHECK(strlen(sudoku_last_error()) == 0);
+
+    for (uint8_t r = 0; r < 9; r++) {
+        uint16_t seen = 0;
+        for (uint8_t c = 0; c < 9; c++) {
+            CHECK(sudoku_puzzle_cell(puzzle, r, c, &value) == SUDOKU_STATUS_OK);
+            CHECK(value >= 1 && value <= 9);
+            seen |= 1 << (value - 1);
+        }
+        CHECK(seen == 0x1ff);
+    }
+    printf("solved in %llu iterations\n", (unsigned long long)iterations);
+
+    sudoku_puzzle_free(puzzle);
+    return 0;
+}
+
+static int test_errors(void)
+{
+    SudokuPuzzle *puzzle = NULL;
+    uint8_t value;
+    SudokuLimits limits = { .max_nodes = 1 };
+
+    CHECK(sudoku_puzzle_new("not a puzzle", &puzzle) == SUDOKU_STATUS_PARSE_ERROR);
+    CHECK(puzzle == NULL);
+    CHECK(strlen(sudoku_last_error()) > 0);
+
+    CHECK(sudoku_puzzle_new(NULL, &puzzle) == SUDOKU_STATUS_NULL_POINTER);
+
+    /* the puzzle needs more trials than allowed, and is left unsolved */
+    CHECK(sudoku_puzzle_new(HARD, &puzzle) == SUDOKU_STATUS_OK);
+    CHECK(sudoku_puzzle_cell(puzzle, 9, 0, &value) == SUDOKU_STATUS_OUT_OF_RANGE);
+    CHECK(sudoku_puzzle_solve(puzzle, &limits, NULL) == SUDOKU_STATUS_LIMIT_EXCEEDED);
+    CHECK(sudoku_puzzle_cell(puzzle, 0, 1, &value) == SUDOKU_STATUS_OK);
+    CHECK(value == 0);
+    CHECK(sudoku_puzzle_solve(puzzle, NULL, NULL) == SUDOKU_STATUS_OK);
+
+    sudoku_puzzle_free(puzzle);
+    sudoku_puzzle_free(NULL);
+    return 0;
+}
+
+int main(void)
+{
+    if (test_solve() || test_errors())
+        return 1;
+
+    printf("all tests passed\n");
+    return 0;
+}
@@ -0,0 +1,55 @@
+//! Build and run the C test program against the shared library
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[test]
+fn c_program() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+    // The shared library is built into the deps directory holding this
+    // test, cargo only copies it to the directory above on a build
+    let exe = env::current_exe().unwrap();
+    let lib_dir = exe.parent().unwrap();
+    let program = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("test_sudoku");
+
+    let cc = env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let status = Command::new(cc)
+        .arg("-std=c99")
+        .arg("-Wall")
+        .arg("-Werror")
+        .arg("-I")
+        .arg(env!("SUDOKU_CAPI_INCLUDE_DIR"))
+        .arg(crate_dir.join("tests/c/test_sudoku.c"))
+        .arg("-o")
+        .arg(&program)
+        .arg("-L")
+        .arg(lib_dir)
+        .arg("-lsudoku_capi")
+        .status()
+        .expect("Failed to run the C compiler");
+    assert!(status.success(), "C test program failed to build");
+
+    let output = Command::new(&program)
+        .env("LD_LIBRARY_PATH", lib_dir)
+        .env("DYLD_LIBRARY_PATH", lib_dir)
+        .output()
+        .unwrap();
+    print!("{}", String::from_utf8_lossy(&output.stdout));
+    eprint!("{}", String::from_utf8_lossy(&output.stderr));
+    assert!(output.status.success(), "C test program failed");
+}
+
+#[test]
+fn header_is_current() {
+    let crate_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let generated = PathBuf::from(env!("SUDOKU_CAPI_INCLUDE_DIR")).join("sudoku.h");
+
+    assert_eq!(
+        fs::read_to_string(crate_dir.join("include/sudoku.h")).unwrap(),
+        fs::read_to_string(generated).unwrap(),
+        "include/sudoku.h is out of date, regenerate it with cbindgen"
+    );
+}
@@ -0,0 +1,183 @@
+//! Python bindings for the sudoku solver
+//!
+//! ```python
+//! import sudoku_py
+//!
+//! puzzle = sudoku_py.Puzzle("8...4629.7....9..5...")
+//! stats = puzzle.solve(timeout_ms=1000)
+//! print(puzzle, stats["iterations"], puzzle.difficulty())
+//! ```
+
+#![warn(missing_docs)]
+
+use std::time::Duration;
+
+use pyo3::create_exception;
+use pyo3::exceptions::{PyException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
+
+use sudoku::prelude::{Difficulty, Pass, SolveLimits, SolveStats, SudokuError};
+
+create_exception!(
+    sudoku_py,
+    SolveError,
+    PyException,
+    "The puzzle could not be solved"
+);
+
+create_exception!(
+    sudoku_py,
+    LimitExceeded,
+    SolveError,
+    "A solver limit was reached before the puzzle was solved"
+);
+
+fn solve_error(e: SudokuError) -> PyErr {
+    match e {
+        SudokuError::LimitExceeded { .. } => LimitExceeded::new_err(e.to_string()),
+        e => SolveError::new_err(e.to_string()),
+    }
+}
+
+fn stats_dict<'py>(py: Python<'py>, stats: &SolveStats) -> PyResult<Bound<'py, PyDict>> {
+    let passes = PyDict::new(py);
+    for pass in Pass::ALL {
+        let p = stats.pass(pass);
+        let entry = PyDict::new(py);
+        entry.set_item("calls", p.calls)?;
+        entry.set_item("elapsed", p.elapsed.as_secs_f64())?;
+        entry.set_item("eliminated", p.eliminated)?;
+        entry.set_item("resolved", p.resolved)?;
+        passes.set_item(pass.to_string(), entry)?;
+    }
+
+    let dict = PyDict::new(py);
+    dict.set_item("iterations", stats.iterations)?;
+    dict.set_item("nodes", stats.nodes)?;
+    dict.set_item("clones", stats.clones)?;
+    dict.set_item("max_depth", stats.max_depth)?;
+    dict.set_item("elapsed", stats.elapsed.as_secs_f64())?;
+    dict.set_item("passes", passes)?;
+    Ok(dict)
+}
+
+/// A sudoku puzzle
+#[pyclass(module = "sudoku_py", unsendable)]
+#[derive(Clone)]
+struct Puzzle(sudoku::prelude::Puzzle);
+
+#[pymethods]
+impl Puzzle {
+    /// Parse a puzzle from the nine line grid format or a single line
+    /// of 81 characters with `.`, `0` or `X` for blanks
+    #[new]
+    fn new(text: &str) -> PyResult<Self> {
+        text.parse()
+            .map(Puzzle)
+            .map_err(|e| PyValueError::new_err(format!("{:#}", e)))
+    }
+
+    /// Create a puzzle from nine rows of nine values, 1-9 or 0 for blanks
+    #[staticmethod]
+    fn from_values(values: [[usize; 9]; 9]) -> PyResult<Self> {
+        sudoku::prelude::Puzzle::from_values(&values)
+            .map(Puzzle)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// Nine rows of nine values, 1-9 or 0 for blanks
+    fn values(&self) -> [[usize; 9]; 9] {
+        self.0.values()
+    }
+
+    /// The puzzle as a single line of 81 characters, `.` for blanks
+    fn to_line(&self) -> String {
+        self.0.to_line()
+    }
+
+    /// A copy of the puzzle
+    fn copy(&self) -> Self {
+        self.clone()
+    }
+
+    /// Solve the puzzle in place, returning the solver statistics as a
+    /// dict.  Raises SolveError when unsolvable, or its subclass
+    /// LimitExceeded when a limit is reached.
+    /// Other Python threads run while the solver does.
+    #[pyo3(signature = (timeout_ms=None, max_nodes=None, max_iterations=None))]
+    fn solve<'py>(
+        &mut self,
+        py: Python<'py>,
+        timeout_ms: Option<u64>,
+        max_nodes: Option<usize>,
+        max_iterations: Option<usize>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let limits = SolveLimits {
+            timeout: timeout_ms.map(Duration::from_millis),
+            max_nodes,
+            max_iterations,
+            cancel: None,
+        };
+
+        let puzzle = &mut self.0;
+        let stats = py.detach(|| {
+            let mut stats = SolveStats::default();
+            puzzle
+                .solve_with_stats(&limits, &mut stats)
+                .map(|_| stats)
+                .map_err(|(e, _)| e)
+        });
+        stats_dict(py, &stats.map_err(solve_error)?)
+    }
+
+    /// Count the solutions, stopping once `limit` have been found
+    #[pyo3(signature = (limit=2))]
+    fn count_solutions(&self, py: Python<'_>, limit: usize) -> usize {
+        // the puzzle is not Sync, count on a copy
+        let puzzle = self.0.clone();
+        py.detach(move || puzzle.count_solutions(limit))
+    }
+
+    /// True if the puzzle has exactly one solution
+    fn has_unique_solution(&self) -> bool {
+        self.0.has_unique_solution()
+    }
+
+    /// Rough difficulty: "easy", "medium", "hard" or "expert"
+    fn difficulty(&self) -> &'static str {
+        match self.0.difficulty() {
+            Difficulty::Easy => "easy",
+            Difficulty::Medium => "medium",
+            Difficulty::Hard => "hard",
+            Difficulty::Expert => "expert",
+        }
+    }
+
+    /// Canonical form of the puzzle, equal for puzzles that differ only
+    /// by symmetry and relabeling
+    fn canonical_form(&self) -> Self {
+        Puzzle(self.0.canonical_form())
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Puzzle('{}')", self.0.to_line())
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self.0.values() == other.0.values()
+    }
+}
+
+/// Python module of the sudoku solver
+#[pymodule]
+fn sudoku_py(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<Puzzle>()?;
+    m.add("SolveError", m.py().get_type::<SolveError>())?;
+    m.add("LimitExceeded", m.py().get_type::<LimitExceeded>())?;
+    Ok(())
+}
@@ -0,0 +1,71 @@
+"""Tests of the sudoku_py module, run after `maturin develop`."""
+
+import unittest
+
+import sudoku_py
+
+PUZZLE = "8...4629.7....9..5..2..5....6.21.84..27.8.53..38.67.2....4..6..9..3......4165...3"
+
+HARD = """
+2 X X   X X 8   X 1 X
+5 X 6   X X X   X X X
+X X X   X 4 X   3 7 X
+X X 5   X X 3   X X 8
+8 2 X   X X X   X 6 3
+4 X X   1 X X   5 X X
+X 8 4   X 1 X   X X X
+X X X   X X X   6 X 9
+X 6 X   5 X X   X X 1
+"""
+
+
+class PuzzleTest(unittest.TestCase):
+    def test_parse(self):
+        puzzle = sudoku_py.Puzzle(PUZZLE)
+        self.assertEqual(puzzle.to_line(), PUZZLE)
+        self.assertEqual(puzzle.values()[0], [8, 0, 0, 0, 4, 6, 2, 9, 0])
+        self.assertEqual(sudoku_py.Puzzle.from_values(puzzle.values()), puzzle)
+        self.assertEqual(sudoku_py.Puzzle(HARD).values()[0][0], 2)
+
+        with self.assertRaises(ValueError):
+            sudoku_py.Puzzle("not a puzzle")
+        with self.assertRaises(ValueError):
+            sudoku_py.Puzzle("11" + "." * 79)
+
+    def test_solve(self):
+        puzzle = sudoku_py.Puzzle(HARD)
+        solved = puzzle.copy()
+        stats = solved.solve()
+
+        self.assertNotIn(".", solved.to_line())
+        self.assertIn(".", puzzle.to_line())
+        self.assertGreater(stats["iterations"], 0)
+        self.assertEqual(stats["max_depth"], 1)
+        self.assertGreater(stats["passes"]["row_scan"]["calls"], 0)
+
+        for row in solved.values():
+            self.assertEqual(sorted(row), list(range(1, 10)))
+
+    def test_solve_limits(self):
+        # solving needs trials of the values of cells
+        puzzle = sudoku_py.Puzzle(HARD)
+        with self.assertRaises(sudoku_py.LimitExceeded) as raised:
+            puzzle.copy().solve(max_nodes=1)
+        self.assertIsInstance(raised.exception, sudoku_py.SolveError)
+        self.assertIn("max nodes", str(raised.exception))
+
+        puzzle.solve()
+
+    def test_analysis(self):
+        puzzle = sudoku_py.Puzzle(HARD)
+        self.assertEqual(puzzle.count_solutions(), 1)
+        self.assertTrue(puzzle.has_unique_solution())
+        self.assertEqual(puzzle.difficulty(), "hard")
+        self.assertEqual(sudoku_py.Puzzle("." * 81).count_solutions(5), 5)
+        self.assertEqual(
+            puzzle.canonical_form(), sudoku_py.Puzzle(HARD).canonical_form()
+        )
+
+
+if __name__ == "__main__":
+    unittest.main()
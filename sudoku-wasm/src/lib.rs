@@ -0,0 +1,67 @@
+//! WebAssembly bindings for the sudoku solver
+//!
+//! Puzzles are passed as text, either the nine line grid format or a
+//! single line of 81 characters.  Grids are returned as 81 cell values
+//! in row major order, 1-9, or 0 for an empty cell.
+
+#![warn(missing_docs)]
+
+use wasm_bindgen::prelude::*;
+
+use sudoku::prelude::*;
+
+fn parse_puzzle(text: &str) -> Result<Puzzle, JsError> {
+    text.parse::<Puzzle>()
+        .map_err(|e| JsError::new(&format!("{:#}", e)))
+}
+
+fn flatten(puzzle: &Puzzle) -> Vec<u8> {
+    puzzle.values().iter().flatten().map(|v| *v as u8).collect()
+}
+
+/// A value for an empty cell.  `row` and `col` are 0-based, `val` is
+/// 1-9.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy)]
+pub struct Hint {
+    /// Row of the cell
+    pub row: u8,
+
+    /// Column of the cell
+    pub col: u8,
+
+    /// Value for the cell
+    pub val: u8,
+}
+
+/// Parse a puzzle, returning its cell values
+#[wasm_bindgen]
+pub fn parse(text: &str) -> Result<Vec<u8>, JsError> {
+    Ok(flatten(&parse_puzzle(text)?))
+}
+
+/// Solve a puzzle, returning the cell values of the solution
+#[wasm_bindgen]
+pub fn solve(text: &str) -> Result<Vec<u8>, JsError> {
+    let mut puzzle = parse_puzzle(text)?;
+    puzzle.solve().map_err(|(e, _)| JsError::from(e))?;
+    Ok(flatten(&puzzle))
+}
+
+/// Suggest a value for an empty cell, or undefined when the puzzle is
+/// complete or has no solution
+#[wasm_bindgen]
+pub fn hint(text: &str) -> Result<Option<Hint>, JsError> {
+    Ok(parse_puzzle(text)?.hint().map(|h| Hint {
+        row: h.row as u8,
+        col: h.col as u8,
+        val: h.val as u8,
+    }))
+}
+
+/// Check a puzzle has exactly one solution, throwing an error saying
+/// why not otherwise
+#[wasm_bindgen]
+pub fn validate(text: &str) -> Result<(), JsError> {
+    parse_puzzle(text)?.validate().map_err(JsError::from)
+}
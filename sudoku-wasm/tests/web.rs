@@ -0,0 +1,37 @@
+//! Headless browser tests, run with `wasm-pack test --headless --firefox`
+
+#![cfg(target_arch = "wasm32")]
+
+use wasm_bindgen_test::*;
+
+use sudoku_wasm::*;
+
+wasm_bindgen_test_configure!(run_in_browser);
+
+const PUZZLE: &str =
+    "8...4629.7....9..5..2..5....6.21.84..27.8.53..38.67.2....4..6..9..3......4165...3";
+
+#[wasm_bindgen_test]
+fn parse_puzzle() {
+    let values = parse(PUZZLE).unwrap();
+    assert_eq!(values.len(), 81);
+    assert_eq!(&values[..9], &[8, 0, 0, 0, 4, 6, 2, 9, 0]);
+
+    assert!(parse("not a puzzle").is_err());
+}
+
+#[wasm_bindgen_test]
+fn solve_puzzle() {
+    let solution = solve(PUZZLE).unwrap();
+    assert!(solution.iter().all(|v| (1..=9).contains(v)));
+
+    let hint = hint(PUZZLE).unwrap().unwrap();
+    let index = hint.row as usize * 9 + hint.col as usize;
+    assert_eq!(solution[index], hint.val);
+}
+
+#[wasm_bindgen_test]
+fn validate_puzzle() {
+    assert!(validate(PUZZLE).is_ok());
+    assert!(validate(&".".repeat(81)).is_err());
+}
@@ -0,0 +1,244 @@
+//! Puzzle canonicalization
+//!
+//! Two puzzles are equivalent when one can be turned into the other by
+//! relabeling digits, permuting bands, stacks, rows within a band and
+//! columns within a stack, and transposing.  Rotations and reflections
+//! are combinations of those.  The canonical form is the equivalent
+//! puzzle whose row-major cell values are lexicographically smallest,
+//! with blanks sorting before digits.
+
+use crate::element::GROUP_SIZE;
+use crate::puzzle::Puzzle;
+
+type Grid = [[usize; GROUP_SIZE]; GROUP_SIZE];
+
+// The six permutations of three things
+const PERMS_3: [[usize; 3]; 6] = [
+    [0, 1, 2],
+    [0, 2, 1],
+    [1, 0, 2],
+    [1, 2, 0],
+    [2, 0, 1],
+    [2, 1, 0],
+];
+
+// All 1296 orderings of the nine columns (or rows) that keep stacks
+// (or bands) together
+fn line_orders() -> Vec<[usize; GROUP_SIZE]> {
+    let mut orders = Vec::with_capacity(1296);
+
+    for stacks in PERMS_3 {
+        for p0 in PERMS_3 {
+            for p1 in PERMS_3 {
+                for p2 in PERMS_3 {
+                    let inner = [p0, p1, p2];
+                    let mut order = [0; GROUP_SIZE];
+                    for (i, stack) in stacks.into_iter().enumerate() {
+                        for j in 0..3 {
+                            order[(i * 3) + j] = (stack * 3) + inner[i][j];
+                        }
+                    }
+                    orders.push(order);
+                }
+            }
+        }
+    }
+
+    orders
+}
+
+fn transpose(grid: &Grid) -> Grid {
+    let mut result = [[0; GROUP_SIZE]; GROUP_SIZE];
+    for (r, row) in grid.iter().enumerate() {
+        for (c, val) in row.iter().enumerate() {
+            result[c][r] = *val;
+        }
+    }
+    result
+}
+
+// Relabel digits in order of first appearance and compare against
+// 'best' as we go, stopping as soon as the result is known to be
+// larger.  Returns the relabeled cells if they are not larger than
+// 'best'.
+fn relabel_cmp<I>(cells: I, best: &[usize]) -> Option<Vec<usize>>
+where
+    I: Iterator<Item = usize>,
+{
+    let mut labels = [0; GROUP_SIZE + 1];
+    let mut next_label = 1;
+    let mut result = Vec::with_capacity(best.len());
+    let mut equal = true;
+
+    for (i, val) in cells.enumerate() {
+        let label = if val == 0 {
+            0
+        } else {
+            if labels[val] == 0 {
+                labels[val] = next_label;
+                next_label += 1;
+            }
+            labels[val]
+        };
+
+        if equal && i < best.len() {
+            if label > best[i] {
+                return None;
+            }
+            equal = label == best[i];
+        }
+        result.push(label);
+    }
+
+    Some(result)
+}
+
+// Orderings of the remaining eight rows once 'first' is chosen as the
+// top row
+fn remaining_row_orders(first: usize) -> Vec<[usize; GROUP_SIZE]> {
+    let band = first / 3;
+    let band_rest: Vec<usize> = (0..3)
+        .map(|i| band * 3 + i)
+        .filter(|r| *r != first)
+        .collect();
+    let other_bands: Vec<usize> = (0..3).filter(|b| *b != band).collect();
+
+    let mut orders = Vec::with_capacity(144);
+    for (a, b) in [(0, 1), (1, 0)] {
+        for (x, y) in [(0, 1), (1, 0)] {
+            for px in PERMS_3 {
+                for py in PERMS_3 {
+                    let mut order = [first, band_rest[a], band_rest[b], 0, 0, 0, 0, 0, 0];
+                    for i in 0..3 {
+                        order[3 + i] = other_bands[x] * 3 + px[i];
+                        order[6 + i] = other_bands[y] * 3 + py[i];
+                    }
+                    orders.push(order);
+                }
+            }
+        }
+    }
+
+    orders
+}
+
+fn canonical_grid(grid: &Grid) -> Grid {
+    let col_orders = line_orders();
+    let grids = [*grid, transpose(grid)];
+
+    // The top row dominates the ordering, so first find every
+    // (transpose, top row, column order) giving the smallest top row.
+    let mut best_row = vec![GROUP_SIZE + 1; GROUP_SIZE];
+    let mut candidates = Vec::new();
+    for (t, g) in grids.iter().enumerate() {
+        for (r, row) in g.iter().enumerate() {
+            for (o, cols) in col_orders.iter().enumerate() {
+                if let Some(relabeled) = relabel_cmp(cols.iter().map(|c| row[*c]), &best_row) {
+                    if relabeled != best_row {
+                        best_row = relabeled;
+                        candidates.clear();
+                    }
+                    candidates.push((t, r, o));
+                }
+            }
+        }
+    }
+
+    // Then try every arrangement of the remaining rows
+    let mut best = vec![GROUP_SIZE + 1; GROUP_SIZE * GROUP_SIZE];
+    for (t, r, o) in candidates {
+        let g = &grids[t];
+        let cols = &col_orders[o];
+        for rows in remaining_row_orders(r) {
+            let cells = rows
+                .iter()
+                .flat_map(|row| cols.iter().map(move |col| g[*row][*col]));
+            if let Some(relabeled) = relabel_cmp(cells, &best) {
+                best = relabeled;
+            }
+        }
+    }
+
+    let mut result = [[0; GROUP_SIZE]; GROUP_SIZE];
+    for (i, val) in best.into_iter().enumerate() {
+        result[i / GROUP_SIZE][i % GROUP_SIZE] = val;
+    }
+    result
+}
+
+impl Puzzle {
+    /// Return the canonical representative of the puzzle's symmetry class.
+    ///
+    /// Only resolved cells are considered.  Two puzzles are equivalent
+    /// exactly when their canonical forms are equal.
+    pub fn canonical_form(&self) -> Puzzle {
+        let canonical = canonical_grid(&self.values());
+
+        // The transformations preserve consistency, so this can not fail
+        Puzzle::from_values(&canonical).expect("canonical form is consistent")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str = "8...4629.\
+                          7....9..5\
+                          ..2..5...\
+                          .6.21.84.\
+                          .27.8.53.\
+                          .38.67.2.\
+                          ...4..6..\
+                          9..3.....\
+                          .4165...3";
+
+    fn rotate(grid: &Grid) -> Grid {
+        let mut result = [[0; GROUP_SIZE]; GROUP_SIZE];
+        for (r, row) in grid.iter().enumerate() {
+            for (c, val) in row.iter().enumerate() {
+                result[c][GROUP_SIZE - 1 - r] = *val;
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn line_orders_are_unique() {
+        let mut orders = line_orders();
+        orders.sort();
+        orders.dedup();
+        assert_eq!(orders.len(), 1296);
+    }
+
+    #[test]
+    fn equivalent_puzzles() {
+        let puzzle: Puzzle = PUZZLE.parse().unwrap();
+        let grid = puzzle.values();
+
+        // rotate, swap the first two bands and relabel 1 <-> 9
+        let mut other = rotate(&grid);
+        other.swap(0, 3);
+        other.swap(1, 4);
+        other.swap(2, 5);
+        for val in other.iter_mut().flatten() {
+            *val = match *val {
+                1 => 9,
+                9 => 1,
+                v => v,
+            };
+        }
+        let other = Puzzle::from_values(&other).unwrap();
+
+        let canonical = puzzle.canonical_form();
+        assert_eq!(canonical.values(), other.canonical_form().values());
+        assert_eq!(canonical.values(), canonical.canonical_form().values());
+        assert_ne!(canonical.values(), grid);
+
+        // a different puzzle is not equivalent
+        let mut different = grid;
+        different[0][0] = 0;
+        let different = Puzzle::from_values(&different).unwrap();
+        assert_ne!(canonical.values(), different.canonical_form().values());
+    }
+}
@@ -0,0 +1,425 @@
+//! Short chain reasoning techniques
+//!
+//! These work on the candidate sets of the unresolved elements and only
+//! eliminate candidates, leaving it to the basic reductions to finalize
+//! any element reduced to a single possibility.
+
+use std::collections::HashMap;
+
+use crate::element::GROUP_SIZE;
+
+// (row, col), 0-based
+type Cell = (usize, usize);
+
+// Candidate bit masks, bit 'v' set when value 'v' (0-based) is possible.
+// Resolved elements have no candidates.
+pub(crate) type Candidates = [[u16; GROUP_SIZE]; GROUP_SIZE];
+
+/// Technique used for a deduction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Technique {
+    /// Pivot {x,y} seeing pincers {x,z} and {y,z}
+    XyWing,
+
+    /// Pivot {x,y,z} seeing pincers {x,z} and {y,z}
+    XyzWing,
+
+    /// Two {x,y} cells joined by a strong link on x
+    WWing,
+
+    /// Single digit coloring of conjugate pairs
+    Coloring,
+
+    /// Assuming the candidate leads to a contradiction
+    Nishio,
+
+    /// Every candidate of the first cell forces the value of the second
+    ForcingChain,
+}
+
+/// A candidate of an element.  `row` and `col` are 0-based, `val` is 1-9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Candidate {
+    /// Row of the element
+    pub row: usize,
+
+    /// Column of the element
+    pub col: usize,
+
+    /// Candidate value
+    pub val: usize,
+}
+
+/// Candidates eliminated by one application of a technique
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Deduction {
+    /// Technique applied
+    pub technique: Technique,
+
+    /// Cells forming the pattern, as 0-based (row, col)
+    pub cells: Vec<(usize, usize)>,
+
+    /// Candidates removed
+    pub eliminations: Vec<Candidate>,
+}
+
+fn sees(a: Cell, b: Cell) -> bool {
+    a != b && (a.0 == b.0 || a.1 == b.1 || (a.0 / 3 == b.0 / 3 && a.1 / 3 == b.1 / 3))
+}
+
+fn all_cells() -> impl Iterator<Item = Cell> {
+    (0..GROUP_SIZE * GROUP_SIZE).map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+}
+
+// Rows, columns and squares
+fn houses() -> [[Cell; GROUP_SIZE]; 3 * GROUP_SIZE] {
+    let mut houses = [[(0, 0); GROUP_SIZE]; 3 * GROUP_SIZE];
+    for (h, house) in houses.iter_mut().enumerate() {
+        let i = h / 3;
+        for (j, cell) in house.iter_mut().enumerate() {
+            *cell = match h % 3 {
+                0 => (i, j),
+                1 => (j, i),
+                _ => ((i / 3) * 3 + j / 3, (i % 3) * 3 + j % 3),
+            };
+        }
+    }
+    houses
+}
+
+fn cand(cands: &Candidates, cell: Cell) -> u16 {
+    cands[cell.0][cell.1]
+}
+
+// Remove 'mask' from every cell outside 'pattern' that sees all of 'targets'
+fn eliminate(cands: &Candidates, mask: u16, pattern: &[Cell], targets: &[Cell]) -> Vec<Candidate> {
+    let val = mask.trailing_zeros() as usize;
+    all_cells()
+        .filter(|cell| cand(cands, *cell) & mask != 0)
+        .filter(|cell| !pattern.contains(cell))
+        .filter(|cell| targets.iter().all(|t| sees(*cell, *t)))
+        .map(|(row, col)| Candidate {
+            row,
+            col,
+            val: val + 1,
+        })
+        .collect()
+}
+
+fn deduction(technique: Technique, cells: Vec<Cell>, eliminations: Vec<Candidate>) -> Deduction {
+    Deduction {
+        technique,
+        cells,
+        eliminations,
+    }
+}
+
+fn cells_with_count(cands: &Candidates, count: u32) -> Vec<Cell> {
+    all_cells()
+        .filter(|cell| cand(cands, *cell).count_ones() == count)
+        .collect()
+}
+
+pub(crate) fn xy_wing(cands: &Candidates) -> Vec<Deduction> {
+    let mut result = Vec::new();
+    let bivalue = cells_with_count(cands, 2);
+
+    for &pivot in &bivalue {
+        let pm = cand(cands, pivot);
+        for &a in bivalue.iter().filter(|a| sees(pivot, **a)) {
+            let am = cand(cands, a);
+            let x = am & pm;
+            let z = am & !pm;
+            if x.count_ones() != 1 || z.count_ones() != 1 {
+                continue;
+            }
+            for &b in bivalue.iter().filter(|b| **b > a && sees(pivot, **b)) {
+                let bm = cand(cands, b);
+                if bm & pm != pm & !x || bm & !pm != z {
+                    continue;
+                }
+                let eliminations = eliminate(cands, z, &[pivot, a, b], &[a, b]);
+                if !eliminations.is_empty() {
+                    result.push(deduction(
+                        Technique::XyWing,
+                        vec![pivot, a, b],
+                        eliminations,
+                    ));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+pub(crate) fn xyz_wing(cands: &Candidates) -> Vec<Deduction> {
+    let mut result = Vec::new();
+    let bivalue = cells_with_count(cands, 2);
+
+    for pivot in cells_with_count(cands, 3) {
+        let pm = cand(cands, pivot);
+        let pincers: Vec<Cell> = bivalue
+            .iter()
+            .copied()
+            .filter(|p| sees(pivot, *p) && cand(cands, *p) & !pm == 0)
+            .collect();
+        for &a in &pincers {
+            for &b in pincers.iter().filter(|b| **b > a) {
+                let (am, bm) = (cand(cands, a), cand(cands, b));
+                let z = am & bm;
+                if am | bm != pm || z.count_ones() != 1 {
+                    continue;
+                }
+                let eliminations = eliminate(cands, z, &[pivot, a, b], &[pivot, a, b]);
+                if !eliminations.is_empty() {
+                    result.push(deduction(
+                        Technique::XyzWing,
+                        vec![pivot, a, b],
+                        eliminations,
+                    ));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+// Houses where 'mask' is possible in exactly two cells
+fn strong_links(cands: &Candidates, mask: u16) -> Vec<(Cell, Cell)> {
+    let mut links = Vec::new();
+    for house in houses() {
+        let mut cells = house.iter().filter(|cell| cand(cands, **cell) & mask != 0);
+        if let (Some(a), Some(b), None) = (cells.next(), cells.next(), cells.next()) {
+            links.push((*a, *b));
+        }
+    }
+    links
+}
+
+pub(crate) fn w_wing(cands: &Candidates) -> Vec<Deduction> {
+    let mut result = Vec::new();
+    let bivalue = cells_with_count(cands, 2);
+    let links: Vec<Vec<(Cell, Cell)>> = (0..GROUP_SIZE)
+        .map(|val| strong_links(cands, 1 << val))
+        .collect();
+
+    for &a in &bivalue {
+        let mask = cand(cands, a);
+        for &b in bivalue.iter().filter(|b| **b > a) {
+            if cand(cands, b) != mask || sees(a, b) {
+                continue;
+            }
+            for x in [mask & mask.wrapping_neg(), mask & (mask - 1)] {
+                let y = mask & !x;
+                for &(l1, l2) in &links[x.trailing_zeros() as usize] {
+                    if [l1, l2].iter().any(|l| *l == a || *l == b) {
+                        continue;
+                    }
+                    let linked = (sees(l1, a) && sees(l2, b)) || (sees(l1, b) && sees(l2, a));
+                    if !linked {
+                        continue;
+                    }
+                    let eliminations = eliminate(cands, y, &[a, b, l1, l2], &[a, b]);
+                    if !eliminations.is_empty() {
+                        result.push(deduction(
+                            Technique::WWing,
+                            vec![a, b, l1, l2],
+                            eliminations,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    result
+}
+
+pub(crate) fn coloring(cands: &Candidates) -> Vec<Deduction> {
+    let mut result = Vec::new();
+
+    for val in 0..GROUP_SIZE {
+        let mask = 1 << val;
+
+        let mut links: HashMap<Cell, Vec<Cell>> = HashMap::new();
+        for (a, b) in strong_links(cands, mask) {
+            links.entry(a).or_default().push(b);
+            links.entry(b).or_default().push(a);
+        }
+
+        // Color each connected group of conjugate pairs
+        let mut colors: HashMap<Cell, bool> = HashMap::new();
+        let mut starts: Vec<Cell> = links.keys().copied().collect();
+        starts.sort();
+        for start in starts {
+            if colors.contains_key(&start) {
+                continue;
+            }
+            let mut group = vec![(start, true)];
+            let mut stack = vec![start];
+            colors.insert(start, true);
+            while let Some(cell) = stack.pop() {
+                let color = colors[&cell];
+                for next in &links[&cell] {
+                    if !colors.contains_key(next) {
+                        colors.insert(*next, !color);
+                        group.push((*next, !color));
+                        stack.push(*next);
+                    }
+                }
+            }
+
+            let cells: Vec<Cell> = group.iter().map(|(cell, _)| *cell).collect();
+            let with_color = |color: bool| group.iter().filter(move |g| g.1 == color).map(|g| g.0);
+
+            // Two cells of one color seeing each other: that color is false
+            let mut eliminations = Vec::new();
+            for color in [true, false] {
+                let same: Vec<Cell> = with_color(color).collect();
+                let wrap = same.iter().any(|a| same.iter().any(|b| sees(*a, *b)));
+                if wrap {
+                    eliminations = same
+                        .iter()
+                        .map(|(row, col)| Candidate {
+                            row: *row,
+                            col: *col,
+                            val: val + 1,
+                        })
+                        .collect();
+                    break;
+                }
+            }
+
+            // A cell seeing both colors can not hold the value
+            if eliminations.is_empty() {
+                eliminations = all_cells()
+                    .filter(|cell| cand(cands, *cell) & mask != 0 && !cells.contains(cell))
+                    .filter(|cell| {
+                        with_color(true).any(|c| sees(*cell, c))
+                            && with_color(false).any(|c| sees(*cell, c))
+                    })
+                    .map(|(row, col)| Candidate {
+                        row,
+                        col,
+                        val: val + 1,
+                    })
+                    .collect();
+            }
+
+            if !eliminations.is_empty() {
+                result.push(deduction(Technique::Coloring, cells, eliminations));
+            }
+        }
+    }
+
+    result
+}
+
+// Techniques in the order the solver tries them
+const TECHNIQUES: [fn(&Candidates) -> Vec<Deduction>; 4] = [xy_wing, xyz_wing, w_wing, coloring];
+
+/// Every deduction the chain techniques can make
+pub(crate) fn deductions(cands: &Candidates) -> Vec<Deduction> {
+    TECHNIQUES.iter().flat_map(|t| t(cands)).collect()
+}
+
+/// Deductions of the first technique that finds any
+pub(crate) fn first_deductions(cands: &Candidates) -> Vec<Deduction> {
+    TECHNIQUES
+        .iter()
+        .map(|t| t(cands))
+        .find(|d| !d.is_empty())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Mask from 1-based values
+    fn mask(vals: &[usize]) -> u16 {
+        vals.iter().fold(0, |m, v| m | (1 << (v - 1)))
+    }
+
+    fn elim(row: usize, col: usize, val: usize) -> Candidate {
+        Candidate { row, col, val }
+    }
+
+    #[test]
+    fn find_xy_wing() {
+        let mut cands = [[0; GROUP_SIZE]; GROUP_SIZE];
+        cands[0][0] = mask(&[1, 2]);
+        cands[0][5] = mask(&[1, 3]);
+        cands[5][0] = mask(&[2, 3]);
+        cands[5][5] = mask(&[3, 4, 5]);
+
+        let found = xy_wing(&cands);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].cells, vec![(0, 0), (0, 5), (5, 0)]);
+        assert_eq!(found[0].eliminations, vec![elim(5, 5, 3)]);
+    }
+
+    #[test]
+    fn find_xyz_wing() {
+        let mut cands = [[0; GROUP_SIZE]; GROUP_SIZE];
+        cands[0][0] = mask(&[1, 2, 3]);
+        cands[0][4] = mask(&[1, 3]);
+        cands[1][1] = mask(&[2, 3]);
+        cands[0][2] = mask(&[3, 4, 5]);
+        cands[2][2] = mask(&[3, 4, 5]);
+
+        let found = xyz_wing(&cands);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].eliminations, vec![elim(0, 2, 3)]);
+    }
+
+    #[test]
+    fn find_w_wing() {
+        let mut cands = [[0; GROUP_SIZE]; GROUP_SIZE];
+        cands[0][0] = mask(&[1, 2]);
+        cands[4][8] = mask(&[1, 2]);
+        cands[8][0] = mask(&[1, 5]);
+        cands[8][8] = mask(&[1, 6]);
+        cands[0][8] = mask(&[2, 3, 5]);
+        cands[4][0] = mask(&[2, 3, 5]);
+
+        let found = w_wing(&cands);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].cells, vec![(0, 0), (4, 8), (8, 0), (8, 8)]);
+        assert_eq!(found[0].eliminations, vec![elim(0, 8, 2), elim(4, 0, 2)]);
+    }
+
+    #[test]
+    fn find_coloring_trap() {
+        // conjugate pairs (0,0)-(0,4)-(5,4)-(5,1), with (3,0) seeing both
+        // colors
+        let mut cands = [[0; GROUP_SIZE]; GROUP_SIZE];
+        for (r, c) in [(0, 0), (0, 4), (5, 4), (5, 1), (3, 0), (7, 0), (4, 2)] {
+            cands[r][c] = mask(&[5]);
+        }
+
+        let found = coloring(&cands);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].cells.len(), 4);
+        assert_eq!(found[0].eliminations, vec![elim(3, 0, 5)]);
+    }
+
+    #[test]
+    fn find_coloring_wrap() {
+        // conjugate pairs (0,0)-(4,0)-(4,6)-(5,7)-(0,7), giving (0,0) and
+        // (0,7) the same color in one row
+        let mut cands = [[0; GROUP_SIZE]; GROUP_SIZE];
+        for (r, c) in [(0, 0), (4, 0), (4, 6), (5, 7), (0, 7), (0, 4)] {
+            cands[r][c] = mask(&[5]);
+        }
+
+        let found = coloring(&cands);
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].eliminations,
+            vec![elim(0, 0, 5), elim(4, 6, 5), elim(0, 7, 5)]
+        );
+    }
+}
@@ -0,0 +1,318 @@
+//! CSV import and export of puzzle collections
+//!
+//! Each puzzle is nine rows of nine comma separated fields, a value
+//! 1-9, or empty, `0` or `X` for a blank.  Fields may be quoted.  A file
+//! holds any number of puzzles, optionally separated by blank lines or
+//! by rows of empty fields as spreadsheets export blank rows, and starts
+//! with a header row when the reader is told so.  Rows of empty fields
+//! after a puzzle are puzzles of blanks in runs of nine, and separators
+//! otherwise.
+
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::Path;
+
+use anyhow::{anyhow, Context};
+
+use crate::element::GROUP_SIZE;
+use crate::puzzle::Puzzle;
+
+// Split a CSV record into trimmed fields, removing quotes
+fn split_record(line: &str, index: usize) -> Result<Vec<String>, anyhow::Error> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut chars = line.chars().peekable();
+    let mut quoted = false;
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field).trim().to_string()),
+            ch => field.push(ch),
+        }
+    }
+    if quoted {
+        return Err(anyhow!(
+            "Unterminated quoted field in CSV line: {}",
+            index + 1
+        ));
+    }
+    fields.push(field.trim().to_string());
+
+    Ok(fields)
+}
+
+// Parse a field to a value 1-9, or 0 for a blank
+fn parse_field(field: &str, index: usize, col: usize) -> Result<usize, anyhow::Error> {
+    if field.is_empty() || field == "X" || field == "x" {
+        return Ok(0);
+    }
+
+    let val: usize = field.parse().with_context(|| {
+        format!(
+            "Unable to parse element: {} as a number in CSV line:col: {}:{}",
+            field,
+            index + 1,
+            col + 1
+        )
+    })?;
+    if val > GROUP_SIZE {
+        return Err(anyhow!(
+            "Element value {} is out of range [1-9] in CSV line:col: {}:{}",
+            val,
+            index + 1,
+            col + 1
+        ));
+    }
+
+    Ok(val)
+}
+
+// Test if a record is a row of empty fields
+fn is_empty_row(fields: &[String]) -> bool {
+    fields.len() == GROUP_SIZE && fields.iter().all(|f| f.is_empty())
+}
+
+/// Read a collection of puzzles from CSV text.  With `header` the first
+/// row is a header and skipped.
+pub fn read_csv(text: &str, header: bool) -> Result<Vec<Puzzle>, anyhow::Error> {
+    // Spreadsheets often start UTF-8 files with a byte order mark
+    let text = text.strip_prefix('\u{feff}').unwrap_or(text);
+
+    let lines: Vec<&str> = text.lines().collect();
+    let mut puzzles = Vec::new();
+    let mut rows = Vec::with_capacity(GROUP_SIZE);
+    let mut skip_header = header;
+    let mut separators = 0;
+
+    for (index, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            if !rows.is_empty() {
+                return Err(anyhow!(
+                    "Not enough rows in puzzle: {}, at CSV line: {}",
+                    rows.len(),
+                    index + 1
+                ));
+            }
+            continue;
+        }
+        if skip_header {
+            skip_header = false;
+            continue;
+        }
+
+        let fields = split_record(line, index)?;
+
+        // Count the rows of empty fields after a puzzle, those beyond
+        // whole puzzles of blanks are separators
+        if rows.is_empty() && !puzzles.is_empty() && is_empty_row(&fields) {
+            if separators == 0 {
+                let run = lines[index..]
+                    .iter()
+                    .take_while(|l| split_record(l, index).is_ok_and(|f| is_empty_row(&f)))
+                    .count();
+                separators = run % GROUP_SIZE;
+            }
+            if separators > 0 {
+                separators -= 1;
+                continue;
+            }
+        }
+
+        if fields.len() != GROUP_SIZE {
+            return Err(anyhow!(
+                "Unexpected number of columns ({}) in CSV line: {}",
+                fields.len(),
+                index + 1
+            ));
+        }
+
+        let mut row = [0; GROUP_SIZE];
+        for (col, field) in fields.iter().enumerate() {
+            row[col] = parse_field(field, index, col)?;
+        }
+        rows.push(row);
+
+        if rows.len() == GROUP_SIZE {
+            let values: [[usize; GROUP_SIZE]; GROUP_SIZE] = rows[..].try_into().unwrap();
+            let puzzle = Puzzle::from_values(&values).with_context(|| {
+                format!("Inconsistent puzzle ending at CSV line: {}", index + 1)
+            })?;
+            puzzles.push(puzzle);
+            rows.clear();
+        }
+    }
+
+    if !rows.is_empty() {
+        return Err(anyhow!("Not enough rows in puzzle: {}", rows.len()));
+    }
+
+    Ok(puzzles)
+}
+
+/// Write a collection of puzzles as CSV text, separated by blank rows
+pub fn write_csv(puzzles: &[Puzzle]) -> String {
+    puzzles
+        .iter()
+        .map(|p| p.to_csv())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+impl Puzzle {
+    /// Read a collection of puzzles from a CSV file, skipping its first
+    /// row with `header`
+    #[cfg(feature = "fs")]
+    pub fn from_csv_file(path: &Path, header: bool) -> Result<Vec<Self>, anyhow::Error> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read CSV file: {}", path.display()))?;
+        read_csv(&text, header)
+            .with_context(|| format!("Failed to parse CSV file: {}", path.display()))
+    }
+
+    /// Format the puzzle as nine CSV rows, blanks as empty fields
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::new();
+        for row in self.values() {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|v| match v {
+                    0 => String::new(),
+                    v => v.to_string(),
+                })
+                .collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str = "8...4629.\
+                          7....9..5\
+                          ..2..5...\
+                          .6.21.84.\
+                          .27.8.53.\
+                          .38.67.2.\
+                          ...4..6..\
+                          9..3.....\
+                          .4165...3";
+
+    #[test]
+    fn csv_round_trip() {
+        let puzzle: Puzzle = PUZZLE.parse().unwrap();
+        let csv = write_csv(&[puzzle.clone(), Puzzle::default(), puzzle.clone()]);
+        assert!(csv.starts_with("8,,,,4,6,2,9,\n"));
+
+        let puzzles = read_csv(&csv, false).unwrap();
+        assert_eq!(puzzles.len(), 3);
+        assert_eq!(puzzles[0].to_line(), PUZZLE);
+        assert_eq!(puzzles[1].to_line(), ".".repeat(81));
+        assert_eq!(puzzles[2].to_line(), PUZZLE);
+    }
+
+    // PUZZLE as CSV rows, givens quoted and blanks as 0
+    fn quoted_csv() -> String {
+        let mut csv = String::new();
+        for row in PUZZLE.as_bytes().chunks(9) {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|b| match b {
+                    b'.' => "0".to_string(),
+                    b => format!("\" {}\"", *b as char),
+                })
+                .collect();
+            csv.push_str(&fields.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    #[test]
+    fn csv_header_and_quotes() {
+        let csv = format!("\"c1\",c2,c3,c4,c5,c6,c7,c8,\"c9, last\"\n{}", quoted_csv());
+        let puzzles = read_csv(&csv, true).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        assert_eq!(puzzles[0].to_line(), PUZZLE);
+        assert!(read_csv(&csv, false).is_err());
+
+        // A numeric header is only a header when asked for
+        let csv = format!("1,2,3,4,5,6,7,8,9\n{}", quoted_csv());
+        assert_eq!(read_csv(&csv, true).unwrap()[0].to_line(), PUZZLE);
+        assert!(read_csv(&csv, false).is_err());
+    }
+
+    #[test]
+    fn csv_byte_order_mark() {
+        let csv = format!("\u{feff}{}", quoted_csv());
+        assert_eq!(read_csv(&csv, false).unwrap()[0].to_line(), PUZZLE);
+
+        let csv = format!("\u{feff}c1,c2,c3,c4,c5,c6,c7,c8,c9\n{}", quoted_csv());
+        assert_eq!(read_csv(&csv, true).unwrap()[0].to_line(), PUZZLE);
+    }
+
+    #[test]
+    fn csv_empty_rows() {
+        // Nine rows of empty fields right after a puzzle are the next
+        // puzzle
+        let csv = format!("{}{}", quoted_csv(), Puzzle::default().to_csv());
+        let puzzles = read_csv(&csv, false).unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert_eq!(puzzles[1].to_line(), ".".repeat(81));
+
+        // fewer separate puzzles, like blank lines do
+        let csv = format!("{},,,,,,,,\n{}", quoted_csv(), quoted_csv());
+        assert_eq!(read_csv(&csv, false).unwrap().len(), 2);
+        let csv = format!("{}\n \n{}", quoted_csv(), quoted_csv());
+        assert_eq!(read_csv(&csv, false).unwrap().len(), 2);
+
+        // and the rows beyond a puzzle of blanks separate it
+        let csv = format!(",,,,,,,,\n{}", Puzzle::default().to_csv());
+        let csv = format!("{}{}{}", quoted_csv(), csv, csv);
+        let puzzles = read_csv(&csv, false).unwrap();
+        assert_eq!(puzzles.len(), 3);
+        assert_eq!(puzzles[2].to_line(), ".".repeat(81));
+
+        // but never start the first puzzle
+        let csv = format!(",,,,,,,,\n{}", quoted_csv());
+        assert!(read_csv(&csv, false).is_err());
+    }
+
+    #[test]
+    fn csv_spreadsheet_export() {
+        // Saved as "CSV UTF-8" by a spreadsheet, with a byte order mark,
+        // CRLF line endings and a row of empty fields after each puzzle
+        let csv = include_str!("../puzzles/spreadsheet.csv");
+        let puzzles = read_csv(csv, false).unwrap();
+        assert_eq!(puzzles.len(), 2);
+        assert!(puzzles[0].to_line().starts_with("2....8.1."));
+        assert!(puzzles[1].to_line().starts_with("1.56..4.."));
+    }
+
+    #[test]
+    fn csv_errors() {
+        let csv = Puzzle::default().to_csv();
+
+        // Wrong shape
+        assert!(read_csv(&csv.replacen(",\n", "\n", 1), false).is_err());
+        assert!(read_csv(&csv[..csv.len() - 9], false).is_err());
+        assert!(read_csv(&csv.replacen('\n', "\n\n", 1), false).is_err());
+
+        // Bad values
+        assert!(read_csv(&csv.replacen(',', "10,", 1), false).is_err());
+        assert!(read_csv(&csv.replacen(',', "a,", 1), false).is_err());
+        assert!(read_csv(&csv.replacen(',', "\"1,", 1), false).is_err());
+
+        // Inconsistent givens
+        assert!(read_csv(&csv.replacen(",,", "1,1,", 1), false).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+//! Explaining why a puzzle has no solution
+//!
+//! Givens are removed one at a time, keeping each removal that leaves
+//! the puzzle without a solution.  What is left is a minimal set of
+//! givens that contradict each other: removing any one of them makes
+//! the puzzle solvable.  The logic solver is then run on just those
+//! givens to show where the contradiction appears.
+
+use std::fmt;
+
+use crate::element::GROUP_SIZE;
+use crate::error::SudokuError;
+use crate::limits::{Search, SolveLimits};
+use crate::minimize::Given;
+use crate::puzzle::Puzzle;
+
+/// Where the logic solver finds a contradiction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Contradiction {
+    /// The same value is in two cells sharing a row, column or square.
+    /// The first cell may hold a value deduced from the givens.
+    Duplicate {
+        /// The value, 1-9
+        val: usize,
+
+        /// The two cells, (row, col) 0-based
+        cells: [(usize, usize); 2],
+    },
+
+    /// An empty cell has no possible value left
+    NoCandidates {
+        /// Row of the cell, 0-based
+        row: usize,
+
+        /// Column of the cell, 0-based
+        col: usize,
+
+        /// For each value that has one, a cell sharing a row, column or
+        /// square with the empty cell that holds the value, given or
+        /// deduced
+        blockers: Vec<Given>,
+    },
+
+    /// A value has no possible cell left in a row, column or square
+    NoPlace {
+        /// The value, 1-9
+        val: usize,
+
+        /// The row, column or square
+        house: House,
+    },
+
+    /// The logic solver finds no contradiction, only guessing shows the
+    /// givens have no solution
+    Search,
+}
+
+/// A row, column or square, numbered from 0.  Squares are numbered
+/// left to right, top to bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum House {
+    /// A row
+    Row(usize),
+
+    /// A column
+    Col(usize),
+
+    /// A 3x3 square
+    Square(usize),
+}
+
+impl House {
+    fn cells(&self) -> Vec<(usize, usize)> {
+        (0..GROUP_SIZE)
+            .map(|i| match self {
+                House::Row(r) => (*r, i),
+                House::Col(c) => (i, *c),
+                House::Square(s) => (s / 3 * 3 + i / 3, s % 3 * 3 + i % 3),
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for House {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            House::Row(r) => write!(f, "row {}", r + 1),
+            House::Col(c) => write!(f, "column {}", c + 1),
+            House::Square(s) => write!(f, "square {}", s + 1),
+        }
+    }
+}
+
+/// Result of `diagnose`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnosis {
+    /// A minimal set of givens with no solution
+    pub givens: Vec<Given>,
+
+    /// How the contradiction between the givens shows up
+    pub contradiction: Contradiction,
+}
+
+type Values = [[usize; GROUP_SIZE]; GROUP_SIZE];
+
+fn sees(a: (usize, usize), b: (usize, usize)) -> bool {
+    a != b && (a.0 == b.0 || a.1 == b.1 || (a.0 / 3 == b.0 / 3 && a.1 / 3 == b.1 / 3))
+}
+
+fn peers(row: usize, col: usize) -> impl Iterator<Item = (usize, usize)> {
+    (0..GROUP_SIZE * GROUP_SIZE)
+        .map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+        .filter(move |cell| sees((row, col), *cell))
+}
+
+fn has_solution(values: &Values) -> bool {
+    Puzzle::from_values(values).is_ok_and(|p| p.count_solutions(1) > 0)
+}
+
+// Two cells breaking the rules with the same value
+fn duplicate(values: &Values) -> Option<Contradiction> {
+    for row in 0..GROUP_SIZE {
+        for col in 0..GROUP_SIZE {
+            let val = values[row][col];
+            if val == 0 {
+                continue;
+            }
+            if let Some(other) = peers(row, col).find(|(r, c)| values[*r][*c] == val) {
+                return Some(Contradiction::Duplicate {
+                    val,
+                    cells: [(row, col), other],
+                });
+            }
+        }
+    }
+    None
+}
+
+// Run the logic solver on 'values' to find where it breaks
+fn contradiction(values: &Values) -> Contradiction {
+    if let Some(duplicate) = duplicate(values) {
+        return duplicate;
+    }
+
+    let mut puzzle = match Puzzle::from_values(values) {
+        Ok(puzzle) => puzzle,
+        Err(_) => return Contradiction::Search,
+    };
+    let mut search = Search::new(&SolveLimits::default());
+    let result = puzzle.propagate(&mut search);
+    let current = puzzle.values();
+
+    match result {
+        Err(SudokuError::PuzzleStateInconsistent(row, col, val)) => {
+            let val = val + 1;
+            match peers(row, col).find(|(r, c)| current[*r][*c] == val) {
+                Some(other) => Contradiction::Duplicate {
+                    val,
+                    cells: [(row, col), other],
+                },
+                None => Contradiction::Search,
+            }
+        }
+        _ => {
+            let empty = (0..GROUP_SIZE * GROUP_SIZE)
+                .map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+                .find(|(r, c)| current[*r][*c] == 0 && puzzle.possible_mask(*r, *c) == 0);
+            if let Some((row, col)) = empty {
+                let blockers = (1..=GROUP_SIZE)
+                    .filter_map(|val| {
+                        peers(row, col)
+                            .find(|(r, c)| current[*r][*c] == val)
+                            .map(|(r, c)| Given {
+                                row: r,
+                                col: c,
+                                val,
+                            })
+                    })
+                    .collect();
+                return Contradiction::NoCandidates { row, col, blockers };
+            }
+
+            no_place(&puzzle).unwrap_or(Contradiction::Search)
+        }
+    }
+}
+
+// A value neither placed nor possible anywhere in a house
+fn no_place(puzzle: &Puzzle) -> Option<Contradiction> {
+    let houses = (0..GROUP_SIZE)
+        .map(House::Row)
+        .chain((0..GROUP_SIZE).map(House::Col))
+        .chain((0..GROUP_SIZE).map(House::Square));
+
+    for house in houses {
+        let mask = house
+            .cells()
+            .iter()
+            .fold(0, |m, (r, c)| m | puzzle.possible_mask(*r, *c));
+        if let Some(v) = (0..GROUP_SIZE).find(|v| mask & (1 << v) == 0) {
+            return Some(Contradiction::NoPlace { val: v + 1, house });
+        }
+    }
+    None
+}
+
+/// Explain why the cell values, 1-9 for givens and 0 for blanks, have
+/// no solution.  Returns None if they have one.
+///
+/// The values need not follow the sudoku rules, so text that fails to
+/// parse as a `Puzzle` can be diagnosed with `Puzzle::parse_values`.
+pub fn diagnose(values: &Values) -> Option<Diagnosis> {
+    if has_solution(values) {
+        return None;
+    }
+
+    // Two givens breaking the rules are a contradiction on their own,
+    // otherwise drop each given the contradiction does not need
+    let mut core = *values;
+    if let Some(Contradiction::Duplicate { val, cells }) = duplicate(values) {
+        core = [[0; GROUP_SIZE]; GROUP_SIZE];
+        for (r, c) in cells {
+            core[r][c] = val;
+        }
+    }
+    for row in 0..GROUP_SIZE {
+        for col in 0..GROUP_SIZE {
+            let val = core[row][col];
+            if val == 0 {
+                continue;
+            }
+            core[row][col] = 0;
+            if has_solution(&core) {
+                core[row][col] = val;
+            }
+        }
+    }
+
+    let givens = (0..GROUP_SIZE * GROUP_SIZE)
+        .map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+        .filter(|(r, c)| core[*r][*c] != 0)
+        .map(|(row, col)| Given {
+            row,
+            col,
+            val: core[row][col],
+        })
+        .collect();
+
+    Some(Diagnosis {
+        givens,
+        contradiction: contradiction(&core),
+    })
+}
+
+impl Puzzle {
+    /// Explain why the puzzle has no solution, see `diagnose`.  Returns
+    /// None if it has one.
+    pub fn diagnose(&self) -> Option<Diagnosis> {
+        diagnose(&self.values())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str = "8...4629.\
+                          7....9..5\
+                          ..2..5...\
+                          .6.21.84.\
+                          .27.8.53.\
+                          .38.67.2.\
+                          ...4..6..\
+                          9..3.....\
+                          .4165...3";
+
+    #[test]
+    fn solvable() {
+        let puzzle: Puzzle = PUZZLE.parse().unwrap();
+        assert_eq!(puzzle.diagnose(), None);
+    }
+
+    #[test]
+    fn duplicate_givens() {
+        // a second 8 in the first row
+        let mut values = Puzzle::parse_values(PUZZLE).unwrap();
+        values[0][3] = 8;
+        assert!(PUZZLE
+            .replacen("8...", "8..8", 1)
+            .parse::<Puzzle>()
+            .is_err());
+
+        let diagnosis = diagnose(&values).unwrap();
+        assert_eq!(
+            diagnosis.givens,
+            vec![
+                Given {
+                    row: 0,
+                    col: 0,
+                    val: 8
+                },
+                Given {
+                    row: 0,
+                    col: 3,
+                    val: 8
+                },
+            ]
+        );
+        assert_eq!(
+            diagnosis.contradiction,
+            Contradiction::Duplicate {
+                val: 8,
+                cells: [(0, 0), (0, 3)]
+            }
+        );
+    }
+
+    // Check removing any given of the diagnosis leaves a solution
+    fn assert_minimal(diagnosis: &Diagnosis) {
+        for given in &diagnosis.givens {
+            let mut core = [[0; GROUP_SIZE]; GROUP_SIZE];
+            for g in diagnosis.givens.iter().filter(|g| *g != given) {
+                core[g.row][g.col] = g.val;
+            }
+            assert!(has_solution(&core), "{:?} is not needed", given);
+        }
+    }
+
+    #[test]
+    fn empty_cell() {
+        // nothing fits at (0, 0): its row, column and square hold 1-9
+        let mut values = [[0; GROUP_SIZE]; GROUP_SIZE];
+        values[0][3..6].copy_from_slice(&[1, 2, 3]);
+        values[3][0] = 4;
+        values[4][0] = 5;
+        values[5][0] = 6;
+        values[1][1] = 7;
+        values[1][2] = 8;
+        values[2][1] = 9;
+        values[8][8] = 1;
+
+        let diagnosis = diagnose(&values).unwrap();
+        assert_eq!(diagnosis.givens.len(), 9);
+        assert_minimal(&diagnosis);
+        match diagnosis.contradiction {
+            Contradiction::NoCandidates { row, col, blockers } => {
+                assert_eq!((row, col), (0, 0));
+                assert_eq!(blockers.len(), GROUP_SIZE);
+            }
+            other => panic!("unexpected contradiction: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn no_place() {
+        // 9 can not go in the first row: columns 3-8 are full and the
+        // first square already holds a 9
+        let mut values = [[0; GROUP_SIZE]; GROUP_SIZE];
+        values[0][3..9].copy_from_slice(&[1, 2, 3, 4, 5, 6]);
+        values[1][1] = 9;
+        values[3][0] = 7;
+        values[8][8] = 1;
+
+        let diagnosis = diagnose(&values).unwrap();
+        assert_eq!(diagnosis.givens.len(), 7);
+        assert_minimal(&diagnosis);
+        assert_eq!(
+            diagnosis.contradiction,
+            Contradiction::NoPlace {
+                val: 9,
+                house: House::Row(0)
+            }
+        );
+    }
+}
@@ -36,6 +36,11 @@ impl Element {
         self.possible.remove(val);
     }
 
+    /// Return the number of possibilities
+    pub fn possible_len(&self) -> usize {
+        self.possible.len()
+    }
+
     /// Return the possibility set
     pub fn possible(&self) -> BitSet {
         self.possible.clone()
@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use crate::limits::{Limit, SolveStats};
+
 /// Enum of error types
 #[derive(Error, Debug)]
 pub enum SudokuError {
@@ -16,4 +18,31 @@ pub enum SudokuError {
     /// Puzzle unsolvable - no forward progress made
     #[error("Puzzle unsolvable")]
     PuzzleUnsolvable,
+
+    /// Puzzle has more than one solution
+    #[error("Puzzle has multiple solutions")]
+    MultipleSolutions,
+
+    /// A cell value is outside 0-9
+    #[error("Invalid value {value} in row {}, col {}", row + 1, col + 1)]
+    InvalidValue {
+        /// Row of the cell, from 0
+        row: usize,
+
+        /// Column of the cell, from 0
+        col: usize,
+
+        /// The value
+        value: usize,
+    },
+
+    /// A solver limit was reached before the puzzle was solved
+    #[error("Solver limit exceeded: {limit}, after {} iterations, {} nodes", stats.iterations, stats.nodes)]
+    LimitExceeded {
+        /// The limit reached
+        limit: Limit,
+
+        /// Statistics up to the point the solver stopped
+        stats: Box<SolveStats>,
+    },
 }
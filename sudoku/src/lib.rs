@@ -2,14 +2,29 @@
 
 #![warn(missing_docs)]
 
+mod canonical;
+mod chains;
+mod csv;
+mod diagnose;
 mod element;
 mod error;
+mod limits;
+mod minimize;
+mod multi;
 mod puzzle;
+mod render;
 
 pub mod prelude {
     //! Common things to include in all modules
 
+    pub use crate::chains::{Candidate, Deduction, Technique};
+    pub use crate::csv::{read_csv, write_csv};
+    pub use crate::diagnose::{diagnose, Contradiction, Diagnosis, House};
     pub use crate::element::Element;
     pub use crate::error::SudokuError;
-    pub use crate::puzzle::Puzzle;
+    pub use crate::limits::{CancelToken, Limit, Pass, PassStats, SolveLimits, SolveStats};
+    pub use crate::minimize::{Given, Minimized};
+    pub use crate::multi::{Layout, MultiPuzzle};
+    pub use crate::puzzle::{Difficulty, Hint, Puzzle};
+    pub use crate::render::{render_booklet, PageLayout, Paper, SvgOptions};
 }
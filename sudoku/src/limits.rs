@@ -0,0 +1,250 @@
+//! Solver limits and cancellation
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use web_time::Instant;
+
+use crate::error::SudokuError;
+
+/// A pass of the logic solver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Pass {
+    /// Remove the values of resolved elements from their neighbours
+    ReduceBasicElements,
+
+    /// Find values only possible in one element of a row
+    RowScan,
+
+    /// Find values only possible in one element of a column
+    ColScan,
+
+    /// Find values only possible in one element of a square
+    SqrScan,
+
+    /// Apply a chain technique deduction
+    ChainScan,
+}
+
+impl Pass {
+    /// All passes, in the order the logic solver runs them
+    pub const ALL: [Pass; 5] = [
+        Pass::ReduceBasicElements,
+        Pass::RowScan,
+        Pass::ColScan,
+        Pass::SqrScan,
+        Pass::ChainScan,
+    ];
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pass::ReduceBasicElements => write!(f, "reduce_basic_elements"),
+            Pass::RowScan => write!(f, "row_scan"),
+            Pass::ColScan => write!(f, "col_scan"),
+            Pass::SqrScan => write!(f, "sqr_scan"),
+            Pass::ChainScan => write!(f, "chain_scan"),
+        }
+    }
+}
+
+/// Statistics of a single logic solver pass
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PassStats {
+    /// Times the pass ran
+    pub calls: usize,
+
+    /// Time spent in the pass
+    pub elapsed: Duration,
+
+    /// Possibilities removed by the pass
+    pub eliminated: usize,
+
+    /// Elements resolved by the pass
+    pub resolved: usize,
+}
+
+/// Statistics gathered while solving
+#[derive(Debug, Clone, Default)]
+pub struct SolveStats {
+    /// Logic solver iterations
+    pub iterations: usize,
+
+    /// Guesses tried, each one a copy of the puzzle
+    pub nodes: usize,
+
+    /// Copies of the puzzle made to try guesses
+    pub clones: usize,
+
+    /// Deepest nesting of guesses
+    pub max_depth: usize,
+
+    /// Wall-clock time spent solving
+    pub elapsed: Duration,
+
+    /// Statistics of each pass, indexed as `Pass::ALL`
+    pub passes: [PassStats; Pass::ALL.len()],
+}
+
+impl SolveStats {
+    /// Statistics of one pass
+    pub fn pass(&self, pass: Pass) -> &PassStats {
+        &self.passes[pass as usize]
+    }
+
+    pub(crate) fn pass_mut(&mut self, pass: Pass) -> &mut PassStats {
+        &mut self.passes[pass as usize]
+    }
+}
+
+/// Token used to cancel a running solve from another thread
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Create a new, not cancelled, token
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Request cancellation
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Test if cancellation was requested
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Bounds on the work done by `Puzzle::solve_with_limits`.
+///
+/// The default is unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct SolveLimits {
+    /// Maximum wall-clock time
+    pub timeout: Option<Duration>,
+
+    /// Maximum number of guesses
+    pub max_nodes: Option<usize>,
+
+    /// Maximum number of logic solver iterations
+    pub max_iterations: Option<usize>,
+
+    /// Cancellation token
+    pub cancel: Option<CancelToken>,
+}
+
+/// The limit that stopped the solver
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// `SolveLimits::timeout` reached
+    Timeout,
+
+    /// `SolveLimits::max_nodes` reached
+    Nodes,
+
+    /// `SolveLimits::max_iterations` reached
+    Iterations,
+
+    /// `CancelToken::cancel` called
+    Cancelled,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Limit::Timeout => write!(f, "timeout"),
+            Limit::Nodes => write!(f, "max nodes"),
+            Limit::Iterations => write!(f, "max iterations"),
+            Limit::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+// Book keeping for a single solve
+pub(crate) struct Search {
+    limits: SolveLimits,
+    start: Instant,
+    pub(crate) stats: SolveStats,
+
+    // Current nesting of guesses
+    depth: usize,
+
+    // Use the chain techniques when the basic passes are stuck
+    pub(crate) chains: bool,
+
+    // Gather the statistics of each pass, which costs a census of the
+    // puzzle before and after every pass
+    pub(crate) pass_stats: bool,
+}
+
+impl Search {
+    pub(crate) fn new(limits: &SolveLimits) -> Self {
+        Self {
+            limits: limits.clone(),
+            start: Instant::now(),
+            stats: SolveStats::default(),
+            depth: 0,
+            chains: true,
+            pass_stats: false,
+        }
+    }
+
+    // Record a copy of the puzzle made to try a guess, one level deeper
+    pub(crate) fn enter(&mut self) {
+        self.stats.clones += 1;
+        self.depth += 1;
+        self.stats.max_depth = self.stats.max_depth.max(self.depth);
+    }
+
+    // Return from a guess
+    pub(crate) fn leave(&mut self) {
+        self.depth -= 1;
+    }
+
+    // Statistics with the elapsed time up to date
+    pub(crate) fn finish(&mut self) -> SolveStats {
+        self.stats.elapsed = self.start.elapsed();
+        self.stats.clone()
+    }
+
+    // Check the limits, returning LimitExceeded with the current stats
+    // if any has been reached
+    pub(crate) fn check(&mut self) -> Result<(), SudokuError> {
+        self.stats.elapsed = self.start.elapsed();
+
+        let limit = if self
+            .limits
+            .cancel
+            .as_ref()
+            .is_some_and(|c| c.is_cancelled())
+        {
+            Some(Limit::Cancelled)
+        } else if self.limits.timeout.is_some_and(|t| self.stats.elapsed >= t) {
+            Some(Limit::Timeout)
+        } else if self.limits.max_nodes.is_some_and(|n| self.stats.nodes > n) {
+            Some(Limit::Nodes)
+        } else if self
+            .limits
+            .max_iterations
+            .is_some_and(|n| self.stats.iterations > n)
+        {
+            Some(Limit::Iterations)
+        } else {
+            None
+        };
+
+        match limit {
+            Some(limit) => Err(SudokuError::LimitExceeded {
+                limit,
+                stats: Box::new(self.stats.clone()),
+            }),
+            None => Ok(()),
+        }
+    }
+}
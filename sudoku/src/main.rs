@@ -1,24 +1,144 @@
 // use sudoku::prelude::*;
 
-use std::path::PathBuf;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use clap::Parser;
+use anyhow::{anyhow, Context};
+use clap::{Parser, Subcommand, ValueEnum};
+use serde_derive::Serialize;
 use tracing::{debug, error, info};
 use tracing_subscriber::EnvFilter;
 
 use sudoku::prelude::*;
 
+mod serve;
+
 /// Sudoku Puzzle Solver
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
 struct Args {
     /// Name of input puzzle file
     #[clap(short, long)]
-    puzzle_file: PathBuf,
+    puzzle_file: Option<PathBuf>,
 
     /// Debug output
     #[clap(short, long)]
     debug: bool,
+
+    /// Give up solving after this many milliseconds
+    #[clap(long)]
+    timeout_ms: Option<u64>,
+
+    /// Give up solving after this many guesses
+    #[clap(long)]
+    max_nodes: Option<usize>,
+
+    /// Give up solving after this many logic iterations
+    #[clap(long)]
+    max_iterations: Option<usize>,
+
+    /// Print solver statistics as a table or JSON
+    #[clap(long, value_enum)]
+    stats: Option<StatsFormat>,
+
+    /// CSV puzzle collections start with a header row
+    #[clap(long)]
+    csv_header: bool,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum StatsFormat {
+    Table,
+    Json,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run an HTTP solving service
+    Serve {
+        /// Address to listen on
+        #[clap(short, long, default_value = "127.0.0.1:8080")]
+        listen: String,
+    },
+
+    /// Group equivalent puzzles in a collection
+    Dedupe {
+        /// Puzzle files, each holding one grid puzzle, one 81 character
+        /// puzzle per line, or CSV puzzles
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Solve a Samurai or other overlapping multi-grid puzzle
+    Multi {
+        /// Multi-grid puzzle file
+        file: PathBuf,
+    },
+
+    /// Check a puzzle is minimal and remove redundant givens
+    Minimize {
+        /// Puzzle file
+        file: PathBuf,
+    },
+
+    /// Explain why a puzzle has no solution
+    Diagnose {
+        /// Puzzle file
+        file: PathBuf,
+    },
+
+    /// Export puzzles to a CSV collection
+    ExportCsv {
+        /// Puzzle files, each holding one grid puzzle, one 81 character
+        /// puzzle per line, or CSV puzzles
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output CSV file
+        #[clap(short, long)]
+        output: PathBuf,
+    },
+
+    /// Render puzzles as SVG, or as printable pages with answer keys
+    Render {
+        /// Puzzle files, each holding one grid puzzle, one 81 character
+        /// puzzle per line, or CSV puzzles
+        #[clap(required = true)]
+        files: Vec<PathBuf>,
+
+        /// Output SVG file, numbered when there is more than one
+        #[clap(short, long)]
+        output: PathBuf,
+
+        /// Draw the solution in the empty cells
+        #[clap(long)]
+        solution: bool,
+
+        /// Draw the possible values in the empty cells
+        #[clap(long)]
+        pencil_marks: bool,
+
+        /// Lay out puzzles on printable pages followed by answer keys
+        #[clap(long)]
+        pages: bool,
+
+        /// Paper size of pages, a4 or letter
+        #[clap(long, default_value = "a4")]
+        paper: Paper,
+
+        /// Puzzles across a page
+        #[clap(long, default_value_t = 2)]
+        columns: usize,
+
+        /// Puzzles down a page
+        #[clap(long, default_value_t = 3)]
+        rows: usize,
+    },
 }
 
 fn main() -> Result<(), anyhow::Error> {
@@ -26,12 +146,79 @@ fn main() -> Result<(), anyhow::Error> {
 
     setup(&args)?;
 
-    let mut puzzle = Puzzle::new(&args.puzzle_file)?;
+    let limits = SolveLimits {
+        timeout: args.timeout_ms.map(Duration::from_millis),
+        max_nodes: args.max_nodes,
+        max_iterations: args.max_iterations,
+        cancel: None,
+    };
+
+    match &args.command {
+        Some(Command::Serve { listen }) => serve::run(listen, &limits),
+        Some(Command::Dedupe { files }) => dedupe(files, args.csv_header),
+        Some(Command::Minimize { file }) => minimize(file),
+        Some(Command::Multi { file }) => solve_multi(file, &limits),
+        Some(Command::Diagnose { file }) => diagnose_file(file),
+        Some(Command::ExportCsv { files, output }) => export_csv(files, output, args.csv_header),
+        Some(Command::Render {
+            files,
+            output,
+            solution,
+            pencil_marks,
+            pages,
+            paper,
+            columns,
+            rows,
+        }) => {
+            let options = RenderArgs {
+                solution: *solution,
+                pencil_marks: *pencil_marks,
+                csv_header: args.csv_header,
+                layout: pages.then(|| PageLayout {
+                    paper: *paper,
+                    columns: *columns,
+                    rows: *rows,
+                }),
+            };
+            render(files, output, &options)
+        }
+        None => {
+            let puzzle_file = args
+                .puzzle_file
+                .as_ref()
+                .ok_or_else(|| anyhow!("A puzzle file is required, see --help"))?;
+            solve_file(puzzle_file, &limits, args.stats)
+        }
+    }
+}
+
+fn solve_file(
+    puzzle_file: &Path,
+    limits: &SolveLimits,
+    format: Option<StatsFormat>,
+) -> Result<(), anyhow::Error> {
+    let mut puzzle = Puzzle::new(puzzle_file)?;
 
     info!("Using puzzle:\n{}", puzzle);
     debug!("Using puzzle deubg:\n{:?}", puzzle);
 
-    match puzzle.solve() {
+    let givens = puzzle.clone();
+
+    // Only gather statistics when they are reported
+    let mut stats = SolveStats::default();
+    let result = match format {
+        Some(_) => puzzle.solve_with_stats(limits, &mut stats),
+        None => puzzle.solve_with_limits(limits),
+    };
+    match format {
+        Some(StatsFormat::Table) => print_stats_table(&stats),
+        Some(StatsFormat::Json) => {
+            println!("{}", serde_json::to_string(&StatsReport::new(&stats))?)
+        }
+        None => {}
+    }
+
+    match result {
         Ok(iters) => {
             info!("Solved puzzle iterations: {}\n{}", iters, puzzle);
             Ok(())
@@ -40,11 +227,338 @@ fn main() -> Result<(), anyhow::Error> {
             error!("Failed to solve puzzle: {:?}\n{}", e, puzzle);
             error!("Error puzzle state:\n{:?}", puzzle);
             error!("Total iterations: {}", iter);
+            // The solver also gives up on some puzzles that have a
+            // solution, only those without one have givens to diagnose
+            if matches!(e, SudokuError::PuzzleUnsolvable) {
+                if givens.count_solutions(1) == 0 {
+                    error!(
+                        "Run 'sudoku diagnose {}' to find the givens that contradict",
+                        puzzle_file.display()
+                    );
+                } else {
+                    error!("The puzzle has a solution, but the solver gave up on it");
+                }
+            }
+            Err(e.into())
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct PassReport {
+    pass: String,
+    calls: usize,
+    elapsed_us: u128,
+    eliminated: usize,
+    resolved: usize,
+}
+
+#[derive(Serialize)]
+struct StatsReport {
+    iterations: usize,
+    nodes: usize,
+    clones: usize,
+    max_depth: usize,
+    elapsed_us: u128,
+    passes: Vec<PassReport>,
+}
+
+impl StatsReport {
+    fn new(stats: &SolveStats) -> Self {
+        Self {
+            iterations: stats.iterations,
+            nodes: stats.nodes,
+            clones: stats.clones,
+            max_depth: stats.max_depth,
+            elapsed_us: stats.elapsed.as_micros(),
+            passes: Pass::ALL
+                .iter()
+                .map(|pass| {
+                    let p = stats.pass(*pass);
+                    PassReport {
+                        pass: pass.to_string(),
+                        calls: p.calls,
+                        elapsed_us: p.elapsed.as_micros(),
+                        eliminated: p.eliminated,
+                        resolved: p.resolved,
+                    }
+                })
+                .collect(),
+        }
+    }
+}
+
+fn print_stats_table(stats: &SolveStats) {
+    let mut table = format!(
+        "{:<22} {:>8} {:>12} {:>11} {:>9}\n",
+        "pass", "calls", "time (us)", "eliminated", "resolved"
+    );
+    for pass in Pass::ALL {
+        let p = stats.pass(pass);
+        table.push_str(&format!(
+            "{:<22} {:>8} {:>12} {:>11} {:>9}\n",
+            pass.to_string(),
+            p.calls,
+            p.elapsed.as_micros(),
+            p.eliminated,
+            p.resolved
+        ));
+    }
+    info!(
+        "Solver statistics: iterations: {}, nodes: {}, clones: {}, max depth: {}, elapsed: {:?}\n{}",
+        stats.iterations, stats.nodes, stats.clones, stats.max_depth, stats.elapsed, table
+    );
+}
+
+// Read a puzzle collection file, returning (label, puzzle) pairs.
+// 'csv_header' tells if CSV files start with a header row.
+fn read_collection(path: &Path, csv_header: bool) -> Result<Vec<(String, Puzzle)>, anyhow::Error> {
+    if path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("csv"))
+    {
+        return Ok(Puzzle::from_csv_file(path, csv_header)?
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| (format!("{}#{}", path.display(), i + 1), p))
+            .collect());
+    }
+
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read puzzle file: {}", path.display()))?;
+
+    let lines: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(i, l)| (i, l.trim()))
+        .filter(|(_, l)| !l.is_empty())
+        .collect();
+
+    if lines.iter().all(|(_, l)| l.len() == 81) {
+        lines
+            .into_iter()
+            .map(|(i, l)| {
+                let label = format!("{}:{}", path.display(), i + 1);
+                let puzzle = l
+                    .parse()
+                    .with_context(|| format!("Failed to parse puzzle: {}", label))?;
+                Ok((label, puzzle))
+            })
+            .collect()
+    } else {
+        Ok(vec![(path.display().to_string(), Puzzle::new(path)?)])
+    }
+}
+
+fn dedupe(files: &[PathBuf], csv_header: bool) -> Result<(), anyhow::Error> {
+    let mut groups: HashMap<String, Vec<String>> = HashMap::new();
+    let mut order = Vec::new();
+    let mut total = 0;
+
+    for file in files {
+        for (label, puzzle) in read_collection(file, csv_header)? {
+            let key = puzzle.canonical_form().to_line();
+            debug!("{}: canonical {}", label, key);
+            let group = groups.entry(key.clone()).or_default();
+            if group.is_empty() {
+                order.push(key);
+            }
+            group.push(label);
+            total += 1;
+        }
+    }
+
+    for (index, key) in order.iter().enumerate() {
+        let members = &groups[key];
+        info!(
+            "Group {} ({} puzzles): {}\n  {}",
+            index + 1,
+            members.len(),
+            key,
+            members.join("\n  ")
+        );
+    }
+    info!("{} puzzles, {} distinct", total, order.len());
+
+    Ok(())
+}
+
+fn solve_multi(file: &Path, limits: &SolveLimits) -> Result<(), anyhow::Error> {
+    let mut puzzle = MultiPuzzle::new(file)?;
+
+    info!("Using puzzle:\n{}", puzzle);
+
+    match puzzle.solve_with_limits(limits) {
+        Ok(iters) => {
+            info!("Solved puzzle iterations: {}\n{}", iters, puzzle);
+            Ok(())
+        }
+        Err((e, iter)) => {
+            error!("Failed to solve puzzle: {:?}\n{}", e, puzzle);
+            error!("Total iterations: {}", iter);
             Err(e.into())
         }
     }
 }
 
+fn minimize(file: &Path) -> Result<(), anyhow::Error> {
+    let puzzle = Puzzle::new(file)?;
+
+    info!("Using puzzle:\n{}", puzzle);
+
+    let minimized = puzzle.minimize()?;
+    if minimized.redundant.is_empty() {
+        info!("Puzzle is minimal");
+        return Ok(());
+    }
+
+    for given in &minimized.redundant {
+        info!(
+            "Redundant given: row: {}, col: {}, val: {}",
+            given.row + 1,
+            given.col + 1,
+            given.val
+        );
+    }
+    info!(
+        "Removed {} redundant givens, minimal puzzle:\n{}",
+        minimized.redundant.len(),
+        minimized.puzzle
+    );
+
+    Ok(())
+}
+
+fn diagnose_file(file: &Path) -> Result<(), anyhow::Error> {
+    let text = fs::read_to_string(file)
+        .with_context(|| format!("Failed to read puzzle file: {}", file.display()))?;
+    let values = Puzzle::parse_values(&text)
+        .with_context(|| format!("Failed to parse puzzle file: {}", file.display()))?;
+
+    let diagnosis = match diagnose(&values) {
+        Some(diagnosis) => diagnosis,
+        None => {
+            info!("Puzzle has a solution");
+            return Ok(());
+        }
+    };
+
+    let cell = |r: usize, c: usize| format!("row {}, col {}", r + 1, c + 1);
+    let givens: Vec<String> = diagnosis
+        .givens
+        .iter()
+        .map(|g| format!("{}: {}", cell(g.row, g.col), g.val))
+        .collect();
+    info!(
+        "Puzzle has no solution, these {} givens contradict each other:\n  {}",
+        givens.len(),
+        givens.join("\n  ")
+    );
+
+    match diagnosis.contradiction {
+        Contradiction::Duplicate { val, cells } => info!(
+            "{} is at both {} and {}",
+            val,
+            cell(cells[0].0, cells[0].1),
+            cell(cells[1].0, cells[1].1)
+        ),
+        Contradiction::NoCandidates { row, col, blockers } => {
+            let blockers: Vec<String> = blockers
+                .iter()
+                .map(|b| format!("{} at {}", b.val, cell(b.row, b.col)))
+                .collect();
+            info!(
+                "No value is possible at {}:\n  {}",
+                cell(row, col),
+                blockers.join("\n  ")
+            )
+        }
+        Contradiction::NoPlace { val, house } => info!("{} has no place in {}", val, house),
+        Contradiction::Search => info!("Only guessing shows the contradiction"),
+    }
+
+    Ok(())
+}
+
+fn export_csv(files: &[PathBuf], output: &Path, csv_header: bool) -> Result<(), anyhow::Error> {
+    let mut puzzles = Vec::new();
+    for file in files {
+        puzzles.extend(
+            read_collection(file, csv_header)?
+                .into_iter()
+                .map(|(_, p)| p),
+        );
+    }
+
+    fs::write(output, write_csv(&puzzles))
+        .with_context(|| format!("Failed to write CSV file: {}", output.display()))?;
+    info!("Wrote {} puzzles to {}", puzzles.len(), output.display());
+
+    Ok(())
+}
+
+struct RenderArgs {
+    solution: bool,
+    pencil_marks: bool,
+    csv_header: bool,
+    layout: Option<PageLayout>,
+}
+
+// Output path for the n'th of several SVG files: out.svg -> out-1.svg
+fn numbered_path(output: &Path, n: usize) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = output
+        .extension()
+        .map_or("svg".into(), |e| e.to_string_lossy());
+    output.with_file_name(format!("{}-{}.{}", stem, n, ext))
+}
+
+fn render(files: &[PathBuf], output: &Path, args: &RenderArgs) -> Result<(), anyhow::Error> {
+    let mut puzzles = Vec::new();
+    for file in files {
+        puzzles.extend(read_collection(file, args.csv_header)?);
+    }
+
+    let svgs = match &args.layout {
+        Some(layout) => render_booklet(&puzzles, layout)?,
+        None => {
+            let mut svgs = Vec::with_capacity(puzzles.len());
+            for (label, puzzle) in &puzzles {
+                let solution = if args.solution {
+                    let mut solution = puzzle.clone();
+                    solution
+                        .solve()
+                        .map_err(|(e, _)| e)
+                        .with_context(|| format!("Failed to solve puzzle: {}", label))?;
+                    Some(solution)
+                } else {
+                    None
+                };
+                let options = SvgOptions {
+                    solution,
+                    pencil_marks: args.pencil_marks,
+                    ..Default::default()
+                };
+                svgs.push(puzzle.to_svg(&options));
+            }
+            svgs
+        }
+    };
+
+    for (i, svg) in svgs.iter().enumerate() {
+        let path = if svgs.len() == 1 {
+            output.to_path_buf()
+        } else {
+            numbered_path(output, i + 1)
+        };
+        fs::write(&path, svg)
+            .with_context(|| format!("Failed to write SVG file: {}", path.display()))?;
+        info!("Wrote {}", path.display());
+    }
+
+    Ok(())
+}
+
 fn setup(args: &Args) -> Result<(), anyhow::Error> {
     if std::env::var("RUST_LIB_BACKTRACE").is_err() {
         std::env::set_var("RUST_LIB_BACKTRACE", "1")
@@ -0,0 +1,142 @@
+//! Puzzle minimality checking and clue reduction
+
+use crate::element::GROUP_SIZE;
+use crate::error::SudokuError;
+use crate::puzzle::Puzzle;
+
+/// A given of a puzzle.  `row` and `col` are 0-based, `val` is 1-9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Given {
+    /// Row of the given
+    pub row: usize,
+
+    /// Column of the given
+    pub col: usize,
+
+    /// Value of the given
+    pub val: usize,
+}
+
+/// Result of `Puzzle::minimize`
+#[derive(Debug, Clone)]
+pub struct Minimized {
+    /// The minimal puzzle
+    pub puzzle: Puzzle,
+
+    /// Givens removed from the original puzzle
+    pub redundant: Vec<Given>,
+}
+
+fn givens(values: &[[usize; GROUP_SIZE]; GROUP_SIZE]) -> Vec<Given> {
+    let mut givens = Vec::new();
+    for (row, cols) in values.iter().enumerate() {
+        for (col, val) in cols.iter().enumerate() {
+            if *val != 0 {
+                givens.push(Given {
+                    row,
+                    col,
+                    val: *val,
+                });
+            }
+        }
+    }
+    givens
+}
+
+// Solution count with one given removed
+fn count_without(
+    values: &[[usize; GROUP_SIZE]; GROUP_SIZE],
+    given: &Given,
+) -> Result<usize, SudokuError> {
+    let mut values = *values;
+    values[given.row][given.col] = 0;
+    Ok(Puzzle::from_values(&values)?.count_solutions(2))
+}
+
+impl Puzzle {
+    /// Test if the puzzle is minimal: it has a unique solution and
+    /// removing any single given breaks uniqueness.
+    ///
+    /// The resolved cells are taken as the givens.
+    pub fn is_minimal(&self) -> Result<bool, SudokuError> {
+        self.validate()?;
+
+        let values = self.values();
+        for given in givens(&values) {
+            if count_without(&values, &given)? == 1 {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Remove redundant givens until the puzzle is minimal.
+    ///
+    /// Givens are tried in row major order, so the result is one of
+    /// possibly several minimal puzzles with the same solution.
+    pub fn minimize(&self) -> Result<Minimized, SudokuError> {
+        self.validate()?;
+
+        let mut values = self.values();
+        let mut redundant = Vec::new();
+        for given in givens(&values) {
+            if count_without(&values, &given)? == 1 {
+                values[given.row][given.col] = 0;
+                redundant.push(given);
+            }
+        }
+
+        Ok(Minimized {
+            puzzle: Puzzle::from_values(&values)?,
+            redundant,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str = "8...4629.\
+                          7....9..5\
+                          ..2..5...\
+                          .6.21.84.\
+                          .27.8.53.\
+                          .38.67.2.\
+                          ...4..6..\
+                          9..3.....\
+                          .4165...3";
+
+    #[test]
+    fn minimize_puzzle() {
+        let puzzle: Puzzle = PUZZLE.parse().unwrap();
+        assert!(!puzzle.is_minimal().unwrap());
+
+        let minimized = puzzle.minimize().unwrap();
+        assert!(!minimized.redundant.is_empty());
+        assert!(minimized.puzzle.is_minimal().unwrap());
+
+        // every removed given came from the original puzzle
+        let values = puzzle.values();
+        for given in &minimized.redundant {
+            assert_eq!(values[given.row][given.col], given.val);
+            assert_eq!(minimized.puzzle.values()[given.row][given.col], 0);
+        }
+
+        // and the solution is unchanged
+        let mut solved = puzzle.clone();
+        let mut min_solved = minimized.puzzle.clone();
+        solved.solve().unwrap();
+        min_solved.solve().unwrap();
+        assert_eq!(solved.values(), min_solved.values());
+    }
+
+    #[test]
+    fn minimize_not_unique() {
+        assert!(matches!(
+            Puzzle::default().minimize(),
+            Err(SudokuError::MultipleSolutions)
+        ));
+    }
+}
@@ -0,0 +1,485 @@
+//! Overlapping multi-grid puzzles, such as Samurai
+//!
+//! A `MultiPuzzle` is several 9x9 grids placed on a larger board, where
+//! grids may overlap.  Each grid is a `Puzzle` with the usual row,
+//! column and square constraints, and an overlapping cell must hold the
+//! same value in every grid covering it.
+//!
+//! The text format is a `layout:` line followed by each grid in the
+//! usual nine line format, in layout order:
+//!
+//! ```text
+//! layout: samurai
+//! 2 X X   X X 8   X 1 X
+//! ...
+//! ```
+//!
+//! The layout is either a known name (`samurai`, `twodoku`,
+//! `butterfly`) or the board offsets of each grid's top left cell as
+//! `row,col` pairs, e.g. `layout: 0,0 6,6`.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+#[cfg(feature = "fs")]
+use std::fs;
+#[cfg(feature = "fs")]
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context};
+
+use crate::element::GROUP_SIZE;
+use crate::error::SudokuError;
+use crate::limits::{Search, SolveLimits};
+use crate::puzzle::Puzzle;
+
+// (grid, row, col) of a grid cell
+type GridCell = (usize, usize, usize);
+
+/// Placement of the grids of a multi-grid puzzle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// Board (row, col) of the top left cell of each grid.  Offsets are
+    /// multiples of 3 so overlaps are made of whole squares.
+    pub offsets: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    /// Five grids, the centre one sharing a corner square with each of
+    /// the others
+    pub fn samurai() -> Self {
+        Self {
+            offsets: vec![(0, 0), (0, 12), (6, 6), (12, 0), (12, 12)],
+        }
+    }
+
+    /// Two grids sharing one corner square
+    pub fn twodoku() -> Self {
+        Self {
+            offsets: vec![(0, 0), (6, 6)],
+        }
+    }
+
+    /// Four grids on a 12x12 board, each overlapping the others
+    pub fn butterfly() -> Self {
+        Self {
+            offsets: vec![(0, 0), (0, 3), (3, 0), (3, 3)],
+        }
+    }
+
+    /// Check the layout has grids, and that they overlap in whole
+    /// squares
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        if self.offsets.is_empty() {
+            return Err(anyhow!("Layout has no grids"));
+        }
+        if self.offsets.iter().any(|(r, c)| r % 3 != 0 || c % 3 != 0) {
+            return Err(anyhow!("Layout offsets must be multiples of 3"));
+        }
+        Ok(())
+    }
+
+    /// Board size as (rows, cols)
+    pub fn size(&self) -> (usize, usize) {
+        let rows = self.offsets.iter().map(|o| o.0).max().unwrap_or(0) + GROUP_SIZE;
+        let cols = self.offsets.iter().map(|o| o.1).max().unwrap_or(0) + GROUP_SIZE;
+        (rows, cols)
+    }
+
+    // Every board cell mapped to the (grid, row, col) covering it
+    fn board_cells(&self) -> HashMap<(usize, usize), Vec<GridCell>> {
+        let mut cells: HashMap<_, Vec<_>> = HashMap::new();
+        for (g, (row, col)) in self.offsets.iter().enumerate() {
+            for r in 0..GROUP_SIZE {
+                for c in 0..GROUP_SIZE {
+                    cells.entry((row + r, col + c)).or_default().push((g, r, c));
+                }
+            }
+        }
+        cells
+    }
+}
+
+impl FromStr for Layout {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let layout = match s.trim() {
+            "samurai" => Self::samurai(),
+            "twodoku" => Self::twodoku(),
+            "butterfly" => Self::butterfly(),
+            offsets => {
+                let offsets = offsets
+                    .split_whitespace()
+                    .map(|pair| {
+                        let (row, col) = pair
+                            .split_once(',')
+                            .ok_or_else(|| anyhow!("Expected row,col offset: {}", pair))?;
+                        Ok((row.parse()?, col.parse()?))
+                    })
+                    .collect::<Result<Vec<(usize, usize)>, anyhow::Error>>()
+                    .with_context(|| format!("Unknown layout: {}", s.trim()))?;
+                Self { offsets }
+            }
+        };
+
+        layout.validate()?;
+        Ok(layout)
+    }
+}
+
+/// A puzzle made of several overlapping grids
+#[derive(Debug, Clone)]
+pub struct MultiPuzzle {
+    layout: Layout,
+    grids: Vec<Puzzle>,
+
+    // Board cells covered by more than one grid
+    shared: Vec<Vec<GridCell>>,
+}
+
+impl MultiPuzzle {
+    /// Create a new multi-grid puzzle from a file
+    #[cfg(feature = "fs")]
+    pub fn new(path: &Path) -> Result<Self, anyhow::Error> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Failed to open puzzle file: {}", path.display()))?;
+
+        let puzzle = text
+            .parse()
+            .with_context(|| format!("Failed to parse puzzle file: {}", path.display()))?;
+        Ok(puzzle)
+    }
+
+    /// Create a multi-grid puzzle from a layout and one puzzle per grid.
+    ///
+    /// Givens of overlapping cells may appear in any of the grids, but
+    /// must agree.
+    pub fn from_grids(layout: Layout, grids: Vec<Puzzle>) -> Result<Self, anyhow::Error> {
+        layout.validate()?;
+        if grids.len() != layout.offsets.len() {
+            return Err(anyhow!(
+                "Layout has {} grids, found {}",
+                layout.offsets.len(),
+                grids.len()
+            ));
+        }
+
+        let mut shared: Vec<_> = layout
+            .board_cells()
+            .into_values()
+            .filter(|cells| cells.len() > 1)
+            .collect();
+        shared.sort();
+
+        let mut puzzle = Self {
+            layout,
+            grids,
+            shared,
+        };
+        while puzzle.sync()? {}
+
+        Ok(puzzle)
+    }
+
+    /// The layout of the grids
+    pub fn layout(&self) -> &Layout {
+        &self.layout
+    }
+
+    /// The grids, in layout order
+    pub fn grids(&self) -> &[Puzzle] {
+        &self.grids
+    }
+
+    // Make overlapping cells agree, returning true if anything changed
+    fn sync(&mut self) -> Result<bool, SudokuError> {
+        let mut changed = false;
+
+        for cells in &self.shared {
+            let mask = cells.iter().fold(u16::MAX, |m, (g, r, c)| {
+                m & self.grids[*g].possible_mask(*r, *c)
+            });
+            if mask == 0 {
+                // A cell with no possibilities left is a contradiction of
+                // its own, otherwise report a value of the first cell the
+                // others rule out
+                let masks: Vec<u16> = cells
+                    .iter()
+                    .map(|(g, r, c)| self.grids[*g].possible_mask(*r, *c))
+                    .collect();
+                if masks.contains(&0) {
+                    return Err(SudokuError::PuzzleUnsolvable);
+                }
+                let (_, r, c) = cells[0];
+                let val = masks[0].trailing_zeros() as usize;
+                return Err(SudokuError::PuzzleStateInconsistent(r, c, val));
+            }
+
+            for (g, r, c) in cells {
+                let grid = &mut self.grids[*g];
+                if grid.element(*r, *c).is_finalized() {
+                    continue;
+                }
+                if mask.count_ones() == 1 {
+                    grid.finalize_element(*r, *c, mask.trailing_zeros() as usize)?;
+                    changed = true;
+                } else {
+                    changed |= grid.restrict(*r, *c, mask);
+                }
+            }
+        }
+
+        Ok(changed)
+    }
+
+    // Propagate within and between grids, then branch on the cell with
+    // the fewest possibilities.  Returns true when solved.
+    fn search(&mut self, search: &mut Search) -> Result<bool, SudokuError> {
+        loop {
+            let mut solved = true;
+            for grid in &mut self.grids {
+                solved &= grid.propagate(search)?;
+            }
+            if !self.sync()? {
+                if solved {
+                    return Ok(true);
+                }
+                break;
+            }
+        }
+
+        let mut best: Option<(usize, usize, usize, u16)> = None;
+        for (g, grid) in self.grids.iter().enumerate() {
+            for (r, vals) in grid.values().iter().enumerate() {
+                for (c, val) in vals.iter().enumerate() {
+                    if *val != 0 {
+                        continue;
+                    }
+                    let mask = grid.possible_mask(r, c);
+                    let better = match best {
+                        Some(b) => mask.count_ones() < b.3.count_ones(),
+                        None => true,
+                    };
+                    if better {
+                        best = Some((g, r, c, mask));
+                    }
+                }
+            }
+        }
+
+        let (g, r, c, mask) = match best {
+            Some(best) => best,
+            None => return Ok(false),
+        };
+        for v in (0..GROUP_SIZE).filter(|v| mask & (1 << v) != 0) {
+            search.stats.nodes += 1;
+            search.check()?;
+
+            let mut next = self.clone();
+            if next.grids[g].finalize_element(r, c, v).is_err() {
+                continue;
+            }
+            search.enter();
+            let result = next.search(search);
+            search.leave();
+            match result {
+                Ok(true) => {
+                    *self = next;
+                    return Ok(true);
+                }
+                Err(e @ SudokuError::LimitExceeded { .. }) => return Err(e),
+                _ => {}
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Attempt to solve the puzzle
+    pub fn solve(&mut self) -> Result<usize, (SudokuError, usize)> {
+        self.solve_with_limits(&SolveLimits::default())
+    }
+
+    /// Attempt to solve the puzzle, giving up with
+    /// `SudokuError::LimitExceeded` once any of `limits` is reached
+    pub fn solve_with_limits(
+        &mut self,
+        limits: &SolveLimits,
+    ) -> Result<usize, (SudokuError, usize)> {
+        let mut search = Search::new(limits);
+        let mut puzzle = self.clone();
+
+        match puzzle.search(&mut search) {
+            Ok(true) => {
+                *self = puzzle;
+                Ok(search.stats.iterations)
+            }
+            Err(e @ SudokuError::LimitExceeded { .. }) => Err((e, search.stats.iterations)),
+            _ => Err((SudokuError::PuzzleUnsolvable, search.stats.iterations)),
+        }
+    }
+}
+
+impl FromStr for MultiPuzzle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().map(str::trim).filter(|l| !l.is_empty());
+
+        let layout: Layout = lines
+            .next()
+            .and_then(|l| l.strip_prefix("layout:"))
+            .ok_or_else(|| anyhow!("Expected layout: line"))?
+            .parse()?;
+
+        let lines: Vec<&str> = lines.collect();
+        if lines.len() != layout.offsets.len() * GROUP_SIZE {
+            return Err(anyhow!(
+                "Expected {} grids of {} rows, found {} rows",
+                layout.offsets.len(),
+                GROUP_SIZE,
+                lines.len()
+            ));
+        }
+
+        let grids = lines
+            .chunks(GROUP_SIZE)
+            .enumerate()
+            .map(|(i, rows)| {
+                rows.join("\n")
+                    .parse()
+                    .with_context(|| format!("Failed to parse grid {}", i + 1))
+            })
+            .collect::<Result<Vec<Puzzle>, anyhow::Error>>()?;
+
+        Self::from_grids(layout, grids)
+    }
+}
+
+impl Display for MultiPuzzle {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (rows, cols) = self.layout.size();
+        let mut board = vec![vec![None; cols]; rows];
+        for ((row, col), grid) in self.layout.offsets.iter().zip(&self.grids) {
+            for (r, vals) in grid.values().iter().enumerate() {
+                for (c, val) in vals.iter().enumerate() {
+                    board[row + r][col + c] = Some(*val);
+                }
+            }
+        }
+
+        for (i, line) in board.iter().enumerate() {
+            for (j, cell) in line.iter().enumerate() {
+                match cell {
+                    Some(0) => write!(f, "X ")?,
+                    Some(v) => write!(f, "{} ", v)?,
+                    None => write!(f, "  ")?,
+                }
+                if ((j + 1) % 3) == 0 {
+                    write!(f, "  ")?;
+                }
+            }
+            writeln!(f)?;
+            if ((i + 1) % 3) == 0 && i + 1 != rows {
+                writeln!(f)?;
+            }
+        }
+        write!(f, "")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn empty(layout: Layout) -> MultiPuzzle {
+        let grids = vec![Puzzle::default(); layout.offsets.len()];
+        MultiPuzzle::from_grids(layout, grids).unwrap()
+    }
+
+    fn check_solved(puzzle: &MultiPuzzle) {
+        for grid in puzzle.grids() {
+            let values = grid.values();
+            assert!(values.iter().flatten().all(|v| *v != 0));
+            assert!(Puzzle::from_values(&values).is_ok());
+        }
+        for cells in &puzzle.shared {
+            let (g, r, c) = cells[0];
+            let val = puzzle.grids[g].values()[r][c];
+            for (g, r, c) in cells {
+                assert_eq!(puzzle.grids[*g].values()[*r][*c], val);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_layout() {
+        assert_eq!("samurai".parse::<Layout>().unwrap(), Layout::samurai());
+        assert_eq!(" 0,0 6,6 ".parse::<Layout>().unwrap(), Layout::twodoku());
+        assert_eq!(Layout::samurai().size(), (21, 21));
+        assert!("0,0 1,6".parse::<Layout>().is_err());
+        assert!("dodecadoku".parse::<Layout>().is_err());
+    }
+
+    #[test]
+    fn solve_samurai() {
+        // Solve an empty board, blank out most cells and solve again
+        let mut full = empty(Layout::samurai());
+        full.solve().unwrap();
+        check_solved(&full);
+
+        let mut text = String::from("layout: samurai\n");
+        for (g, grid) in full.grids().iter().enumerate() {
+            let mut values = grid.values();
+            for (i, val) in values.iter_mut().flatten().enumerate() {
+                if (i + g) % 3 != 0 {
+                    *val = 0;
+                }
+            }
+            text.push_str(&Puzzle::from_values(&values).unwrap().to_string());
+            text.push('\n');
+        }
+
+        let mut puzzle: MultiPuzzle = text.parse().unwrap();
+        assert!(puzzle.to_string().lines().count() > 21);
+        puzzle.solve().unwrap();
+        check_solved(&puzzle);
+    }
+
+    #[test]
+    fn shared_givens_conflict() {
+        // Bottom right square of grid 1 is top left of grid 2
+        let mut first = [[0; GROUP_SIZE]; GROUP_SIZE];
+        let mut second = [[0; GROUP_SIZE]; GROUP_SIZE];
+        first[8][8] = 1;
+        second[2][2] = 2;
+        let grids = vec![
+            Puzzle::from_values(&first).unwrap(),
+            Puzzle::from_values(&second).unwrap(),
+        ];
+        assert!(MultiPuzzle::from_grids(Layout::twodoku(), grids).is_err());
+    }
+
+    #[test]
+    fn shared_cell_without_possibilities() {
+        let mut first = Puzzle::default();
+        first.restrict(8, 8, 0);
+        let grids = vec![first, Puzzle::default()];
+        let e = MultiPuzzle::from_grids(Layout::twodoku(), grids).unwrap_err();
+        assert!(matches!(
+            e.downcast_ref::<SudokuError>(),
+            Some(SudokuError::PuzzleUnsolvable)
+        ));
+    }
+
+    #[test]
+    fn from_grids_checks_layout() {
+        let layout = Layout {
+            offsets: vec![(0, 0), (1, 6)],
+        };
+        let grids = vec![Puzzle::default(); 2];
+        assert!(MultiPuzzle::from_grids(layout, grids).is_err());
+        assert!(MultiPuzzle::from_grids(Layout { offsets: vec![] }, vec![]).is_err());
+    }
+}
@@ -4,20 +4,63 @@ use std::borrow::Borrow;
 use std::cell::{Ref, RefCell, RefMut};
 use std::fmt::Debug;
 use std::fmt::Display;
+#[cfg(feature = "fs")]
 use std::fs::File;
+#[cfg(feature = "fs")]
 use std::io::prelude::*;
+#[cfg(feature = "fs")]
 use std::io::BufReader;
+#[cfg(feature = "fs")]
 use std::path::Path;
+use std::str::FromStr;
+
+use web_time::Instant;
 
 use anyhow::{anyhow, Context};
 use bit_set::BitSet;
 use tracing::debug;
 
+use crate::chains::{self, Candidate, Candidates, Deduction, Technique};
 use crate::element::{Element, GROUP_SIZE};
+use crate::limits::{Pass, Search, SolveLimits, SolveStats};
 use crate::prelude::SudokuError;
 
 const NUM_ELEMENTS: usize = GROUP_SIZE * GROUP_SIZE;
 
+// Only elements with at most this many possibilities are tried by the
+// trial with contradiction tier
+const MAX_TRIAL_POSSIBLE: usize = 2;
+
+/// A value for an empty cell, from `Puzzle::hint`.  `row` and `col`
+/// are 0-based, `val` is 1-9.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Hint {
+    /// Row of the cell
+    pub row: usize,
+
+    /// Column of the cell
+    pub col: usize,
+
+    /// Value for the cell
+    pub val: usize,
+}
+
+/// Rough difficulty rating of a puzzle
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Difficulty {
+    /// Solvable by the basic logic passes alone
+    Easy,
+
+    /// Requires chain techniques
+    Medium,
+
+    /// Requires trial with contradiction (nishio or forcing chains)
+    Hard,
+
+    /// Requires guessing
+    Expert,
+}
+
 /// Represents a sudoku puzzle
 #[derive(Clone)]
 pub struct Puzzle {
@@ -44,6 +87,19 @@ impl Debug for Puzzle {
     }
 }
 
+impl FromStr for Puzzle {
+    type Err = anyhow::Error;
+
+    /// Parse a puzzle from text.
+    ///
+    /// Two formats are accepted: the nine line grid used by the puzzle
+    /// files, or a single line of 81 characters where blanks are
+    /// written as `.`, `0` or `X`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Self::from_values(&Self::parse_values(s)?)?)
+    }
+}
+
 // Map from square number to array of element coordinates (row, col)
 // for that square.  Note: The (row, col) is 1-based.
 const SQR_TO_ROW_COL_MAP: [[(usize, usize); 9]; 9] = [
@@ -185,6 +241,7 @@ fn map_row_col_to_sqr_index(row: usize, col: usize) -> usize {
 
 impl Puzzle {
     /// Create a new puzzle from a file
+    #[cfg(feature = "fs")]
     pub fn new(path: &Path) -> Result<Self, anyhow::Error> {
         let file = File::open(path)
             .with_context(|| format!("Failed to open puzzle file: {}", path.display()))?;
@@ -203,13 +260,29 @@ impl Puzzle {
             puzzle_lines.push(line);
         }
 
-        let puzzle = Self::parse_puzzle(puzzle_lines)
+        let values = Self::grid_values(&puzzle_lines)
+            .with_context(|| format!("Failed to parse puzzle file: {}", path.display()))?;
+        let puzzle = Self::from_values(&values)
             .with_context(|| format!("Failed to parse puzzle file: {}", path.display()))?;
         Ok(puzzle)
     }
 
-    fn parse_puzzle(puzzle_lines: Vec<String>) -> Result<Self, anyhow::Error> {
-        let mut puzzle = Puzzle::default();
+    /// Parse puzzle text like `from_str`, returning the cell values, 1-9
+    /// for givens and 0 for blanks, without checking the sudoku rules
+    pub fn parse_values(s: &str) -> Result<[[usize; GROUP_SIZE]; GROUP_SIZE], anyhow::Error> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        if fields.len() == 1 && fields[0].chars().count() == NUM_ELEMENTS {
+            Self::line_values(fields[0])
+        } else {
+            let lines: Vec<String> = s.lines().map(|l| l.trim().to_string()).collect();
+            Self::grid_values(&lines)
+        }
+    }
+
+    fn grid_values(
+        puzzle_lines: &[String],
+    ) -> Result<[[usize; GROUP_SIZE]; GROUP_SIZE], anyhow::Error> {
+        let mut values = [[0; GROUP_SIZE]; GROUP_SIZE];
         let mut row = 0;
 
         for (index, line) in puzzle_lines.iter().enumerate() {
@@ -218,6 +291,13 @@ impl Puzzle {
                 continue;
             }
 
+            if row == GROUP_SIZE {
+                return Err(anyhow!(format!(
+                    "Too many rows in puzzle line:{}",
+                    index + 1
+                )));
+            }
+
             let fields: Vec<&str> = line.split_whitespace().collect();
             if fields.len() != GROUP_SIZE {
                 return Err(anyhow!(format!(
@@ -246,7 +326,7 @@ impl Puzzle {
                             col + 1
                         )));
                     }
-                    puzzle.finalize_element(row, col, val - 1)?;
+                    values[row][col] = val;
                 }
             }
             row += 1;
@@ -256,9 +336,117 @@ impl Puzzle {
             return Err(anyhow!(format!("Not enough rows in puzzle: {}", row)));
         }
 
+        Ok(values)
+    }
+
+    fn line_values(line: &str) -> Result<[[usize; GROUP_SIZE]; GROUP_SIZE], anyhow::Error> {
+        let mut values = [[0; GROUP_SIZE]; GROUP_SIZE];
+
+        for (index, ch) in line.chars().enumerate() {
+            let (row, col) = (index / GROUP_SIZE, index % GROUP_SIZE);
+            match ch {
+                '.' | '0' | 'X' | 'x' => continue,
+                '1'..='9' => {
+                    values[row][col] = ch.to_digit(10).unwrap() as usize;
+                }
+                _ => {
+                    return Err(anyhow!(format!(
+                        "Unable to parse element: {} at row:col: {}:{}",
+                        ch,
+                        row + 1,
+                        col + 1
+                    )));
+                }
+            }
+        }
+
+        Ok(values)
+    }
+
+    /// Create a puzzle from cell values, 1-9 for givens and 0 for blanks
+    pub fn from_values(values: &[[usize; GROUP_SIZE]; GROUP_SIZE]) -> Result<Self, SudokuError> {
+        let mut puzzle = Puzzle::default();
+
+        for (r, row) in values.iter().enumerate() {
+            for (c, val) in row.iter().enumerate() {
+                match *val {
+                    0 => {}
+                    v if v <= GROUP_SIZE => puzzle.finalize_element(r, c, v - 1)?,
+                    value => {
+                        return Err(SudokuError::InvalidValue {
+                            row: r,
+                            col: c,
+                            value,
+                        })
+                    }
+                }
+            }
+        }
+
         Ok(puzzle)
     }
 
+    /// Format the puzzle as a single line of 81 characters, `.` for blanks
+    pub fn to_line(&self) -> String {
+        self.values()
+            .iter()
+            .flatten()
+            .map(|v| match v {
+                0 => '.',
+                v => char::from_digit(*v as u32, 10).unwrap(),
+            })
+            .collect()
+    }
+
+    /// Return the cell values, 1-9 for resolved cells and 0 otherwise
+    pub fn values(&self) -> [[usize; GROUP_SIZE]; GROUP_SIZE] {
+        let mut values = [[0; GROUP_SIZE]; GROUP_SIZE];
+
+        for (r, row) in values.iter_mut().enumerate() {
+            for (c, value) in row.iter_mut().enumerate() {
+                if let Some(v) = self.element(r, c).resolved() {
+                    *value = v + 1;
+                }
+            }
+        }
+
+        values
+    }
+
+    /// Rate the difficulty of the puzzle.
+    ///
+    /// The rating is made on a copy, the puzzle itself is unchanged.
+    pub fn difficulty(&self) -> Difficulty {
+        self.difficulty_with_limits(&SolveLimits::default())
+            .unwrap_or(Difficulty::Expert)
+    }
+
+    /// Rate the difficulty of the puzzle like `difficulty`, giving up
+    /// with `SudokuError::LimitExceeded` once any of `limits` is reached
+    pub fn difficulty_with_limits(&self, limits: &SolveLimits) -> Result<Difficulty, SudokuError> {
+        let mut search = Search::new(limits);
+
+        search.chains = false;
+        match self.clone().logic_solve(&mut search) {
+            Ok(_) => return Ok(Difficulty::Easy),
+            Err((e @ SudokuError::LimitExceeded { .. }, _)) => return Err(e),
+            Err(_) => {}
+        }
+
+        search.chains = true;
+        match self.clone().logic_solve(&mut search) {
+            Ok(_) => return Ok(Difficulty::Medium),
+            Err((e @ SudokuError::LimitExceeded { .. }, _)) => return Err(e),
+            Err(_) => {}
+        }
+
+        match self.clone().trial_solve(&mut search) {
+            Ok(_) => Ok(Difficulty::Hard),
+            Err((e @ SudokuError::LimitExceeded { .. }, _)) => Err(e),
+            Err(_) => Ok(Difficulty::Expert),
+        }
+    }
+
     // remove 'val' from every column of 'row'
     fn row_remove_possible(&mut self, row: usize, col: usize, val: usize) {
         for c in 0..GROUP_SIZE {
@@ -325,7 +513,12 @@ impl Puzzle {
         Ok(())
     }
 
-    fn finalize_element(&mut self, row: usize, col: usize, val: usize) -> Result<(), SudokuError> {
+    pub(crate) fn finalize_element(
+        &mut self,
+        row: usize,
+        col: usize,
+        val: usize,
+    ) -> Result<(), SudokuError> {
         self.check_inconsistent(row, col, val)?;
 
         self.element_as_mut(row, col).finalize(val);
@@ -338,7 +531,7 @@ impl Puzzle {
         Ok(())
     }
 
-    fn element(&self, row: usize, col: usize) -> Ref<'_, Element> {
+    pub(crate) fn element(&self, row: usize, col: usize) -> Ref<'_, Element> {
         self.elements[(row * GROUP_SIZE) + col].borrow()
     }
 
@@ -575,18 +768,101 @@ impl Puzzle {
         Ok(updates)
     }
 
-    fn reduce(&mut self) -> Result<(), SudokuError> {
+    // Candidate masks of the unresolved elements
+    pub(crate) fn candidates(&self) -> Candidates {
+        let mut cands = [[0; GROUP_SIZE]; GROUP_SIZE];
+
+        for (r, row) in cands.iter_mut().enumerate() {
+            for (c, mask) in row.iter_mut().enumerate() {
+                let element = self.element(r, c);
+                if !element.is_finalized() {
+                    *mask = element.possible().iter().fold(0, |m, v| m | (1 << v));
+                }
+            }
+        }
+
+        cands
+    }
+
+    /// Find the eliminations the chain techniques (XY-Wing, XYZ-Wing,
+    /// W-Wing and coloring) can make in the current state.
+    ///
+    /// Nothing is applied to the puzzle.
+    pub fn chain_deductions(&self) -> Vec<Deduction> {
+        chains::deductions(&self.candidates())
+    }
+
+    // Apply the first chain deduction found, returning the number of
+    // candidates eliminated
+    fn chain_scan(&mut self) -> usize {
+        match chains::first_deductions(&self.candidates())
+            .into_iter()
+            .next()
+        {
+            Some(deduction) => {
+                debug!("chain_scan: {:?}", deduction);
+                for e in &deduction.eliminations {
+                    self.element_as_mut(e.row, e.col).remove(e.val - 1);
+                }
+                deduction.eliminations.len()
+            }
+            None => 0,
+        }
+    }
+
+    // Total possibilities and resolved elements
+    fn census(&self) -> (usize, usize) {
+        self.elements.iter().fold((0, 0), |(p, r), e| {
+            let e = e.borrow();
+            (p + e.possible_len(), r + e.is_finalized() as usize)
+        })
+    }
+
+    // Run a logic solver pass, recording its statistics
+    fn run_pass(
+        &mut self,
+        search: &mut Search,
+        pass: Pass,
+        f: fn(&mut Self) -> Result<usize, SudokuError>,
+    ) -> Result<usize, SudokuError> {
+        if !search.pass_stats {
+            return f(self);
+        }
+
+        let (possible, resolved) = self.census();
+        let start = Instant::now();
+
+        let result = f(self);
+
+        let (possible_after, resolved_after) = self.census();
+        let stats = search.stats.pass_mut(pass);
+        stats.calls += 1;
+        stats.elapsed += start.elapsed();
+        stats.eliminated += possible.saturating_sub(possible_after);
+        stats.resolved += resolved_after.saturating_sub(resolved);
+
+        result
+    }
+
+    fn reduce(&mut self, search: &mut Search) -> Result<(), SudokuError> {
         debug!("Before reduce_basic_elements(): {:?}", &self);
-        let mut updates = self.reduce_basic_elements()?;
+        let mut updates = self.run_pass(search, Pass::ReduceBasicElements, |p| {
+            p.reduce_basic_elements()
+        })?;
 
         debug!("Before row_scan(): updates: {}, {:?}", updates, &self);
-        updates += self.row_scan()?;
+        updates += self.run_pass(search, Pass::RowScan, |p| p.row_scan())?;
 
         debug!("Before col_scan(): updates: {}, {:?}", updates, &self);
-        updates += self.col_scan()?;
+        updates += self.run_pass(search, Pass::ColScan, |p| p.col_scan())?;
 
         debug!("Before sqr_scan(): updates: {}, {:?}", updates, &self);
-        updates += self.sqr_scan()?;
+        updates += self.run_pass(search, Pass::SqrScan, |p| p.sqr_scan())?;
+
+        if updates == 0 && search.chains {
+            debug!("Before chain_scan(): {:?}", &self);
+            updates += self.run_pass(search, Pass::ChainScan, |p| Ok(p.chain_scan()))?;
+        }
 
         if self.is_complete() {
             // Time to stop
@@ -600,7 +876,7 @@ impl Puzzle {
         }
     }
 
-    fn logic_solve(&mut self) -> Result<usize, (SudokuError, usize)> {
+    fn logic_solve(&mut self, search: &mut Search) -> Result<usize, (SudokuError, usize)> {
         let mut iters = 0;
 
         // loop while !puzzle.solved()
@@ -614,12 +890,20 @@ impl Puzzle {
         loop {
             debug!("Iter: {}\n{}", iters, &self);
 
-            match self.reduce() {
+            if let Err(e) = search.check() {
+                result = Err((e, iters));
+                break;
+            }
+
+            match self.reduce(search) {
                 Ok(()) => {
                     result = Ok(iters);
                     break;
                 }
-                Err(SudokuError::PuzzleUnsolved) => iters += 1,
+                Err(SudokuError::PuzzleUnsolved) => {
+                    iters += 1;
+                    search.stats.iterations += 1;
+                }
                 Err(e) => {
                     result = Err((e, iters));
                     break;
@@ -630,10 +914,213 @@ impl Puzzle {
         result
     }
 
-    /// Attempt to solve the puzzle
+    // Test if some unresolved element has no possibilities left
+    fn has_empty_element(&self) -> bool {
+        self.elements
+            .iter()
+            .any(|e| !e.borrow().is_finalized() && e.borrow().possible().is_empty())
+    }
+
+    // Assume 'val' at (row, col) and propagate.  Returns None on a
+    // contradiction, otherwise the resulting puzzle.
+    fn trial(
+        &self,
+        row: usize,
+        col: usize,
+        val: usize,
+        search: &mut Search,
+    ) -> Result<Option<Puzzle>, SudokuError> {
+        search.stats.nodes += 1;
+        search.check()?;
+
+        let mut puzzle = self.clone();
+        if puzzle.finalize_element(row, col, val).is_err() {
+            return Ok(None);
+        }
+        search.enter();
+        let result = puzzle.logic_solve(search);
+        search.leave();
+        match result {
+            Err((e @ SudokuError::LimitExceeded { .. }, _)) => Err(e),
+            Err((SudokuError::PuzzleStateInconsistent(..), _)) => Ok(None),
+            _ if puzzle.has_empty_element() => Ok(None),
+            _ => Ok(Some(puzzle)),
+        }
+    }
+
+    // Try each possibility of each unresolved element with two
+    // possibilities.  A possibility leading to a contradiction is
+    // eliminated (nishio).  When every possibility of an element leads
+    // to the same value somewhere else, that value is forced.
+    fn trial_scan(&self, search: &mut Search) -> Result<Option<Deduction>, SudokuError> {
+        let cells: Vec<(usize, usize)> = (0..NUM_ELEMENTS)
+            .map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+            .filter(|(r, c)| {
+                let element = self.element(*r, *c);
+                !element.is_finalized()
+                    && (2..=MAX_TRIAL_POSSIBLE).contains(&element.possible().len())
+            })
+            .collect();
+
+        for (r, c) in cells {
+            let possible = self.element(r, c).possible();
+            let mut outcomes = Vec::new();
+
+            for v in possible.iter() {
+                match self.trial(r, c, v, search)? {
+                    Some(puzzle) => outcomes.push(puzzle.values()),
+                    None => {
+                        return Ok(Some(Deduction {
+                            technique: Technique::Nishio,
+                            cells: vec![(r, c)],
+                            eliminations: vec![Candidate {
+                                row: r,
+                                col: c,
+                                val: v + 1,
+                            }],
+                        }));
+                    }
+                }
+            }
+
+            let current = self.values();
+            for i in 0..NUM_ELEMENTS {
+                let (r2, c2) = (i / GROUP_SIZE, i % GROUP_SIZE);
+                let val = outcomes[0][r2][c2];
+                if current[r2][c2] != 0 || val == 0 || outcomes.iter().any(|o| o[r2][c2] != val) {
+                    continue;
+                }
+                let eliminations: Vec<Candidate> = self
+                    .element(r2, c2)
+                    .possible()
+                    .iter()
+                    .filter(|v| v + 1 != val)
+                    .map(|v| Candidate {
+                        row: r2,
+                        col: c2,
+                        val: v + 1,
+                    })
+                    .collect();
+                if !eliminations.is_empty() {
+                    return Ok(Some(Deduction {
+                        technique: Technique::ForcingChain,
+                        cells: vec![(r, c), (r2, c2)],
+                        eliminations,
+                    }));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Find the next deduction of the trial with contradiction tier:
+    /// either a possibility that leads to a contradiction (nishio), or a
+    /// value forced by every possibility of some element (forcing chain).
+    ///
+    /// Nothing is applied to the puzzle.
+    pub fn trial_deduction(&self) -> Option<Deduction> {
+        let mut search = Search::new(&SolveLimits::default());
+        self.trial_scan(&mut search).unwrap_or(None)
+    }
+
+    // Alternate the logic solver and the trial tier until solved or
+    // neither makes progress
+    fn trial_solve(&mut self, search: &mut Search) -> Result<usize, (SudokuError, usize)> {
+        let mut iterations = 0;
+
+        loop {
+            match self.logic_solve(search) {
+                Ok(iter) => return Ok(iterations + iter),
+                Err((SudokuError::PuzzleUnsolvable, iter)) => iterations += iter,
+                Err((e, iter)) => return Err((e, iterations + iter)),
+            }
+
+            match self.trial_scan(search) {
+                Ok(Some(deduction)) => {
+                    debug!("trial_scan: {:?}", deduction);
+                    for e in &deduction.eliminations {
+                        self.element_as_mut(e.row, e.col).remove(e.val - 1);
+                    }
+                }
+                Ok(None) => return Err((SudokuError::PuzzleUnsolvable, iterations)),
+                Err(e) => return Err((e, iterations)),
+            }
+        }
+    }
+
+    // Run the logic solver until stuck.  Returns true when solved, false
+    // when stuck, or the error on a contradiction
+    pub(crate) fn propagate(&mut self, search: &mut Search) -> Result<bool, SudokuError> {
+        match self.logic_solve(search) {
+            Ok(_) => Ok(true),
+            Err((SudokuError::PuzzleUnsolvable, _)) if !self.has_empty_element() => Ok(false),
+            Err((e, _)) => Err(e),
+        }
+    }
+
+    /// Possible values of an element as a bit mask, bit `v - 1` set for
+    /// each possible value `v`.  A resolved element has only its value
+    /// set.  `row` and `col` are 0-based.
+    pub fn possible_mask(&self, row: usize, col: usize) -> u16 {
+        self.element(row, col)
+            .possible()
+            .iter()
+            .fold(0, |m, v| m | (1 << v))
+    }
+
+    // Remove the possibilities of an unresolved element not in 'mask',
+    // returning true if any were removed
+    pub(crate) fn restrict(&mut self, row: usize, col: usize, mask: u16) -> bool {
+        let mut element = self.element_as_mut(row, col);
+        let remove: Vec<usize> = element
+            .possible()
+            .iter()
+            .filter(|v| mask & (1 << v) == 0)
+            .collect();
+        for v in &remove {
+            element.remove(*v);
+        }
+        !remove.is_empty()
+    }
+
+    /// Attempt to solve the puzzle.
+    ///
+    /// A solution found satisfies the givens and the sudoku rules, but
+    /// the solver guesses only one cell deep, so it may fail with
+    /// `PuzzleUnsolvable` on a puzzle that has a solution.
+    /// `count_solutions` searches completely.
     pub fn solve(&mut self) -> Result<usize, (SudokuError, usize)> {
-        let mut iterations = match self.logic_solve() {
+        self.solve_with_limits(&SolveLimits::default())
+    }
+
+    /// Attempt to solve the puzzle, giving up with
+    /// `SudokuError::LimitExceeded` once any of `limits` is reached
+    pub fn solve_with_limits(
+        &mut self,
+        limits: &SolveLimits,
+    ) -> Result<usize, (SudokuError, usize)> {
+        self.solve_search(&mut Search::new(limits))
+    }
+
+    /// Attempt to solve the puzzle like `solve_with_limits`, filling in
+    /// `stats` whether solved or not
+    pub fn solve_with_stats(
+        &mut self,
+        limits: &SolveLimits,
+        stats: &mut SolveStats,
+    ) -> Result<usize, (SudokuError, usize)> {
+        let mut search = Search::new(limits);
+        search.pass_stats = true;
+        let result = self.solve_search(&mut search);
+        *stats = search.finish();
+        result
+    }
+
+    fn solve_search(&mut self, search: &mut Search) -> Result<usize, (SudokuError, usize)> {
+        let mut iterations = match self.trial_solve(search) {
             Ok(iter) => return Ok(iter),
+            Err((e @ SudokuError::LimitExceeded { .. }, iter)) => return Err((e, iter)),
             Err((_, iter)) => iter,
         };
 
@@ -646,16 +1133,27 @@ impl Puzzle {
                 }
                 let bitvec = self.element(r, c).borrow().possible().clone();
                 for v in bitvec.into_iter() {
+                    search.stats.nodes += 1;
+                    if let Err(e) = search.check() {
+                        return Err((e, iterations));
+                    }
+
                     let mut puzzle = self.clone();
                     if puzzle.finalize_element(r, c, v).is_err() {
                         continue;
                     }
-                    match puzzle.logic_solve() {
+                    search.enter();
+                    let result = puzzle.logic_solve(search);
+                    search.leave();
+                    match result {
                         Ok(iter) => {
                             // the guess worked
                             *self = puzzle;
                             return Ok(iterations + iter);
                         }
+                        Err((e @ SudokuError::LimitExceeded { .. }, iter)) => {
+                            return Err((e, iterations + iter));
+                        }
                         Err((_e, iter)) => {
                             // guess failed. or maybe need to guess more
                             iterations += iter;
@@ -667,15 +1165,135 @@ impl Puzzle {
 
         Err((SudokuError::PuzzleUnsolvable, iterations))
     }
+
+    /// Count the solutions of the puzzle, stopping once `limit` have
+    /// been found
+    pub fn count_solutions(&self, limit: usize) -> usize {
+        let mut count = 0;
+        self.clone()
+            .count_solutions_inner(limit, &mut count, &mut None);
+        count
+    }
+
+    // The values of the first solution found by the complete search of
+    // count_solutions, None when there is none
+    fn first_solution(&self) -> Option<[[usize; GROUP_SIZE]; GROUP_SIZE]> {
+        let mut solution = None;
+        self.clone().count_solutions_inner(1, &mut 0, &mut solution);
+        solution
+    }
+
+    /// Test if the puzzle has exactly one solution
+    pub fn has_unique_solution(&self) -> bool {
+        self.count_solutions(2) == 1
+    }
+
+    /// Check the puzzle has exactly one solution, returning
+    /// `PuzzleUnsolvable` or `MultipleSolutions` otherwise
+    pub fn validate(&self) -> Result<(), SudokuError> {
+        match self.count_solutions(2) {
+            0 => Err(SudokuError::PuzzleUnsolvable),
+            1 => Ok(()),
+            _ => Err(SudokuError::MultipleSolutions),
+        }
+    }
+
+    /// Suggest a value for an empty cell.
+    ///
+    /// A cell the logic passes resolve next is preferred, otherwise the
+    /// solution value of the empty cell with the fewest possibilities is
+    /// given.  Returns None when the puzzle is complete or has no
+    /// solution.
+    pub fn hint(&self) -> Option<Hint> {
+        let current = self.values();
+        let empty = |r: usize, c: usize| current[r][c] == 0;
+
+        let mut next = self.clone();
+        let mut search = Search::new(&SolveLimits::default());
+        search.chains = false;
+        if !matches!(
+            next.reduce(&mut search),
+            Err(SudokuError::PuzzleStateInconsistent(..))
+        ) {
+            let values = next.values();
+            let found = (0..NUM_ELEMENTS)
+                .map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+                .find(|(r, c)| empty(*r, *c) && values[*r][*c] != 0);
+            if let Some((row, col)) = found {
+                return Some(Hint {
+                    row,
+                    col,
+                    val: values[row][col],
+                });
+            }
+        }
+
+        let solution = self.first_solution()?;
+        (0..NUM_ELEMENTS)
+            .map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+            .filter(|(r, c)| empty(*r, *c))
+            .min_by_key(|(r, c)| self.element(*r, *c).possible_len())
+            .map(|(row, col)| Hint {
+                row,
+                col,
+                val: solution[row][col],
+            })
+    }
+
+    // Count solutions up to 'limit', keeping the values of the first one
+    // in 'first'
+    fn count_solutions_inner(
+        mut self,
+        limit: usize,
+        count: &mut usize,
+        first: &mut Option<[[usize; GROUP_SIZE]; GROUP_SIZE]>,
+    ) {
+        let mut search = Search::new(&SolveLimits::default());
+        match self.logic_solve(&mut search) {
+            Ok(_) => {
+                *count += 1;
+                if first.is_none() {
+                    *first = Some(self.values());
+                }
+                return;
+            }
+            Err((SudokuError::PuzzleStateInconsistent(..), _)) => return,
+            Err(_) => {}
+        }
+
+        // Branch on the unresolved cell with the fewest possibilities.  A
+        // cell with none left has no branches, so is a dead end.
+        let cell = (0..NUM_ELEMENTS)
+            .map(|i| (i / GROUP_SIZE, i % GROUP_SIZE))
+            .filter(|(r, c)| !self.element(*r, *c).is_finalized())
+            .min_by_key(|(r, c)| self.element(*r, *c).possible().len());
+
+        if let Some((r, c)) = cell {
+            let possible = self.element(r, c).possible();
+            for v in possible.iter() {
+                if *count >= limit {
+                    break;
+                }
+                let mut puzzle = self.clone();
+                if puzzle.finalize_element(r, c, v).is_ok() {
+                    puzzle.count_solutions_inner(limit, count, first);
+                }
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    use std::time::Duration;
+
+    use crate::limits::{CancelToken, Limit};
+
     fn parse_data(data: Vec<&str>) -> Result<Puzzle, anyhow::Error> {
-        let data_string = data.iter().map(|s| s.to_string()).collect();
-        Puzzle::parse_puzzle(data_string)
+        let data_string: Vec<String> = data.iter().map(|s| s.to_string()).collect();
+        Ok(Puzzle::from_values(&Puzzle::grid_values(&data_string)?)?)
     }
 
     #[test]
@@ -715,6 +1333,25 @@ mod tests {
         assert!(result.is_ok());
     }
 
+    #[test]
+    fn from_values_out_of_range() {
+        let mut values = [[0; GROUP_SIZE]; GROUP_SIZE];
+        values[0][0] = 9;
+        assert!(Puzzle::from_values(&values).is_ok());
+
+        values[2][4] = 10;
+        let e = Puzzle::from_values(&values).unwrap_err();
+        assert!(matches!(
+            e,
+            SudokuError::InvalidValue {
+                row: 2,
+                col: 4,
+                value: 10
+            }
+        ));
+        assert_eq!(e.to_string(), "Invalid value 10 in row 3, col 5");
+    }
+
     #[test]
     fn parse_bad_puzzle() {
         let bad_num_cols = vec![
@@ -798,6 +1435,38 @@ mod tests {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn parse_from_str() {
+        let grid = "8 7 X 1 X X X X X\n\
+                    X X 2 X X X 1 X 4\n\
+                    X X X X 5 9 7 8 X\n\
+                    \n\
+                    3 X X 4 X 6 X X X\n\
+                    X X 7 X X X 9 X X\n\
+                    X X X 8 X 3 X X 6\n\
+                    \n\
+                    X 4 5 9 X X X X X\n\
+                    2 X 3 X X X 4 X X\n\
+                    X X X X X 7 X 5 9\n";
+        let line = "87.1.....\
+                    ..2...1.4\
+                    ....5978.\
+                    3..4.6...\
+                    ..7...9..\
+                    ...8.3..6\
+                    .459.....\
+                    2.3...4..\
+                    .....7.59";
+
+        let from_grid: Puzzle = grid.parse().unwrap();
+        let from_line: Puzzle = line.parse().unwrap();
+        assert_eq!(from_grid.values(), from_line.values());
+        assert_eq!(from_line.values()[0], [8, 7, 0, 1, 0, 0, 0, 0, 0]);
+
+        assert!("87.1....*".repeat(9).parse::<Puzzle>().is_err());
+        assert!("87.1".parse::<Puzzle>().is_err());
+    }
+
     #[test]
     fn solve_good_puzzles() {
         let input1 = vec![
@@ -874,4 +1543,238 @@ mod tests {
         let mut puzzle = parse_data(input5).unwrap();
         assert!(puzzle.solve().is_ok());
     }
+
+    #[test]
+    fn solve_with_limits() {
+        let input = vec![
+            "2 X X X X 8 X 1 X",
+            "5 X 6 X X X X X X",
+            "X X X X 4 X 3 7 X",
+            "X X 5 X X 3 X X 8",
+            "8 2 X X X X X 6 3",
+            "4 X X 1 X X 5 X X",
+            "X 8 4 X 1 X X X X",
+            "X X X X X X 6 X 9",
+            "X 6 X 5 X X X X 1",
+        ];
+        let puzzle = parse_data(input).unwrap();
+
+        let limits = SolveLimits {
+            max_nodes: Some(0),
+            ..Default::default()
+        };
+        match puzzle.clone().solve_with_limits(&limits) {
+            Err((SudokuError::LimitExceeded { limit, stats }, _)) => {
+                assert_eq!(limit, Limit::Nodes);
+                assert_eq!(stats.nodes, 1);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let limits = SolveLimits {
+            max_iterations: Some(0),
+            ..Default::default()
+        };
+        match puzzle.clone().solve_with_limits(&limits) {
+            Err((SudokuError::LimitExceeded { limit, .. }, _)) => {
+                assert_eq!(limit, Limit::Iterations)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let limits = SolveLimits {
+            cancel: Some(cancel),
+            ..Default::default()
+        };
+        match puzzle.clone().solve_with_limits(&limits) {
+            Err((SudokuError::LimitExceeded { limit, .. }, _)) => {
+                assert_eq!(limit, Limit::Cancelled)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+
+        let limits = SolveLimits {
+            timeout: Some(Duration::from_secs(60)),
+            max_nodes: Some(1000),
+            max_iterations: Some(1000),
+            cancel: Some(CancelToken::new()),
+        };
+        assert!(puzzle.clone().solve_with_limits(&limits).is_ok());
+    }
+
+    #[test]
+    fn solve_with_stats() {
+        let input = [
+            "2 X X X X 8 X 1 X",
+            "5 X 6 X X X X X X",
+            "X X X X 4 X 3 7 X",
+            "X X 5 X X 3 X X 8",
+            "8 2 X X X X X 6 3",
+            "4 X X 1 X X 5 X X",
+            "X 8 4 X 1 X X X X",
+            "X X X X X X 6 X 9",
+            "X 6 X 5 X X X X 1",
+        ];
+        let mut puzzle = parse_data(input.to_vec()).unwrap();
+        let givens = puzzle
+            .values()
+            .iter()
+            .flatten()
+            .filter(|v| **v != 0)
+            .count();
+
+        let mut stats = SolveStats::default();
+        assert!(puzzle
+            .solve_with_stats(&SolveLimits::default(), &mut stats)
+            .is_ok());
+
+        // the trial tier copies the puzzle one level deep
+        assert!(stats.clones > 0);
+        assert_eq!(stats.max_depth, 1);
+        assert!(stats.iterations > 0);
+
+        for pass in Pass::ALL {
+            assert!(stats.pass(pass).calls > 0, "{} never ran", pass);
+        }
+        let basic = stats.pass(Pass::ReduceBasicElements);
+        assert!(basic.calls >= stats.pass(Pass::RowScan).calls);
+        assert!(basic.eliminated > 0);
+
+        // trials resolve elements of their copies too
+        let resolved: usize = stats.passes.iter().map(|p| p.resolved).sum();
+        assert!(resolved >= NUM_ELEMENTS - givens);
+        // plain solves skip the bookkeeping of each pass
+        let mut puzzle = parse_data(input.to_vec()).unwrap();
+        let mut search = Search::new(&SolveLimits::default());
+        assert!(puzzle.solve_search(&mut search).is_ok());
+        assert!(search.stats.passes.iter().all(|p| p.calls == 0));
+    }
+
+    #[test]
+    fn hint_and_validate() {
+        let input = vec![
+            "2 X X X X 8 X 1 X",
+            "5 X 6 X X X X X X",
+            "X X X X 4 X 3 7 X",
+            "X X 5 X X 3 X X 8",
+            "8 2 X X X X X 6 3",
+            "4 X X 1 X X 5 X X",
+            "X 8 4 X 1 X X X X",
+            "X X X X X X 6 X 9",
+            "X 6 X 5 X X X X 1",
+        ];
+        let mut puzzle = parse_data(input).unwrap();
+        assert!(puzzle.validate().is_ok());
+
+        let mut solution = puzzle.clone();
+        solution.solve().unwrap();
+        let solution = solution.values();
+
+        // following the hints solves the puzzle
+        while let Some(hint) = puzzle.hint() {
+            assert_eq!(puzzle.values()[hint.row][hint.col], 0);
+            assert_eq!(solution[hint.row][hint.col], hint.val);
+            puzzle
+                .finalize_element(hint.row, hint.col, hint.val - 1)
+                .unwrap();
+        }
+        assert_eq!(puzzle.values(), solution);
+
+        assert!(matches!(
+            Puzzle::default().validate(),
+            Err(SudokuError::MultipleSolutions)
+        ));
+
+        // hints for hard proper puzzles, the first of which the solver
+        // gives up on
+        let puzzles: Vec<Puzzle> = [
+            "1.......2.9.4...5...6...7...5.9.3.......7.......85..4.7.....6...3...9.8...2.....1",
+            "..............3.85..1.2.......5.7.....4...1...9.......5......73..2.1........4...9",
+        ]
+        .iter()
+        .map(|line| line.parse().unwrap())
+        .collect();
+        assert!(puzzles[0].clone().solve().is_err());
+        for puzzle in puzzles {
+            assert_eq!(puzzle.count_solutions(2), 1);
+
+            let hint = puzzle.hint().unwrap();
+            let mut solution = puzzle.clone();
+            solution
+                .finalize_element(hint.row, hint.col, hint.val - 1)
+                .unwrap();
+            assert_eq!(solution.count_solutions(2), 1);
+        }
+    }
+
+    #[test]
+    fn count_solutions() {
+        let input = vec![
+            "8 X X X 4 6 2 9 X",
+            "7 X X X X 9 X X 5",
+            "X X 2 X X 5 X X X",
+            "X 6 X 2 1 X 8 4 X",
+            "X 2 7 X 8 X 5 3 X",
+            "X 3 8 X 6 7 X 2 X",
+            "X X X 4 X X 6 X X",
+            "9 X X 3 X X X X X",
+            "X 4 1 6 5 X X X 3",
+        ];
+        let puzzle = parse_data(input).unwrap();
+        assert_eq!(puzzle.count_solutions(10), 1);
+        assert!(puzzle.has_unique_solution());
+
+        // an empty grid has many solutions
+        assert_eq!(Puzzle::default().count_solutions(5), 5);
+
+        // no place left for a 1 in the first row
+        let input = vec![
+            "X X X X X X 2 3 4",
+            "1 X X X X X X X X",
+            "X X X 1 X X X X X",
+            "X 1 X X X X X X X",
+            "X X X X 1 X X X X",
+            "X X X X X X X X X",
+            "X X 1 X X X X X X",
+            "X X X X X 1 X X X",
+            "X X X X X X X X X",
+        ];
+        let puzzle = parse_data(input).unwrap();
+        assert_eq!(puzzle.count_solutions(2), 0);
+    }
+
+    #[test]
+    fn trial_deductions() {
+        let input = vec![
+            "2 X X X X 8 X 1 X",
+            "5 X 6 X X X X X X",
+            "X X X X 4 X 3 7 X",
+            "X X 5 X X 3 X X 8",
+            "8 2 X X X X X 6 3",
+            "4 X X 1 X X 5 X X",
+            "X 8 4 X 1 X X X X",
+            "X X X X X X 6 X 9",
+            "X 6 X 5 X X X X 1",
+        ];
+        let mut puzzle = parse_data(input).unwrap();
+        assert_eq!(puzzle.difficulty(), Difficulty::Hard);
+
+        let mut search = Search::new(&SolveLimits::default());
+        assert!(matches!(
+            puzzle.logic_solve(&mut search),
+            Err((SudokuError::PuzzleUnsolvable, _))
+        ));
+
+        let deduction = puzzle.trial_deduction().unwrap();
+        assert_eq!(deduction.technique, Technique::Nishio);
+
+        // the eliminated candidates are not part of the solution
+        let mut solved = puzzle.clone();
+        solved.solve().unwrap();
+        for e in deduction.eliminations {
+            assert_ne!(solved.values()[e.row][e.col], e.val);
+        }
+    }
 }
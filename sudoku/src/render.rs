@@ -0,0 +1,396 @@
+//! SVG rendering of puzzles
+//!
+//! `Puzzle::to_svg` draws a single puzzle.  `render_booklet` lays out
+//! many puzzles on printable pages followed by answer key pages.  Pages
+//! are sized in millimetres, so they convert to PDF at the right size
+//! with any SVG tool, e.g. `rsvg-convert -f pdf`.
+
+use std::fmt::Write;
+use std::str::FromStr;
+
+use crate::element::GROUP_SIZE;
+use crate::puzzle::Puzzle;
+
+const GIVEN_COLOR: &str = "#000000";
+const SOLUTION_COLOR: &str = "#2060c0";
+const MARK_COLOR: &str = "#707070";
+
+/// Options for `Puzzle::to_svg`
+#[derive(Debug, Clone)]
+pub struct SvgOptions {
+    /// Width of a cell in SVG user units
+    pub cell_size: f64,
+
+    /// Solution to draw in the unresolved cells
+    pub solution: Option<Puzzle>,
+
+    /// Draw the possibilities of the unresolved cells
+    pub pencil_marks: bool,
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            cell_size: 40.0,
+            solution: None,
+            pencil_marks: false,
+        }
+    }
+}
+
+/// Paper size of booklet pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Paper {
+    /// 210 x 297 mm
+    A4,
+
+    /// 8.5 x 11 inches
+    Letter,
+}
+
+impl Paper {
+    // (width, height) in mm
+    fn size(&self) -> (f64, f64) {
+        match self {
+            Paper::A4 => (210.0, 297.0),
+            Paper::Letter => (215.9, 279.4),
+        }
+    }
+}
+
+impl FromStr for Paper {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "a4" => Ok(Paper::A4),
+            "letter" => Ok(Paper::Letter),
+            _ => Err(anyhow::anyhow!("Unknown paper size: {}", s)),
+        }
+    }
+}
+
+/// Page layout for `render_booklet`
+#[derive(Debug, Clone)]
+pub struct PageLayout {
+    /// Paper size
+    pub paper: Paper,
+
+    /// Puzzles across a page
+    pub columns: usize,
+
+    /// Puzzles down a page
+    pub rows: usize,
+}
+
+impl Default for PageLayout {
+    fn default() -> Self {
+        Self {
+            paper: Paper::A4,
+            columns: 2,
+            rows: 3,
+        }
+    }
+}
+
+// Page margin, and gap between puzzles, in mm
+const MARGIN: f64 = 15.0;
+const GAP: f64 = 8.0;
+const TITLE_SIZE: f64 = 4.0;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn draw_text(svg: &mut String, x: f64, y: f64, size: f64, color: &str, text: &str) {
+    let _ = writeln!(
+        svg,
+        r#"<text x="{:.2}" y="{:.2}" font-size="{:.2}" fill="{}" text-anchor="middle" dominant-baseline="central" font-family="sans-serif">{}</text>"#,
+        x,
+        y,
+        size,
+        color,
+        escape(text)
+    );
+}
+
+// Draw a puzzle with its top left corner at (x, y)
+fn draw_grid(svg: &mut String, x: f64, y: f64, puzzle: &Puzzle, options: &SvgOptions) {
+    let cell = options.cell_size;
+    let size = cell * GROUP_SIZE as f64;
+    let thin = cell / 40.0;
+    let thick = cell / 12.0;
+
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{:.2}" y="{:.2}" width="{:.2}" height="{:.2}" fill="white"/>"#,
+        x, y, size, size
+    );
+
+    // Digits
+    let givens = puzzle.values();
+    let solution = options.solution.as_ref().map(|s| s.values());
+    let cands = puzzle.candidates();
+    for r in 0..GROUP_SIZE {
+        for c in 0..GROUP_SIZE {
+            let cx = x + (c as f64 + 0.5) * cell;
+            let cy = y + (r as f64 + 0.5) * cell;
+            if givens[r][c] != 0 {
+                let text = givens[r][c].to_string();
+                draw_text(svg, cx, cy, cell * 0.6, GIVEN_COLOR, &text);
+            } else if let Some(v) = solution.map(|s| s[r][c]).filter(|v| *v != 0) {
+                draw_text(svg, cx, cy, cell * 0.6, SOLUTION_COLOR, &v.to_string());
+            } else if options.pencil_marks {
+                for v in (0..GROUP_SIZE).filter(|v| cands[r][c] & (1 << v) != 0) {
+                    let mx = x + (c as f64 + (v % 3) as f64 / 3.0 + 1.0 / 6.0) * cell;
+                    let my = y + (r as f64 + (v / 3) as f64 / 3.0 + 1.0 / 6.0) * cell;
+                    draw_text(svg, mx, my, cell * 0.25, MARK_COLOR, &(v + 1).to_string());
+                }
+            }
+        }
+    }
+
+    // Lines, thick on square borders
+    for i in 0..=GROUP_SIZE {
+        let width = if i % 3 == 0 { thick } else { thin };
+        let offset = i as f64 * cell;
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="black" stroke-width="{:.2}" stroke-linecap="square"/>"#,
+            x + offset,
+            y,
+            x + offset,
+            y + size,
+            width
+        );
+        let _ = writeln!(
+            svg,
+            r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="black" stroke-width="{:.2}" stroke-linecap="square"/>"#,
+            x,
+            y + offset,
+            x + size,
+            y + offset,
+            width
+        );
+    }
+}
+
+impl Puzzle {
+    /// Render the puzzle as an SVG document
+    pub fn to_svg(&self, options: &SvgOptions) -> String {
+        let margin = options.cell_size / 4.0;
+        let size = options.cell_size * GROUP_SIZE as f64 + 2.0 * margin;
+
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0:.2}" height="{0:.2}" viewBox="0 0 {0:.2} {0:.2}">"#,
+            size
+        );
+        draw_grid(&mut svg, margin, margin, self, options);
+        svg.push_str("</svg>\n");
+        svg
+    }
+}
+
+// Width and height of a slot for a titled puzzle on pages of 'columns'
+// x 'rows', and the size of the grid in it.  The grid size is not
+// positive when the puzzles do not fit.
+fn slot_size(paper: Paper, columns: usize, rows: usize) -> (f64, f64, f64) {
+    let (width, height) = paper.size();
+    let slot_w = (width - 2.0 * MARGIN - (columns as f64 - 1.0) * GAP) / columns as f64;
+    let slot_h = (height - 2.0 * MARGIN - (rows as f64 - 1.0) * GAP) / rows as f64;
+    (slot_w, slot_h, slot_w.min(slot_h - 2.0 * TITLE_SIZE))
+}
+
+// Lay out (title, puzzle, options) on pages of 'columns' x 'rows'
+fn render_pages(
+    items: &[(String, &Puzzle, SvgOptions)],
+    paper: Paper,
+    columns: usize,
+    rows: usize,
+) -> Vec<String> {
+    let (width, height) = paper.size();
+    let (slot_w, slot_h, grid) = slot_size(paper, columns, rows);
+
+    items
+        .chunks(columns * rows)
+        .map(|page| {
+            let mut svg = String::new();
+            let _ = writeln!(
+                svg,
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}mm" height="{1}mm" viewBox="0 0 {0} {1}">"#,
+                width, height
+            );
+            for (i, (title, puzzle, options)) in page.iter().enumerate() {
+                let x = MARGIN + (i % columns) as f64 * (slot_w + GAP) + (slot_w - grid) / 2.0;
+                let y = MARGIN + (i / columns) as f64 * (slot_h + GAP);
+                draw_text(&mut svg, x + grid / 2.0, y + TITLE_SIZE / 2.0, TITLE_SIZE, GIVEN_COLOR, title);
+
+                let options = SvgOptions {
+                    cell_size: grid / GROUP_SIZE as f64,
+                    ..options.clone()
+                };
+                draw_grid(&mut svg, x, y + 2.0 * TITLE_SIZE, puzzle, &options);
+            }
+            svg.push_str("</svg>\n");
+            svg
+        })
+        .collect()
+}
+
+/// Render titled puzzles on printable pages, followed by answer key
+/// pages with the solutions.
+///
+/// Answer keys are laid out denser than the puzzles.  Fails if the
+/// layout has no columns or rows, if the puzzles or answers do not fit
+/// on the paper, or if any puzzle can not be solved.
+pub fn render_booklet(
+    puzzles: &[(String, Puzzle)],
+    layout: &PageLayout,
+) -> Result<Vec<String>, anyhow::Error> {
+    if layout.columns == 0 || layout.rows == 0 {
+        return Err(anyhow::anyhow!(
+            "Page layout needs at least one column and row, found {}x{}",
+            layout.columns,
+            layout.rows
+        ));
+    }
+    // The answers are denser, but only check them once the puzzles fit,
+    // so adding a column and row can not overflow
+    let fits = |columns, rows| slot_size(layout.paper, columns, rows).2 > 0.0;
+    if !fits(layout.columns, layout.rows) || !fits(layout.columns + 1, layout.rows + 1) {
+        return Err(anyhow::anyhow!(
+            "Page layout {}x{} leaves no room for puzzles on {:?} paper",
+            layout.columns,
+            layout.rows,
+            layout.paper
+        ));
+    }
+
+    let mut solutions = Vec::with_capacity(puzzles.len());
+    for (title, puzzle) in puzzles {
+        let mut solution = puzzle.clone();
+        solution
+            .solve()
+            .map_err(|(e, _)| anyhow::anyhow!("Failed to solve puzzle '{}': {}", title, e))?;
+        solutions.push(solution);
+    }
+
+    let questions: Vec<_> = puzzles
+        .iter()
+        .enumerate()
+        .map(|(i, (title, puzzle))| {
+            (
+                format!("{}. {}", i + 1, title),
+                puzzle,
+                SvgOptions::default(),
+            )
+        })
+        .collect();
+    let answers: Vec<_> = puzzles
+        .iter()
+        .zip(solutions)
+        .enumerate()
+        .map(|(i, ((_, puzzle), solution))| {
+            let options = SvgOptions {
+                solution: Some(solution),
+                ..Default::default()
+            };
+            (format!("Answer {}", i + 1), puzzle, options)
+        })
+        .collect();
+
+    let mut pages = render_pages(&questions, layout.paper, layout.columns, layout.rows);
+    pages.extend(render_pages(
+        &answers,
+        layout.paper,
+        layout.columns + 1,
+        layout.rows + 1,
+    ));
+
+    Ok(pages)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str = "8...4629.\
+                          7....9..5\
+                          ..2..5...\
+                          .6.21.84.\
+                          .27.8.53.\
+                          .38.67.2.\
+                          ...4..6..\
+                          9..3.....\
+                          .4165...3";
+
+    #[test]
+    fn render_svg() {
+        let puzzle: Puzzle = PUZZLE.parse().unwrap();
+        let givens = PUZZLE.chars().filter(|c| *c != '.').count();
+
+        let svg = puzzle.to_svg(&SvgOptions::default());
+        assert!(svg.starts_with("<svg"));
+        assert_eq!(svg.matches("<text").count(), givens);
+        assert_eq!(svg.matches("<line").count(), 20);
+        assert_eq!(svg.matches(r#"stroke-width="3.33""#).count(), 8);
+
+        let mut solution = puzzle.clone();
+        solution.solve().unwrap();
+        let options = SvgOptions {
+            solution: Some(solution),
+            ..Default::default()
+        };
+        let svg = puzzle.to_svg(&options);
+        assert_eq!(svg.matches("<text").count(), 81);
+        assert_eq!(svg.matches(SOLUTION_COLOR).count(), 81 - givens);
+
+        let options = SvgOptions {
+            pencil_marks: true,
+            ..Default::default()
+        };
+        let svg = puzzle.to_svg(&options);
+        assert!(svg.matches(MARK_COLOR).count() > 81 - givens);
+    }
+
+    #[test]
+    fn render_pages() {
+        let puzzle: Puzzle = PUZZLE.parse().unwrap();
+        let puzzles: Vec<_> = (0..7)
+            .map(|i| (format!("<puzzle & {}>", i), puzzle.clone()))
+            .collect();
+
+        let pages = render_booklet(&puzzles, &PageLayout::default()).unwrap();
+
+        // 7 puzzles at 6 a page, then answers at 12 a page
+        assert_eq!(pages.len(), 3);
+        assert!(pages[0].contains(r#"width="210mm""#));
+        assert!(pages[0].contains("1. &lt;puzzle &amp; 0&gt;"));
+        assert!(pages[2].contains("Answer 7"));
+
+        // Puzzles without a solution have no answer key: the first row
+        // needs a 9 in the last column, which already has one
+        let unsolvable: Puzzle = format!("12345678.{}9{}", ".".repeat(8), ".".repeat(63))
+            .parse()
+            .unwrap();
+        assert_eq!(unsolvable.count_solutions(1), 0);
+        let unsolvable = [("unsolvable".to_string(), unsolvable)];
+        let e = render_booklet(&unsolvable, &PageLayout::default()).unwrap_err();
+        assert!(e.to_string().contains("'unsolvable'"));
+
+        // Nor is there a page without room for a puzzle
+        for (columns, rows) in [(0, 3), (2, 0), (23, 3), (2, 30), (usize::MAX, usize::MAX)] {
+            let layout = PageLayout {
+                columns,
+                rows,
+                ..Default::default()
+            };
+            assert!(render_booklet(&puzzles, &layout).is_err());
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! HTTP solving service
+//!
+//! Accepts `POST /solve` with a puzzle in any format understood by
+//! `Puzzle::from_str` and answers with a JSON `SolveResponse`.
+
+use std::io::Read;
+use std::time::Instant;
+
+use anyhow::anyhow;
+use serde_derive::Serialize;
+use tiny_http::{Header, Method, Request, Response, Server};
+use tracing::{debug, error, info};
+
+use sudoku::prelude::*;
+
+// Largest request body accepted, far more than any puzzle format needs
+const MAX_BODY: u64 = 64 * 1024;
+
+/// JSON body returned for every solve request
+#[derive(Serialize, Debug)]
+pub struct SolveResponse {
+    /// `Solved`, `ParseError`, `BadRequest`, `BodyTooLarge` or the name
+    /// of the `SudokuError`
+    pub status: String,
+
+    /// Solved grid, present when status is `Solved`
+    pub solution: Option<[[usize; 9]; 9]>,
+
+    /// Difficulty rating of the input puzzle
+    pub difficulty: Option<String>,
+
+    /// Logic solver iterations
+    pub iterations: usize,
+
+    /// Time spent rating and solving in microseconds
+    pub elapsed_us: u128,
+
+    /// Human readable error message
+    pub error: Option<String>,
+}
+
+impl SolveResponse {
+    // Response for a request that was not solved at all
+    fn rejected(status: &str, error: String, start: Instant) -> SolveResponse {
+        SolveResponse {
+            status: status.to_string(),
+            solution: None,
+            difficulty: None,
+            iterations: 0,
+            elapsed_us: start.elapsed().as_micros(),
+            error: Some(error),
+        }
+    }
+}
+
+fn error_status(e: &SudokuError) -> &'static str {
+    match e {
+        SudokuError::PuzzleStateInconsistent(..) => "PuzzleStateInconsistent",
+        SudokuError::PuzzleUnsolved => "PuzzleUnsolved",
+        SudokuError::PuzzleUnsolvable => "PuzzleUnsolvable",
+        SudokuError::MultipleSolutions => "MultipleSolutions",
+        SudokuError::InvalidValue { .. } => "InvalidValue",
+        SudokuError::LimitExceeded { .. } => "LimitExceeded",
+    }
+}
+
+/// Parse and solve a textual puzzle, returning an HTTP status code and response body
+pub fn solve_text(text: &str, limits: &SolveLimits) -> (u16, SolveResponse) {
+    let start = Instant::now();
+
+    let mut puzzle: Puzzle = match text.parse() {
+        Ok(p) => p,
+        Err(e) => {
+            let status = e
+                .downcast_ref::<SudokuError>()
+                .map_or("ParseError", error_status);
+            return (
+                400,
+                SolveResponse::rejected(status, format!("{:#}", e), start),
+            );
+        }
+    };
+
+    let difficulty = match puzzle.difficulty_with_limits(limits) {
+        Ok(difficulty) => Some(format!("{:?}", difficulty)),
+        Err(e) => {
+            let iterations = match &e {
+                SudokuError::LimitExceeded { stats, .. } => stats.iterations,
+                _ => 0,
+            };
+            return (
+                422,
+                SolveResponse {
+                    status: error_status(&e).to_string(),
+                    solution: None,
+                    difficulty: None,
+                    iterations,
+                    elapsed_us: start.elapsed().as_micros(),
+                    error: Some(e.to_string()),
+                },
+            );
+        }
+    };
+
+    // Rating and solving share the timeout
+    let limits = SolveLimits {
+        timeout: limits.timeout.map(|t| t.saturating_sub(start.elapsed())),
+        ..limits.clone()
+    };
+
+    match puzzle.solve_with_limits(&limits) {
+        Ok(iterations) => (
+            200,
+            SolveResponse {
+                status: "Solved".to_string(),
+                solution: Some(puzzle.values()),
+                difficulty,
+                iterations,
+                elapsed_us: start.elapsed().as_micros(),
+                error: None,
+            },
+        ),
+        Err((e, iterations)) => (
+            422,
+            SolveResponse {
+                status: error_status(&e).to_string(),
+                solution: None,
+                difficulty,
+                iterations,
+                elapsed_us: start.elapsed().as_micros(),
+                error: Some(e.to_string()),
+            },
+        ),
+    }
+}
+
+fn handle_request(mut request: Request, limits: &SolveLimits) -> Result<(), anyhow::Error> {
+    debug!("Request: {} {}", request.method(), request.url());
+
+    if request.url() != "/solve" {
+        return Ok(request.respond(Response::empty(404))?);
+    }
+    if *request.method() != Method::Post {
+        return Ok(request.respond(Response::empty(405))?);
+    }
+
+    // Read one byte past the limit to tell a body at the limit from one
+    // over it
+    let start = Instant::now();
+    let mut body = Vec::new();
+    let read = request
+        .as_reader()
+        .take(MAX_BODY + 1)
+        .read_to_end(&mut body);
+
+    let (code, response) = match read {
+        Err(e) => (
+            400,
+            SolveResponse::rejected("BadRequest", e.to_string(), start),
+        ),
+        Ok(_) if body.len() as u64 > MAX_BODY => (
+            413,
+            SolveResponse::rejected(
+                "BodyTooLarge",
+                format!("Request body is over {} bytes", MAX_BODY),
+                start,
+            ),
+        ),
+        Ok(_) => match String::from_utf8(body) {
+            Ok(text) => solve_text(&text, limits),
+            Err(e) => (
+                400,
+                SolveResponse::rejected("BadRequest", e.to_string(), start),
+            ),
+        },
+    };
+    let json = serde_json::to_string(&response)?;
+    let header = Header::from_bytes("Content-Type", "application/json").unwrap();
+
+    request.respond(
+        Response::from_string(json)
+            .with_status_code(code)
+            .with_header(header),
+    )?;
+
+    Ok(())
+}
+
+/// Handle requests on an already bound server until it shuts down
+pub fn serve(server: Server, limits: &SolveLimits) {
+    for request in server.incoming_requests() {
+        if let Err(e) = handle_request(request, limits) {
+            error!("Failed to handle request: {:#}", e);
+        }
+    }
+}
+
+/// Run the solving service on the given address, applying `limits` to
+/// every request
+pub fn run(listen: &str, limits: &SolveLimits) -> Result<(), anyhow::Error> {
+    let server =
+        Server::http(listen).map_err(|e| anyhow!("Failed to listen on {}: {}", listen, e))?;
+
+    info!("Listening on http://{}/solve", server.server_addr());
+    serve(server, limits);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::thread;
+    use std::time::Duration;
+
+    const PUZZLE: &str = "8 X X X 4 6 2 9 X\n\
+                          7 X X X X 9 X X 5\n\
+                          X X 2 X X 5 X X X\n\
+                          X 6 X 2 1 X 8 4 X\n\
+                          X 2 7 X 8 X 5 3 X\n\
+                          X 3 8 X 6 7 X 2 X\n\
+                          X X X 4 X X 6 X X\n\
+                          9 X X 3 X X X X X\n\
+                          X 4 1 6 5 X X X 3\n";
+
+    fn post(addr: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        post_bytes(addr, path, body.as_bytes())
+    }
+
+    fn post_bytes(addr: &str, path: &str, body: &[u8]) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            path,
+            addr,
+            body.len(),
+        )
+        .unwrap();
+        // the server may answer and close before reading all of a large
+        // body
+        let _ = stream.write_all(body);
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let code = response[9..12].parse().unwrap();
+        let body = response.split("\r\n\r\n").nth(1).unwrap_or_default();
+        (code, serde_json::from_str(body).unwrap_or_default())
+    }
+
+    #[test]
+    fn solve_over_http() {
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let addr = server.server_addr().to_string();
+        thread::spawn(move || serve(server, &SolveLimits::default()));
+
+        let (code, json) = post(&addr, "/solve", PUZZLE);
+        assert_eq!(code, 200);
+        assert_eq!(json["status"], "Solved");
+        assert_eq!(json["difficulty"], "Easy");
+        assert_eq!(json["solution"][0][0], 8);
+
+        let (code, json) = post(&addr, "/solve", "8 X X");
+        assert_eq!(code, 400);
+        assert_eq!(json["status"], "ParseError");
+
+        let (code, _) = post(&addr, "/other", PUZZLE);
+        assert_eq!(code, 404);
+
+        let (code, json) = post_bytes(&addr, "/solve", b"8 X X \xff");
+        assert_eq!(code, 400);
+        assert_eq!(json["status"], "BadRequest");
+
+        let (code, json) = post(&addr, "/solve", &" ".repeat(MAX_BODY as usize + 1));
+        assert_eq!(code, 413);
+        assert_eq!(json["status"], "BodyTooLarge");
+    }
+
+    #[test]
+    fn solve_text_inconsistent() {
+        let (code, response) = solve_text(&format!("11{}", ".".repeat(79)), &Default::default());
+        assert_eq!(code, 400);
+        assert_eq!(response.status, "PuzzleStateInconsistent");
+    }
+
+    #[test]
+    fn solve_text_limit_exceeded() {
+        let limits = SolveLimits {
+            timeout: Some(Duration::ZERO),
+            ..Default::default()
+        };
+        let (code, response) = solve_text(PUZZLE, &limits);
+        assert_eq!(code, 422);
+        assert_eq!(response.status, "LimitExceeded");
+    }
+
+    #[test]
+    fn rating_limit_exceeded() {
+        const HARD: &str = "2 X X X X 8 X 1 X\n\
+                            5 X 6 X X X X X X\n\
+                            X X X X 4 X 3 7 X\n\
+                            X X 5 X X 3 X X 8\n\
+                            8 2 X X X X X 6 3\n\
+                            4 X X 1 X X 5 X X\n\
+                            X 8 4 X 1 X X X X\n\
+                            X X X X X X 6 X 9\n\
+                            X 6 X 5 X X X X 1\n";
+
+        // enough iterations to solve the puzzle, but not to also rate it
+        let puzzle: Puzzle = HARD.parse().unwrap();
+        let mut stats = SolveStats::default();
+        puzzle
+            .clone()
+            .solve_with_stats(&Default::default(), &mut stats)
+            .unwrap();
+        let limits = SolveLimits {
+            max_iterations: Some(stats.iterations),
+            ..Default::default()
+        };
+        assert!(puzzle.clone().solve_with_limits(&limits).is_ok());
+
+        let (code, response) = solve_text(HARD, &limits);
+        assert_eq!(code, 422);
+        assert_eq!(response.status, "LimitExceeded");
+        assert_eq!(response.difficulty, None);
+    }
+}
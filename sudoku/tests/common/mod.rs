@@ -0,0 +1,40 @@
+//! Helpers shared by the integration tests
+
+use std::collections::HashSet;
+
+type Grid = [[usize; 9]; 9];
+
+/// Check `solution` is a complete, valid grid that keeps every given of
+/// `puzzle`
+pub fn check_solution(puzzle: &Grid, solution: &Grid) -> Result<(), String> {
+    for r in 0..9 {
+        for c in 0..9 {
+            let val = solution[r][c];
+            if !(1..=9).contains(&val) {
+                return Err(format!("row: {}, col: {} unresolved", r + 1, c + 1));
+            }
+            if puzzle[r][c] != 0 && puzzle[r][c] != val {
+                return Err(format!("row: {}, col: {} given changed", r + 1, c + 1));
+            }
+        }
+    }
+
+    for i in 0..9 {
+        let row: HashSet<usize> = (0..9).map(|c| solution[i][c]).collect();
+        let col: HashSet<usize> = (0..9).map(|r| solution[r][i]).collect();
+        let sqr: HashSet<usize> = (0..9)
+            .map(|j| solution[(i / 3) * 3 + j / 3][(i % 3) * 3 + j % 3])
+            .collect();
+        if row.len() != 9 {
+            return Err(format!("row: {} has duplicates", i + 1));
+        }
+        if col.len() != 9 {
+            return Err(format!("col: {} has duplicates", i + 1));
+        }
+        if sqr.len() != 9 {
+            return Err(format!("square: {} has duplicates", i + 1));
+        }
+    }
+
+    Ok(())
+}
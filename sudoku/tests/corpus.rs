@@ -0,0 +1,61 @@
+//! Solve every puzzle in the `puzzles` directory
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use sudoku::prelude::*;
+
+fn puzzle_files() -> Vec<PathBuf> {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("puzzles");
+    let mut files: Vec<PathBuf> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+        .collect();
+    files.sort();
+    files
+}
+
+#[test]
+fn solve_corpus() {
+    let files = puzzle_files();
+    assert!(!files.is_empty());
+
+    for file in files {
+        let mut puzzle = Puzzle::new(&file).unwrap();
+        let givens = puzzle.values();
+
+        if let Err((e, _)) = puzzle.solve() {
+            panic!("{}: failed to solve: {}", file.display(), e);
+        }
+        if let Err(e) = common::check_solution(&givens, &puzzle.values()) {
+            panic!("{}: bad solution: {}", file.display(), e);
+        }
+    }
+}
+
+#[test]
+fn corpus_solutions_unique() {
+    for file in puzzle_files() {
+        let puzzle = Puzzle::new(&file).unwrap();
+        assert!(puzzle.has_unique_solution(), "{}", file.display());
+    }
+}
+
+#[test]
+fn corpus_round_trip() {
+    for file in puzzle_files() {
+        let puzzle = Puzzle::new(&file).unwrap();
+
+        let printed: Puzzle = puzzle.to_string().parse().unwrap();
+        assert_eq!(printed.values(), puzzle.values(), "{}", file.display());
+
+        let line: Puzzle = puzzle.to_line().parse().unwrap();
+        assert_eq!(line.values(), puzzle.values(), "{}", file.display());
+
+        let from_values = Puzzle::from_values(&puzzle.values()).unwrap();
+        assert_eq!(from_values.values(), puzzle.values(), "{}", file.display());
+    }
+}
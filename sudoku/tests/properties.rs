@@ -0,0 +1,98 @@
+//! Property based solver tests
+
+mod common;
+
+use proptest::prelude::*;
+
+use sudoku::prelude::*;
+
+// A valid solved grid, shuffled to produce others
+const SOLUTION: [[usize; 9]; 9] = [
+    [8, 5, 3, 7, 4, 6, 2, 9, 1],
+    [7, 1, 4, 8, 2, 9, 3, 6, 5],
+    [6, 9, 2, 1, 3, 5, 7, 8, 4],
+    [5, 6, 9, 2, 1, 3, 8, 4, 7],
+    [1, 2, 7, 9, 8, 4, 5, 3, 6],
+    [4, 3, 8, 5, 6, 7, 1, 2, 9],
+    [3, 7, 5, 4, 9, 2, 6, 1, 8],
+    [9, 8, 6, 3, 7, 1, 4, 5, 2],
+    [2, 4, 1, 6, 5, 8, 9, 7, 3],
+];
+
+// Puzzles made by relabeling the digits of SOLUTION and blanking cells
+fn solvable_puzzle() -> impl Strategy<Value = [[usize; 9]; 9]> {
+    (
+        Just((1..=9).collect::<Vec<usize>>()).prop_shuffle(),
+        prop::collection::vec(prop::bool::weighted(0.4), 81),
+    )
+        .prop_map(|(labels, keep)| {
+            let mut grid = [[0; 9]; 9];
+            for r in 0..9 {
+                for c in 0..9 {
+                    if keep[r * 9 + c] {
+                        grid[r][c] = labels[SOLUTION[r][c] - 1];
+                    }
+                }
+            }
+            grid
+        })
+}
+
+// Sparse grids of random digits, often inconsistent or unsolvable
+fn random_puzzle() -> impl Strategy<Value = [[usize; 9]; 9]> {
+    prop::collection::vec(prop_oneof![4 => Just(0usize), 1 => 1..=9usize], 81).prop_map(|cells| {
+        let mut grid = [[0; 9]; 9];
+        for (i, val) in cells.into_iter().enumerate() {
+            grid[i / 9][i % 9] = val;
+        }
+        grid
+    })
+}
+
+#[test]
+fn solution_is_valid() {
+    assert_eq!(common::check_solution(&SOLUTION, &SOLUTION), Ok(()));
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(64))]
+
+    #[test]
+    fn solvable_puzzles_have_solutions(grid in solvable_puzzle()) {
+        let puzzle = Puzzle::from_values(&grid).unwrap();
+        prop_assert!(puzzle.count_solutions(1) == 1);
+    }
+
+    #[test]
+    fn solutions_satisfy_constraints(grid in solvable_puzzle()) {
+        // the solver may give up on a puzzle that needs deeper guessing,
+        // but any solution it finds is valid
+        let mut puzzle = Puzzle::from_values(&grid).unwrap();
+        if puzzle.solve().is_ok() {
+            prop_assert_eq!(common::check_solution(&grid, &puzzle.values()), Ok(()));
+        }
+    }
+
+    #[test]
+    fn random_solutions_satisfy_constraints(grid in random_puzzle()) {
+        // most random grids have no solution or many
+        if let Ok(mut puzzle) = Puzzle::from_values(&grid) {
+            let solutions = puzzle.count_solutions(1);
+            if puzzle.solve().is_ok() {
+                prop_assert_eq!(solutions, 1);
+                prop_assert_eq!(common::check_solution(&grid, &puzzle.values()), Ok(()));
+            }
+        }
+    }
+
+    #[test]
+    fn print_parse_round_trip(grid in random_puzzle()) {
+        if let Ok(puzzle) = Puzzle::from_values(&grid) {
+            let printed: Puzzle = puzzle.to_string().parse().unwrap();
+            prop_assert_eq!(printed.values(), grid);
+
+            let line: Puzzle = puzzle.to_line().parse().unwrap();
+            prop_assert_eq!(line.values(), grid);
+        }
+    }
+}